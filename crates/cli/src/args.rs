@@ -0,0 +1,146 @@
+//! Minimal parser for positional arguments, `--name value` options and boolean flags.
+
+use std::collections::VecDeque;
+
+use anyhow::{Context, bail};
+
+/// Options which take no value.
+const FLAGS: [&str; 5] = ["--async", "--backwards", "--force", "--help", "--local"];
+
+pub struct Args {
+    positional: VecDeque<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut positional = VecDeque::new();
+        let mut options = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "-h" {
+                options.push(("--help".to_string(), None));
+            } else if !arg.starts_with("--") {
+                positional.push_back(arg);
+            } else if let Some((name, value)) = arg.split_once('=') {
+                options.push((name.to_string(), Some(value.to_string())));
+            } else if FLAGS.contains(&arg.as_str()) {
+                options.push((arg, None));
+            } else {
+                let value = args
+                    .next()
+                    .with_context(|| format!("{arg} requires a value"))?;
+                options.push((arg, Some(value)));
+            }
+        }
+
+        Ok(Args {
+            positional,
+            options,
+        })
+    }
+
+    /// Takes the next positional argument, failing if there is none.
+    pub fn required(&mut self, name: &str) -> anyhow::Result<String> {
+        self.positional
+            .pop_front()
+            .with_context(|| format!("missing <{name}>"))
+    }
+
+    /// Takes the next positional argument, if any.
+    pub fn optional(&mut self) -> Option<String> {
+        self.positional.pop_front()
+    }
+
+    /// Takes the last value of an option.
+    pub fn option(&mut self, name: &str) -> Option<String> {
+        self.options_of(name).pop()
+    }
+
+    /// Takes the last value of an option, parsed with `FromStr`.
+    pub fn parsed<T>(&mut self, name: &str) -> anyhow::Result<Option<T>>
+    where
+        T: std::str::FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("invalid {name} `{value}`"))
+            })
+            .transpose()
+    }
+
+    /// Takes every value of a repeatable option.
+    pub fn options_of(&mut self, name: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        self.options.retain(|(option, value)| {
+            if option != name {
+                return true;
+            }
+            values.extend(value.clone());
+            false
+        });
+        values
+    }
+
+    /// Takes a flag, returning whether it was set.
+    pub fn flag(&mut self, name: &str) -> bool {
+        let len = self.options.len();
+        self.options.retain(|(option, _)| option != name);
+        self.options.len() < len
+    }
+
+    /// Fails if any arguments were not taken.
+    pub fn finish(self) -> anyhow::Result<()> {
+        if let Some(arg) = self.positional.front() {
+            bail!("unexpected argument `{arg}`");
+        }
+        if let Some((option, _)) = self.options.first() {
+            bail!("unexpected option `{option}`");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parses_options_flags_and_positional_arguments() {
+        let mut args = args(&[
+            "events",
+            "query",
+            "--type",
+            "SentFunds",
+            "--type=ReceivedFunds",
+            "--backwards",
+            "--limit",
+            "10",
+        ]);
+        assert_eq!(args.required("command").unwrap(), "events");
+        assert_eq!(args.optional().as_deref(), Some("query"));
+        assert_eq!(args.options_of("--type"), ["SentFunds", "ReceivedFunds"]);
+        assert!(args.flag("--backwards"));
+        assert_eq!(args.parsed::<u32>("--limit").unwrap(), Some(10));
+        assert!(args.finish().is_ok());
+    }
+
+    #[test]
+    fn rejects_unused_and_incomplete_arguments() {
+        let mut unused = args(&["schema", "push", "--forse=true"]);
+        unused.required("command").unwrap();
+        unused.required("schema command").unwrap();
+        assert!(unused.finish().is_err());
+
+        assert!(Args::parse(["--limit".to_string()]).is_err());
+        assert!(args(&["--limit", "ten"]).parsed::<u32>("--limit").is_err());
+    }
+}
@@ -0,0 +1,40 @@
+//! `esruntime commands`, executing commands on the server.
+
+use anyhow::{Context, bail};
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::{Config, args::Args, server::print_json};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "x-idempotency-key";
+const HANDLER_VERSION_HEADER: &str = "x-handler-version";
+
+pub async fn run(config: &Config, mut args: Args) -> anyhow::Result<()> {
+    match args.required("commands command")?.as_str() {
+        "exec" => {
+            let name = args.required("name")?;
+            let input = args.required("json")?;
+            let input: Value = serde_json::from_str(&input).context("input must be JSON")?;
+            let idempotency_key = args.option("--idempotency-key");
+            let handler_version = args.option("--handler-version");
+            let run_async = args.flag("--async");
+            args.finish()?;
+
+            let server = config.server()?;
+            let mut request = server
+                .request(Method::POST, &format!("/commands/{name}"))
+                .json(&input);
+            if run_async {
+                request = request.query(&[("async", "true")]);
+            }
+            if let Some(idempotency_key) = idempotency_key {
+                request = request.header(IDEMPOTENCY_KEY_HEADER, idempotency_key);
+            }
+            if let Some(handler_version) = handler_version {
+                request = request.header(HANDLER_VERSION_HEADER, handler_version);
+            }
+            print_json(&server.send(request).await?)
+        }
+        command => bail!("unknown commands command `{command}`"),
+    }
+}
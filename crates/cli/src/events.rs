@@ -0,0 +1,181 @@
+//! `esruntime events`, reading events directly from UmaDB.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+};
+
+use anyhow::{Context, bail};
+use esruntime_sdk::{
+    export::{self, ExportRange, ExportedEvent},
+    migrate::{self, Migration},
+};
+use futures_util::StreamExt;
+use serde_json::Value;
+use umadb_dcb::{DCBEventStoreAsync, DCBQuery, DCBQueryItem, DCBSequencedEvent};
+use uuid::Uuid;
+
+use crate::{Config, args::Args, connect_umadb};
+
+pub async fn run(config: &Config, mut args: Args) -> anyhow::Result<()> {
+    match args.required("events command")?.as_str() {
+        "tail" => {
+            let query = query(&mut args);
+            let after = args.parsed::<u64>("--after")?;
+            args.finish()?;
+
+            let umadb = config.umadb().await?;
+            let start = match after {
+                Some(after) => after.saturating_add(1),
+                None => umadb.head().await?.map_or(1, |head| head.saturating_add(1)),
+            };
+            let mut events = umadb.read(query, Some(start), false, None, true).await?;
+            while let Some(event) = events.next().await {
+                print_event(event?)?;
+            }
+        }
+        "query" => {
+            let query = query(&mut args);
+            let after = args.parsed::<u64>("--after")?;
+            let limit = args.parsed::<u32>("--limit")?;
+            let backwards = args.flag("--backwards");
+            args.finish()?;
+
+            let umadb = config.umadb().await?;
+            let start = after.map(|after| after.saturating_add(1));
+            let mut events = umadb.read(query, start, backwards, limit, false).await?;
+            while let Some(event) = events.next().await {
+                print_event(event?)?;
+            }
+        }
+        "get" => {
+            let id = args.required("id")?;
+            let id = Uuid::parse_str(&id).with_context(|| format!("invalid event ID `{id}`"))?;
+            args.finish()?;
+
+            // UmaDB cannot look up events by ID, so the store is scanned.
+            let umadb = config.umadb().await?;
+            let mut events = umadb.read(None, None, false, None, false).await?;
+            while let Some(event) = events.next().await {
+                let event = event?;
+                if event.event.uuid == Some(id) {
+                    return print_event(event);
+                }
+            }
+            bail!("no event has the ID {id}");
+        }
+        "export" => {
+            let range = ExportRange {
+                query: query(&mut args),
+                after: args.parsed("--after")?,
+                until: args.parsed("--until")?,
+            };
+            let output = args.option("--output");
+            args.finish()?;
+
+            let umadb = config.umadb().await?;
+            let exported = match output {
+                Some(path) => {
+                    let file =
+                        File::create(&path).with_context(|| format!("failed to create {path}"))?;
+                    export::export(&umadb, &range, BufWriter::new(file)).await?
+                }
+                None => export::export(&umadb, &range, io::stdout().lock()).await?,
+            };
+            eprintln!("Exported {exported} events");
+        }
+        "import" => {
+            let input = args.optional();
+            args.finish()?;
+
+            let umadb = config.umadb().await?;
+            let imported = match input {
+                Some(path) => {
+                    let file =
+                        File::open(&path).with_context(|| format!("failed to open {path}"))?;
+                    export::import(&umadb, BufReader::new(file)).await?
+                }
+                None => export::import(&umadb, io::stdin().lock()).await?,
+            };
+            eprintln!("Imported {imported} events");
+        }
+        "migrate" => {
+            let target = args
+                .option("--target")
+                .context("--target is required, the URL of an empty UmaDB store")?;
+            let migration = migration(&mut args)?;
+            let position_map = args.option("--position-map");
+            args.finish()?;
+
+            let source = config.umadb().await?;
+            let target = connect_umadb(&target).await?;
+            let report = migration.run(&source, &target).await?;
+            eprintln!("Migrated {} events into {}", report.read, report.written);
+            if let Some(path) = position_map {
+                let file =
+                    File::create(&path).with_context(|| format!("failed to create {path}"))?;
+                report.positions.write_jsonl(BufWriter::new(file))?;
+            }
+        }
+        command => bail!("unknown events command `{command}`"),
+    }
+
+    Ok(())
+}
+
+/// Builds a query from the `--type` and `--tag` options, matching events of any of the types
+/// with every tag.
+fn query(args: &mut Args) -> Option<DCBQuery> {
+    let types = args.options_of("--type");
+    let tags = args.options_of("--tag");
+    if types.is_empty() && tags.is_empty() {
+        return None;
+    }
+
+    Some(DCBQuery {
+        items: vec![DCBQueryItem { types, tags }],
+    })
+}
+
+/// Builds a migration from the transformation options, which refer to source event types.
+///
+/// Fields are redacted and domain IDs removed and added before types are renamed.
+fn migration(args: &mut Args) -> anyhow::Result<Migration> {
+    let mut migration = Migration::new();
+    for option in args.options_of("--redact") {
+        let (event_type, field) = type_and_name("--redact", &option)?;
+        migration = migration.transform(migrate::redact(
+            event_type,
+            field,
+            Value::String("[redacted]".to_string()),
+        ));
+    }
+    for option in args.options_of("--remove-domain-id") {
+        let (event_type, category) = type_and_name("--remove-domain-id", &option)?;
+        migration = migration.transform(migrate::remove_domain_id(event_type, category));
+    }
+    for option in args.options_of("--add-domain-id") {
+        let (event_type, field) = type_and_name("--add-domain-id", &option)?;
+        migration = migration.transform(migrate::add_domain_id(event_type, field));
+    }
+    for option in args.options_of("--rename") {
+        let Some((from, to)) = option.split_once('=') else {
+            bail!("invalid --rename `{option}`, expected OLD=NEW");
+        };
+        migration = migration.transform(migrate::rename_type(from.to_string(), to.to_string()));
+    }
+    Ok(migration)
+}
+
+/// Splits a `TYPE.NAME` option value.
+fn type_and_name(name: &str, value: &str) -> anyhow::Result<(String, String)> {
+    let (event_type, name) = value
+        .split_once('.')
+        .with_context(|| format!("invalid {name} `{value}`, expected TYPE.NAME"))?;
+    Ok((event_type.to_string(), name.to_string()))
+}
+
+fn print_event(event: DCBSequencedEvent) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(&ExportedEvent::from(event))?);
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! `esruntime handlers`, managing the WASM handlers uploaded to the server.
+
+use anyhow::{Context, bail};
+use reqwest::{Method, header::CONTENT_TYPE};
+
+use crate::{Config, args::Args, server::print_json};
+
+const HANDLER_VERSION_HEADER: &str = "x-handler-version";
+
+pub async fn run(config: &Config, mut args: Args) -> anyhow::Result<()> {
+    match args.required("handlers command")?.as_str() {
+        "list" => {
+            args.finish()?;
+
+            let server = config.server()?;
+            print_json(
+                &server
+                    .send(server.request(Method::GET, "/handlers"))
+                    .await?,
+            )
+        }
+        "upload" => {
+            let name = args.required("name")?;
+            let file = args.required("file")?;
+            let version = args.option("--version").context("--version is required")?;
+            args.finish()?;
+
+            let wasm = tokio::fs::read(&file)
+                .await
+                .with_context(|| format!("failed to read {file}"))?;
+            let server = config.server()?;
+            let request = server
+                .request(Method::PUT, &format!("/handlers/{name}"))
+                .header(CONTENT_TYPE, "application/wasm")
+                .header(HANDLER_VERSION_HEADER, version)
+                .body(wasm);
+            print_json(&server.send(request).await?)
+        }
+        "delete" => {
+            let name = args.required("name")?;
+            let version = args.option("--version");
+            args.finish()?;
+
+            let server = config.server()?;
+            let mut request = server.request(Method::DELETE, &format!("/handlers/{name}"));
+            if let Some(version) = version {
+                request = request.header(HANDLER_VERSION_HEADER, version);
+            }
+            server.send(request).await?;
+            Ok(())
+        }
+        command => bail!("unknown handlers command `{command}`"),
+    }
+}
@@ -0,0 +1,132 @@
+//! # esruntime
+//!
+//! Command-line tool for inspecting the event store and operating an `esruntime-server`.
+//!
+//! Events are read directly from UmaDB, commands, handlers and schemas are managed through the
+//! server, and projection checkpoints are read from Postgres.
+
+mod args;
+mod commands;
+mod events;
+mod handlers;
+mod projections;
+mod schema;
+mod server;
+
+use std::{env, process::ExitCode};
+
+use anyhow::{Context, bail};
+use umadb_client::{AsyncUmaDBClient, UmaDBClient};
+
+use crate::{args::Args, server::Server};
+
+const DEFAULT_UMADB_URL: &str = "http://0.0.0.0:50051";
+
+const USAGE: &str = "\
+Usage: esruntime [OPTIONS] <COMMAND>
+
+Commands:
+  events tail [--type T].. [--tag T].. [--after N]      Follow events as they are appended
+  events query [--type T].. [--tag T].. [--after N] [--limit N] [--backwards]
+  events get <ID>                                       Print the event with a UUID
+  events export [--type T].. [--tag T].. [--after N] [--until N] [--output FILE]
+  events import [<FILE>]                                Append an export to an empty store
+  events migrate --target <URL> [--rename OLD=NEW].. [--add-domain-id TYPE.FIELD]..
+      [--remove-domain-id TYPE.CATEGORY].. [--redact TYPE.FIELD].. [--position-map FILE]
+  commands exec <NAME> <JSON> [--idempotency-key K] [--handler-version V] [--async]
+  handlers list
+  handlers upload <NAME> <FILE> --version V
+  handlers delete <NAME> [--version V]
+  schema validate <FILE> [--local]                      Check a schema, against the server's unless --local
+  schema push <FILE> [--force]                          Replace the server's schema
+  schema diff <OLD> [<NEW>]                             Compare two schemas, or the server's with <OLD>
+  projections status                                    Print the checkpoint and lag of each projection
+  projections reset <NAME>                              Delete a projection's checkpoint, so it replays every
+                                                        event when next run. Its tables are not truncated
+
+Options:
+  --umadb <URL>          UmaDB URL [env: ESRUNTIME_UMADB_URL, default: http://0.0.0.0:50051]
+  --server <URL>         Server URL, eg. http://localhost:3000 [env: ESRUNTIME_SERVER_URL]
+  --database-url <URL>   Postgres URL of the projection checkpoints [env: DATABASE_URL]
+  --checkpoint-table <T> Table of the projection checkpoints [default: checkpoints]
+  -h, --help             Print this help
+";
+
+/// Where to reach the event store, server and projection database.
+pub struct Config {
+    umadb_url: String,
+    server_url: Option<String>,
+    database_url: Option<String>,
+    checkpoint_table: Option<String>,
+}
+
+impl Config {
+    fn from_args(args: &mut Args) -> Self {
+        Config {
+            umadb_url: args
+                .option("--umadb")
+                .or_else(|| env::var("ESRUNTIME_UMADB_URL").ok())
+                .unwrap_or_else(|| DEFAULT_UMADB_URL.to_string()),
+            server_url: args
+                .option("--server")
+                .or_else(|| env::var("ESRUNTIME_SERVER_URL").ok()),
+            database_url: args
+                .option("--database-url")
+                .or_else(|| env::var("DATABASE_URL").ok()),
+            checkpoint_table: args.option("--checkpoint-table"),
+        }
+    }
+
+    pub async fn umadb(&self) -> anyhow::Result<AsyncUmaDBClient> {
+        connect_umadb(&self.umadb_url).await
+    }
+
+    pub fn server(&self) -> anyhow::Result<Server> {
+        match &self.server_url {
+            Some(url) => Ok(Server::new(url)),
+            None => bail!("--server or ESRUNTIME_SERVER_URL is required"),
+        }
+    }
+
+    pub fn has_server(&self) -> bool {
+        self.server_url.is_some()
+    }
+}
+
+pub async fn connect_umadb(url: &str) -> anyhow::Result<AsyncUmaDBClient> {
+    UmaDBClient::new(url.to_string())
+        .connect_async()
+        .await
+        .with_context(|| format!("failed to connect to UmaDB at {url}"))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(env::args().skip(1)).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let mut args = Args::parse(args)?;
+    let config = Config::from_args(&mut args);
+    let command = args.optional();
+    let help = args.flag("--help");
+    let Some(command) = command.filter(|_| !help) else {
+        print!("{USAGE}");
+        return Ok(());
+    };
+
+    match command.as_str() {
+        "events" => events::run(&config, args).await,
+        "commands" => commands::run(&config, args).await,
+        "handlers" => handlers::run(&config, args).await,
+        "schema" => schema::run(&config, args).await,
+        "projections" => projections::run(&config, args).await,
+        command => bail!("unknown command `{command}`, see `esruntime --help`"),
+    }
+}
@@ -0,0 +1,57 @@
+//! `esruntime projections`, reading and resetting projection checkpoints in Postgres.
+//!
+//! Resetting only deletes the checkpoint. The projection's own tables are left as they are.
+
+use anyhow::{Context, bail};
+use esruntime_postgres::CheckpointTable;
+use sqlx::PgPool;
+use umadb_dcb::DCBEventStoreAsync;
+
+use crate::{Config, args::Args};
+
+pub async fn run(config: &Config, mut args: Args) -> anyhow::Result<()> {
+    match args.required("projections command")?.as_str() {
+        "status" => {
+            args.finish()?;
+
+            let positions = checkpoints(config, "").await?.positions().await?;
+            let head = config.umadb().await?.head().await?.unwrap_or(0);
+            println!("{:<32} {:>12} {:>12}", "PROJECTION", "POSITION", "LAG");
+            for (projection, position) in positions {
+                let lag = head.saturating_sub(position);
+                println!("{projection:<32} {position:>12} {lag:>12}");
+            }
+            Ok(())
+        }
+        "reset" => {
+            let name = args.required("name")?;
+            args.finish()?;
+
+            if !checkpoints(config, &name).await?.reset().await? {
+                bail!("projection `{name}` has no checkpoint");
+            }
+            println!(
+                "deleted the checkpoint of `{name}`, which replays every event when next run; \
+                 its tables were not truncated, so clear them first unless its handlers are idempotent"
+            );
+            Ok(())
+        }
+        command => bail!("unknown projections command `{command}`"),
+    }
+}
+
+async fn checkpoints(config: &Config, projection_id: &str) -> anyhow::Result<CheckpointTable> {
+    let database_url = config
+        .database_url
+        .as_deref()
+        .context("--database-url or DATABASE_URL is required")?;
+    let pool = PgPool::connect(database_url)
+        .await
+        .context("failed to connect to Postgres")?;
+
+    let checkpoints = CheckpointTable::new(pool, projection_id);
+    Ok(match &config.checkpoint_table {
+        Some(table) => checkpoints.table(table.as_str()),
+        None => checkpoints,
+    })
+}
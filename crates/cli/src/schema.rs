@@ -0,0 +1,91 @@
+//! `esruntime schema`, validating, pushing and comparing ESDL schemas.
+
+use anyhow::{Context, bail};
+use esruntime_schema::Schema;
+use reqwest::{Method, header::CONTENT_TYPE};
+use serde_json::json;
+
+use crate::{Config, args::Args, server::print_json};
+
+pub async fn run(config: &Config, mut args: Args) -> anyhow::Result<()> {
+    match args.required("schema command")?.as_str() {
+        "validate" => {
+            let file = args.required("file")?;
+            let local = args.flag("--local") || !config.has_server();
+            args.finish()?;
+
+            let source = read(&file).await?;
+            if local {
+                parse(&file, &source)?;
+                println!("{file} is valid");
+                return Ok(());
+            }
+            let server = config.server()?;
+            let request = server
+                .request(Method::POST, "/schema/validate")
+                .header(CONTENT_TYPE, "text/plain")
+                .body(source);
+            print_json(&server.send(request).await?)
+        }
+        "push" => {
+            let file = args.required("file")?;
+            let force = args.flag("--force");
+            args.finish()?;
+
+            let source = read(&file).await?;
+            parse(&file, &source)?;
+            let server = config.server()?;
+            let mut request = server
+                .request(Method::PUT, "/schema")
+                .header(CONTENT_TYPE, "text/plain")
+                .body(source);
+            if force {
+                request = request.query(&[("force", "true")]);
+            }
+            print_json(&server.send(request).await?)
+        }
+        "diff" => {
+            let old_file = args.required("old")?;
+            let new_file = args.optional();
+            args.finish()?;
+
+            let (old, new) = match new_file {
+                Some(new_file) => {
+                    let old = parse(&old_file, &read(&old_file).await?)?;
+                    let new = parse(&new_file, &read(&new_file).await?)?;
+                    (old, new)
+                }
+                None => {
+                    let server = config.server()?;
+                    let current = server.send(server.request(Method::GET, "/schema")).await?;
+                    let source = current["schema"]
+                        .as_str()
+                        .context("the server returned a schema without its source")?;
+                    let old = parse("server schema", source)?;
+                    let new = parse(&old_file, &read(&old_file).await?)?;
+                    (old, new)
+                }
+            };
+
+            let changes = esruntime_schema::diff(&old, &new);
+            print_json(&json!(changes))
+        }
+        command => bail!("unknown schema command `{command}`"),
+    }
+}
+
+async fn read(file: &str) -> anyhow::Result<String> {
+    tokio::fs::read_to_string(file)
+        .await
+        .with_context(|| format!("failed to read {file}"))
+}
+
+fn parse(file: &str, source: &str) -> anyhow::Result<Schema> {
+    esruntime_schema::parse(source).map_err(|diagnostics| {
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| format!("{file}:{diagnostic}"))
+            .collect();
+        anyhow::anyhow!("invalid schema\n{}", errors.join("\n"))
+    })
+}
@@ -0,0 +1,69 @@
+//! HTTP requests to an `esruntime-server`.
+
+use anyhow::{Context, bail};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde_json::Value;
+
+pub struct Server {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Server {
+    pub fn new(url: &str) -> Self {
+        Server {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, format!("{}{path}", self.url))
+    }
+
+    /// Sends a request, returning the JSON body of a successful response.
+    ///
+    /// Error responses fail with the error code, message and details returned by the server.
+    pub async fn send(&self, request: RequestBuilder) -> anyhow::Result<Value> {
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("failed to reach the server at {}", self.url))?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        let value = if body.is_empty() || status == StatusCode::NO_CONTENT {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()))
+        };
+
+        if status.is_success() {
+            return Ok(value);
+        }
+        let code = value["code"].as_str().unwrap_or("unknown_error");
+        let message = value["message"].as_str().unwrap_or_else(|| {
+            status
+                .canonical_reason()
+                .unwrap_or("the server returned an error")
+        });
+        let mut error = format!("{message} ({status}, {code})");
+        for key in ["errors", "details"] {
+            if let Some(extra) = value.get(key) {
+                error.push_str(&format!(
+                    "\n{key}: {}",
+                    serde_json::to_string_pretty(extra)?
+                ));
+            }
+        }
+        bail!(error)
+    }
+}
+
+/// Prints a JSON value, pretty printed.
+pub fn print_json(value: &Value) -> anyhow::Result<()> {
+    if !value.is_null() {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,618 @@
+//! # ESRuntime Client
+//!
+//! Typed HTTP client for executing commands on a remote `CommandRouter`.
+//!
+//! Commands are executed with the same [`Command`] types registered on the server,
+//! and rejections are returned as the [`CommandError`] produced by the handler.
+//!
+//! ```ignore
+//! let client = Client::new("http://localhost:8080");
+//!
+//! let result = client
+//!     .execute::<TransferFunds, _>(TransferFundsInput {
+//!         source_account: "alice".to_string(),
+//!         dest_account: "bob".to_string(),
+//!         amount: 50.0,
+//!     })
+//!     .await?;
+//! ```
+
+use std::time::Duration;
+
+use esruntime_sdk::{
+    command::{Command, CommandContext, ExecuteResult, snake_case},
+    error::{CommandError, ErrorCode, FieldError},
+};
+use reqwest::{StatusCode, header::IF_MATCH};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use thiserror::Error;
+use tracing::warn;
+use umadb_dcb::DCBEvent;
+use uuid::Uuid;
+
+/// Header carrying the correlation ID shared by every command in a request chain.
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+/// Header carrying the ID of the event or command which caused the command.
+pub const CAUSATION_ID_HEADER: &str = "x-causation-id";
+/// Default header carrying the idempotency key.
+pub const DEFAULT_IDEMPOTENCY_KEY_HEADER: &str = "x-idempotency-key";
+/// Response header set when a response is replayed for a previously used idempotency key.
+pub const IDEMPOTENCY_REPLAYED_HEADER: &str = "idempotency-replayed";
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+const DEFAULT_MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Client for the commands of a remote `CommandRouter`.
+///
+/// Commands which fail with a conflict are retried up to 3 times by default,
+/// starting with a 50ms backoff which doubles with each retry up to 5s.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    idempotency_key_header: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+    max_retry_backoff: Duration,
+}
+
+impl Client {
+    /// Creates a client for the server at `base_url`, eg. `http://localhost:8080`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Client::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Creates a client using a preconfigured [`reqwest::Client`].
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Client {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            idempotency_key_header: DEFAULT_IDEMPOTENCY_KEY_HEADER.to_string(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            max_retry_backoff: DEFAULT_MAX_RETRY_BACKOFF,
+        }
+    }
+
+    /// Sets the header carrying idempotency keys, which must match the server's idempotency config.
+    pub fn idempotency_key_header(mut self, header: impl Into<String>) -> Self {
+        self.idempotency_key_header = header.into();
+        self
+    }
+
+    /// Sets how many times a command is retried when the server responds with a conflict.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, doubling with each subsequent retry.
+    pub fn retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Sets the longest delay between retries, which the doubling backoff stops at.
+    pub fn max_retry_backoff(mut self, max_retry_backoff: Duration) -> Self {
+        self.max_retry_backoff = max_retry_backoff;
+        self
+    }
+
+    /// Executes a command registered with the snake case name of `C`, eg. `transfer_funds` for `TransferFunds`.
+    ///
+    /// Use [`Client::execute_with`] for commands registered under a different name.
+    pub async fn execute<C, O>(&self, input: C::Input) -> Result<ExecuteResult<O>, ClientError>
+    where
+        C: Command<O>,
+        C::Input: Serialize,
+        O: DeserializeOwned + Send,
+    {
+        self.execute_with::<C, O>(&input, ExecuteOptions::new())
+            .await
+    }
+
+    /// Executes a command with an idempotency key, correlation headers or command name.
+    pub async fn execute_with<C, O>(
+        &self,
+        input: &C::Input,
+        options: ExecuteOptions,
+    ) -> Result<ExecuteResult<O>, ClientError>
+    where
+        C: Command<O>,
+        C::Input: Serialize,
+        O: DeserializeOwned + Send,
+    {
+        let command = options.command.clone().unwrap_or_else(command_name::<C>);
+        let url = format!("{}/commands/{command}", self.base_url);
+
+        let mut backoff = self.retry_backoff;
+        let mut retries = 0;
+        loop {
+            let mut request = self.http.post(&url).json(input);
+            if let Some(idempotency_key) = &options.idempotency_key {
+                request = request.header(&self.idempotency_key_header, idempotency_key);
+            }
+            if let Some(correlation_id) = options.correlation_id {
+                request = request.header(CORRELATION_ID_HEADER, correlation_id.to_string());
+            }
+            if let Some(causation_id) = options.causation_id {
+                request = request.header(CAUSATION_ID_HEADER, causation_id.to_string());
+            }
+            if let Some(expected_position) = options.expected_position {
+                request = request.header(IF_MATCH, format!("\"{expected_position}\""));
+            }
+
+            match execute_request(request).await {
+                Err(err) if err.is_conflict() && retries < self.max_retries => {
+                    retries += 1;
+                    warn!(%command, retries, "command conflicted, retrying in {backoff:?}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = next_backoff(backoff, self.max_retry_backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Doubles the backoff, up to `max`.
+fn next_backoff(backoff: Duration, max: Duration) -> Duration {
+    backoff.saturating_mul(2).min(max)
+}
+
+/// Options for [`Client::execute_with`].
+#[derive(Clone, Debug, Default)]
+pub struct ExecuteOptions {
+    command: Option<String>,
+    idempotency_key: Option<String>,
+    correlation_id: Option<Uuid>,
+    causation_id: Option<Uuid>,
+    expected_position: Option<u64>,
+}
+
+impl ExecuteOptions {
+    pub fn new() -> Self {
+        ExecuteOptions::default()
+    }
+
+    /// Continues the correlation of `context`, with the command as the causation.
+    ///
+    /// Used when a command is executed while handling another command or event.
+    pub fn from_context(context: &CommandContext) -> Self {
+        ExecuteOptions::new()
+            .correlation_id(context.correlation_id)
+            .causation_id(context.command_id)
+    }
+
+    /// Sets the name the command was registered with on the server.
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// Sets the idempotency key, so retries with the same key replay the response of the first
+    /// execution instead of executing the command again.
+    ///
+    /// Keys are only honoured for as long as the server's idempotency backend keeps them, eg.
+    /// until they expire or the server restarts with the memory backend.
+    pub fn idempotency_key(mut self, idempotency_key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(idempotency_key.into());
+        self
+    }
+
+    /// Sets the correlation ID, continuing an existing correlation.
+    pub fn correlation_id(mut self, correlation_id: Uuid) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+
+    /// Sets the ID of the event or command which triggered this command.
+    pub fn causation_id(mut self, causation_id: Uuid) -> Self {
+        self.causation_id = Some(causation_id);
+        self
+    }
+
+    /// Sets the position last observed by the caller, eg. from a previous [`ExecuteResult`].
+    ///
+    /// The command fails with [`ErrorCode::PreconditionFailed`] if events matching its query
+    /// were appended since, rather than overwriting changes the caller has not seen.
+    pub fn expected_position(mut self, expected_position: u64) -> Self {
+        self.expected_position = Some(expected_position);
+        self
+    }
+}
+
+/// Error returned when executing a command fails.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The server responded with an error, eg. the handler rejected the command.
+    #[error(transparent)]
+    Command(#[from] CommandError),
+    /// The server responded with an error which is not a command error, eg. a timeout.
+    #[error("server responded with {status}: {}", message.as_deref().unwrap_or(code))]
+    Server {
+        status: StatusCode,
+        code: String,
+        message: Option<String>,
+    },
+    /// The request failed or the response could not be decoded.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    /// The command output could not be decoded.
+    #[error("invalid command output: {0}")]
+    Output(#[from] serde_json::Error),
+}
+
+impl ClientError {
+    /// Returns `true` if the command failed due to a concurrent modification.
+    pub fn is_conflict(&self) -> bool {
+        match self {
+            ClientError::Command(err) => err.code == ErrorCode::Conflict,
+            ClientError::Server { status, .. } => *status == StatusCode::CONFLICT,
+            ClientError::Http(_) | ClientError::Output(_) => false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ResponseBody {
+    events: Vec<EventBody>,
+    #[serde(default)]
+    result: Option<Value>,
+    position: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct EventBody {
+    id: Option<Uuid>,
+    #[serde(rename = "type")]
+    event_type: String,
+    data: Value,
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    status: String,
+    code: String,
+    message: Option<String>,
+    #[serde(default)]
+    errors: Vec<FieldError>,
+    details: Option<Value>,
+}
+
+async fn execute_request<O: DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+) -> Result<ExecuteResult<O>, ClientError> {
+    let resp = request.send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.bytes().await?;
+        return Err(error_from_body(status, &body));
+    }
+
+    let replayed = resp
+        .headers()
+        .get(IDEMPOTENCY_REPLAYED_HEADER)
+        .is_some_and(|value| value == "true");
+    let body: ResponseBody = resp.json().await?;
+
+    execute_result(body, replayed)
+}
+
+/// Converts a response body into the result returned by [`Command::execute`].
+///
+/// The event data is re-encoded as JSON, matching how events are stored. A `null` result, eg.
+/// replayed from the event store, has no output.
+fn execute_result<O: DeserializeOwned>(
+    body: ResponseBody,
+    replayed: bool,
+) -> Result<ExecuteResult<O>, ClientError> {
+    let events = body
+        .events
+        .into_iter()
+        .map(|event| DCBEvent {
+            event_type: event.event_type,
+            tags: event.tags,
+            data: serde_json::to_vec(&event.data).expect("json values are serializable"),
+            uuid: event.id,
+        })
+        .collect();
+
+    let output = body
+        .result
+        .filter(|result| !result.is_null())
+        .map(serde_json::from_value)
+        .transpose()?;
+    let mut result = ExecuteResult::new(body.position, events, output);
+    result.replayed = replayed;
+    Ok(result)
+}
+
+/// Converts an error response back into the [`CommandError`] which caused it where possible.
+fn error_from_body(status: StatusCode, body: &[u8]) -> ClientError {
+    let Ok(ErrorBody {
+        status: error_status,
+        code,
+        message,
+        errors,
+        details,
+    }) = serde_json::from_slice(body)
+    else {
+        let message = String::from_utf8_lossy(body).trim().to_string();
+        return ClientError::Server {
+            status,
+            code: status.as_str().to_string(),
+            message: (!message.is_empty()).then_some(message),
+        };
+    };
+
+    let Some(error_code) = error_status
+        .parse::<ErrorCode>()
+        .ok()
+        .filter(|_| is_command_code(&code))
+    else {
+        return ClientError::Server {
+            status,
+            code,
+            message,
+        };
+    };
+    // Generic codes like `command_rejected` are used when the handler gave no reason.
+    let reason = (code != format!("command_{error_code}")).then(|| code.clone());
+
+    ClientError::Command(CommandError {
+        code: error_code,
+        message: message.unwrap_or(code),
+        reason,
+        details,
+        fields: errors,
+    })
+}
+
+/// Codes of errors raised by the server itself rather than by a command.
+const SERVER_CODES: &[&str] = &[
+    "authentication_error",
+    "breaking_changes",
+    "cancelled",
+    "checkpoint_unavailable",
+    "command_not_found",
+    "corruption",
+    "database_corrupted",
+    "deserialization_error",
+    "dirty_page_not_found",
+    "idempotency_key_in_use",
+    "idempotency_key_mismatch",
+    "idempotency_unavailable",
+    "initialization_error",
+    "integrity_error",
+    "internal",
+    "internal_error",
+    "invalid_domain_id",
+    "invalid_header",
+    "invalid_metadata",
+    "invalid_module",
+    "invalid_schema",
+    "io_error",
+    "page_already_dirty",
+    "page_already_freed",
+    "page_not_found",
+    "position_not_reached",
+    "root_id_mismatch",
+    "schema_not_found",
+    "serialization_error",
+    "stale_position",
+    "submission_not_found",
+    "submission_queue_full",
+    "timeout",
+    "transport_error",
+    "undeclared_event_type",
+    "unknown_domain_id",
+    "unknown_event_type",
+    "unsupported_content_type",
+];
+
+/// Returns `true` if an error code was raised by a command, eg. `command_rejected` or a reason
+/// like `insufficient_funds`, rather than by the server or a WASM handler's runtime.
+fn is_command_code(code: &str) -> bool {
+    !code.starts_with("handler_") && !SERVER_CODES.contains(&code)
+}
+
+/// Converts the type name of `C` to snake case, eg. `TransferFunds` to `transfer_funds`.
+fn command_name<C>() -> String {
+    let name = std::any::type_name::<C>();
+    let name = name.split('<').next().unwrap_or(name);
+    snake_case(name.rsplit("::").next().unwrap_or(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[allow(dead_code)]
+    struct TransferFunds;
+
+    #[test]
+    fn command_name_is_snake_case() {
+        #[allow(clippy::upper_case_acronyms, dead_code)]
+        struct HTTPRequest;
+
+        assert_eq!(command_name::<TransferFunds>(), "transfer_funds");
+        assert_eq!(command_name::<HTTPRequest>(), "http_request");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let max = Duration::from_secs(5);
+
+        assert_eq!(
+            next_backoff(Duration::from_millis(50), max),
+            Duration::from_millis(100)
+        );
+        assert_eq!(next_backoff(Duration::from_secs(4), max), max);
+        assert_eq!(next_backoff(Duration::MAX, max), max);
+    }
+
+    #[test]
+    fn response_is_converted_to_execute_result() {
+        let body: ResponseBody = serde_json::from_value(json!({
+            "status": "ok",
+            "events": [{
+                "id": "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b",
+                "type": "SentFunds",
+                "data": { "amount": 50.0 },
+                "tags": ["account_id:alice"],
+            }],
+            "position": 12,
+            "correlation_id": "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b",
+            "causation_id": "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b",
+        }))
+        .unwrap();
+        let result = execute_result::<()>(body, true).unwrap();
+
+        assert_eq!(result.position, Some(12));
+        assert_eq!(result.output, None);
+        assert!(result.replayed);
+        assert_eq!(result.events[0].event_type, "SentFunds");
+        assert_eq!(result.events[0].tags, ["account_id:alice"]);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&result.events[0].data).unwrap(),
+            json!({ "amount": 50.0 })
+        );
+    }
+
+    #[test]
+    fn result_is_decoded_as_output() {
+        let body: ResponseBody = serde_json::from_value(json!({
+            "status": "ok",
+            "events": [],
+            "result": { "task_id": "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b" },
+            "position": 12,
+        }))
+        .unwrap();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Output {
+            task_id: Uuid,
+        }
+        let result = execute_result::<Output>(body, false).unwrap();
+
+        assert_eq!(
+            result.output.unwrap().task_id.to_string(),
+            "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b"
+        );
+    }
+
+    #[test]
+    fn rejections_are_command_errors() {
+        let body = json!({
+            "status": "rejected",
+            "code": "command_rejected",
+            "message": "insufficient funds",
+        });
+        let err = error_from_body(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body.to_string().as_bytes(),
+        );
+
+        let ClientError::Command(err) = err else {
+            panic!("expected command error, got {err:?}");
+        };
+        assert_eq!(err.code, ErrorCode::Rejected);
+        assert_eq!(err.message, "insufficient funds");
+        assert_eq!(err.reason, None);
+    }
+
+    #[test]
+    fn reasons_and_details_are_kept() {
+        let body = json!({
+            "status": "rejected",
+            "code": "insufficient_funds",
+            "message": "insufficient funds",
+            "details": { "available": 30.0, "requested": 50.0 },
+        });
+        let err = error_from_body(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body.to_string().as_bytes(),
+        );
+
+        let ClientError::Command(err) = err else {
+            panic!("expected command error, got {err:?}");
+        };
+        assert_eq!(err.reason.as_deref(), Some("insufficient_funds"));
+        assert_eq!(
+            err.details,
+            Some(json!({ "available": 30.0, "requested": 50.0 }))
+        );
+    }
+
+    #[test]
+    fn field_errors_are_kept() {
+        let body = json!({
+            "status": "invalid_input",
+            "code": "invalid_command",
+            "message": "invalid command input",
+            "errors": [{ "path": "/amount", "message": "missing field `amount`" }],
+        });
+        let err = error_from_body(StatusCode::BAD_REQUEST, body.to_string().as_bytes());
+
+        let ClientError::Command(err) = err else {
+            panic!("expected command error, got {err:?}");
+        };
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+        assert_eq!(
+            err.fields,
+            [FieldError::new("/amount", "missing field `amount`")]
+        );
+    }
+
+    #[test]
+    fn server_errors_are_not_command_errors() {
+        for (status, error_status, code) in [
+            (StatusCode::SERVICE_UNAVAILABLE, "unavailable", "timeout"),
+            (StatusCode::NOT_FOUND, "not_found", "command_not_found"),
+            (
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "handler_version_not_found",
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+                "handler_failed",
+            ),
+        ] {
+            let body = json!({
+                "status": error_status,
+                "code": code,
+                "message": "failed",
+            });
+            let err = error_from_body(status, body.to_string().as_bytes());
+
+            let ClientError::Server {
+                status: err_status,
+                code: err_code,
+                message,
+            } = err
+            else {
+                panic!("expected server error, got {err:?}");
+            };
+            assert_eq!(err_status, status);
+            assert_eq!(err_code, code);
+            assert_eq!(message.as_deref(), Some("failed"));
+        }
+    }
+
+    #[test]
+    fn conflicts_are_retried() {
+        let body = json!({ "status": "conflict", "code": "integrity_error" });
+        let err = error_from_body(StatusCode::CONFLICT, body.to_string().as_bytes());
+
+        assert!(err.is_conflict());
+        assert!(!error_from_body(StatusCode::REQUEST_TIMEOUT, b"").is_conflict());
+    }
+}
@@ -0,0 +1,231 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    DeriveInput, Ident, LitStr,
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+};
+
+const STATUSES: &[(&str, &str)] = &[
+    ("rejected", "Rejected"),
+    ("invalid_input", "InvalidInput"),
+    ("unauthorized", "Unauthorized"),
+    ("forbidden", "Forbidden"),
+    ("not_found", "NotFound"),
+    ("conflict", "Conflict"),
+    ("precondition_failed", "PreconditionFailed"),
+    ("internal", "Internal"),
+    ("unavailable", "Unavailable"),
+];
+
+#[derive(Debug)]
+pub struct DeriveCommandError {
+    ident: Ident,
+    variants: Vec<ErrorVariant>,
+}
+
+#[derive(Debug)]
+struct ErrorVariant {
+    ident: Ident,
+    fields: syn::Fields,
+    reason: Option<LitStr>,
+    status: Ident,
+    message: Option<LitStr>,
+    details: bool,
+}
+
+impl DeriveCommandError {
+    pub fn expand(self) -> TokenStream {
+        let Self { ident, variants } = self;
+
+        if variants.is_empty() {
+            return quote! {
+                #[automatically_derived]
+                impl ::std::convert::From<#ident> for ::esruntime_sdk::error::CommandError {
+                    fn from(err: #ident) -> Self {
+                        match err {}
+                    }
+                }
+            };
+        }
+
+        let arms = variants.iter().map(
+            |ErrorVariant {
+                 ident: variant,
+                 fields,
+                 reason,
+                 status,
+                 message,
+                 details,
+             }| {
+                // Fields are bound to `__`-prefixed locals, so they can't clash with the
+                // generated `err` and `details` locals.
+                let bindings: Vec<_> = (0..fields.len())
+                    .map(|i| format_ident!("__field_{i}"))
+                    .collect();
+                let names: Vec<_> = fields.iter().filter_map(|field| field.ident.clone()).collect();
+                let pattern = match (fields, details) {
+                    (syn::Fields::Named(_), true) => {
+                        quote! { #ident::#variant { #( #names: #bindings ),* } }
+                    }
+                    (syn::Fields::Unnamed(_), true) => quote! { #ident::#variant( #( #bindings ),* ) },
+                    (syn::Fields::Named(_), false) => quote! { #ident::#variant { .. } },
+                    (syn::Fields::Unnamed(_), false) => quote! { #ident::#variant(..) },
+                    (syn::Fields::Unit, _) => quote! { #ident::#variant },
+                };
+                // Defaults to the variant name in snake case, eg. `insufficient_funds`.
+                let reason = match reason {
+                    Some(reason) => quote! { #reason },
+                    None => {
+                        let name = variant.to_string();
+                        quote! { ::esruntime_sdk::command::snake_case(#name) }
+                    }
+                };
+                let message = match message {
+                    Some(message) => quote! { ::std::string::String::from(#message) },
+                    None => quote! { ::std::string::ToString::to_string(&err) },
+                };
+                // Details which fail to serialize fail the command with an internal error, rather
+                // than being silently dropped.
+                let details = if !*details {
+                    quote! {}
+                } else {
+                    let value = if let syn::Fields::Named(_) = fields {
+                        let keys = names.iter().map(|name| name.unraw().to_string());
+                        quote! {
+                            (|| {
+                                let mut details = ::esruntime_sdk::__private::serde_json::Map::new();
+                                #(
+                                    details.insert(
+                                        ::std::string::String::from(#keys),
+                                        ::esruntime_sdk::__private::serde_json::to_value(#bindings)?,
+                                    );
+                                )*
+                                ::std::result::Result::Ok(
+                                    ::esruntime_sdk::__private::serde_json::Value::Object(details),
+                                )
+                            })()
+                        }
+                    } else {
+                        quote! {
+                            ::esruntime_sdk::__private::serde_json::to_value(( #( #bindings ),* ))
+                        }
+                    };
+                    quote! {
+                        let details: ::std::result::Result<
+                            ::esruntime_sdk::__private::serde_json::Value,
+                            ::esruntime_sdk::__private::serde_json::Error,
+                        > = #value;
+                        let err = match details {
+                            ::std::result::Result::Ok(details) => err.with_details(details),
+                            ::std::result::Result::Err(details_err) => {
+                                return ::esruntime_sdk::error::CommandError::internal(
+                                    ::std::format!("failed to serialize error details: {details_err}"),
+                                );
+                            }
+                        };
+                    }
+                };
+
+                quote! {
+                    #pattern => {
+                        let err = ::esruntime_sdk::error::CommandError::new(
+                            ::esruntime_sdk::error::ErrorCode::#status,
+                            #message,
+                        )
+                        .with_reason(#reason);
+                        #details
+                        err
+                    }
+                }
+            },
+        );
+
+        quote! {
+            #[automatically_derived]
+            impl ::std::convert::From<#ident> for ::esruntime_sdk::error::CommandError {
+                fn from(err: #ident) -> Self {
+                    match &err {
+                        #( #arms )*
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Parse for DeriveCommandError {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let input: DeriveInput = input.parse()?;
+
+        let variants = match input.data {
+            syn::Data::Enum(data) => data
+                .variants
+                .into_iter()
+                .map(ErrorVariant::from_variant)
+                .collect::<syn::Result<_>>()?,
+            _ => {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "CommandError can only be derived on enums",
+                ));
+            }
+        };
+
+        Ok(DeriveCommandError {
+            ident: input.ident,
+            variants,
+        })
+    }
+}
+
+impl ErrorVariant {
+    fn from_variant(variant: syn::Variant) -> syn::Result<Self> {
+        let mut reason: Option<LitStr> = None;
+        let mut status: Option<Ident> = None;
+        let mut message: Option<LitStr> = None;
+        let mut details = false;
+
+        for attr in &variant.attrs {
+            if !attr.path().is_ident("command_error") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("reason") {
+                    reason = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("status") {
+                    let value: Ident = meta.value()?.parse()?;
+                    let Some((_, variant)) = STATUSES.iter().find(|(name, _)| value == name) else {
+                        let statuses: Vec<_> = STATUSES.iter().map(|(name, _)| *name).collect();
+                        return Err(syn::Error::new(
+                            value.span(),
+                            format!("unknown status, expected one of {}", statuses.join(", ")),
+                        ));
+                    };
+                    status = Some(Ident::new(variant, value.span()));
+                } else if meta.path.is_ident("message") {
+                    message = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("details") {
+                    details = true;
+                } else {
+                    return Err(meta.error("unsupported command_error attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        let status = status.unwrap_or_else(|| Ident::new("Rejected", variant.ident.span()));
+
+        Ok(ErrorVariant {
+            ident: variant.ident,
+            fields: variant.fields,
+            reason,
+            status,
+            message,
+            details,
+        })
+    }
+}
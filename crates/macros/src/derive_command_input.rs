@@ -17,7 +17,14 @@ impl DeriveCommandInput {
     pub fn expand(self) -> TokenStream {
         let Self { ident, domain_ids } = self;
 
-        let domain_ids_inserts = domain_ids.into_iter().map(|(ident, domain_id)| {
+        let mut bindings: Vec<_> = domain_ids.iter().collect();
+        bindings.sort_by_key(|(ident, _)| ident.to_string());
+        let bindings = bindings.into_iter().map(|(ident, domain_id)| {
+            let field = LitStr::new(&ident.to_string(), ident.span());
+            quote! { (#field, #domain_id) }
+        });
+
+        let domain_ids_inserts = domain_ids.iter().map(|(ident, domain_id)| {
             quote! {
                 if let ::esruntime_sdk::domain_id::DomainIdValue::Value(domain_id) = ::std::convert::Into::into(::std::clone::Clone::clone(&self.#ident)) {
                     bindings
@@ -31,6 +38,8 @@ impl DeriveCommandInput {
         quote! {
             #[automatically_derived]
             impl ::esruntime_sdk::command::CommandInput for #ident {
+                const DOMAIN_ID_BINDINGS: &'static [(&'static str, &'static str)] = &[#( #bindings ),*];
+
                 fn domain_id_bindings(&self) -> ::esruntime_sdk::domain_id::DomainIdBindings {
                     let mut bindings: ::esruntime_sdk::domain_id::DomainIdBindings = ::std::collections::HashMap::new();
                     #( #domain_ids_inserts )*
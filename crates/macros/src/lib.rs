@@ -1,3 +1,4 @@
+mod derive_command_error;
 mod derive_command_input;
 mod derive_event;
 mod derive_event_set;
@@ -5,6 +6,7 @@ mod derive_event_set;
 use proc_macro::TokenStream;
 use syn::parse_macro_input;
 
+use crate::derive_command_error::DeriveCommandError;
 use crate::derive_command_input::DeriveCommandInput;
 use crate::derive_event::DeriveEvent;
 use crate::derive_event_set::DeriveEventSet;
@@ -15,6 +17,30 @@ pub fn command(input: TokenStream) -> TokenStream {
     TokenStream::from(input.expand())
 }
 
+/// Implements `From<T> for CommandError` for an error enum.
+///
+/// Each variant maps to a reason (defaulting to the snake case variant name), a status
+/// (defaulting to `rejected`) and a message (defaulting to its `Display` impl).
+/// `details` includes the variant's fields as the error details. If they fail to serialize, the
+/// error is converted to an internal error instead.
+///
+/// ```ignore
+/// #[derive(Debug, thiserror::Error, CommandError)]
+/// enum TransferFundsError {
+///     #[error("insufficient funds: available {available}, requested {requested}")]
+///     #[command_error(details)]
+///     InsufficientFunds { available: f64, requested: f64 },
+///     #[error("account {0} not found")]
+///     #[command_error(reason = "account_not_found", status = not_found)]
+///     AccountNotFound(String),
+/// }
+/// ```
+#[proc_macro_derive(CommandError, attributes(command_error))]
+pub fn command_error(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveCommandError);
+    TokenStream::from(input.expand())
+}
+
 #[proc_macro_derive(Event, attributes(event_type, domain_id))]
 pub fn event(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveEvent);
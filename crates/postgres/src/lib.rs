@@ -1,21 +1,36 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
 use esruntime_sdk::{
     error::SerializationError,
     event::{EventSet, StoredEvent, StoredEventData},
 };
 use futures::TryStreamExt;
+use metrics::{gauge, histogram};
 use serde_json::Value;
 use sqlx::{PgPool, PgTransaction};
 use thiserror::Error;
-use tokio::time::Instant;
+use tokio::{sync::Notify, time::Instant};
 use tracing::warn;
-use umadb_client::AsyncUmaDBClient;
 use umadb_dcb::{DCBError, DCBEventStoreAsync, DCBQuery, DCBQueryItem, DCBReadResponseAsync};
 
 const DEFAULT_CHECKPOINT_TABLE_NAME: &str = "checkpoints";
 const DEFAULT_CHECKPOINT_POSITION_COLUMN: &str = "position";
 const DEFAULT_CHECKPOINT_PROJECTION_ID_COL: &str = "projection_id";
+const MIN_CHECKPOINT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const MAX_CHECKPOINT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const DEFAULT_HEAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const PROJECTION_POSITION: &str = "esruntime_projection_position";
+pub const PROJECTION_HEAD_POSITION: &str = "esruntime_projection_head_position";
+pub const PROJECTION_LAG: &str = "esruntime_projection_lag";
+pub const PROJECTION_FLUSH_DURATION: &str = "esruntime_projection_flush_duration_seconds";
 
 pub struct ProjectionRunner<H, C>
 where
@@ -23,10 +38,18 @@ where
     C: Checkpoint,
 {
     pool: PgPool,
+    status: ProjectionStatus,
     handler: H,
     checkpoint: C,
     flush_config: FlushConfig,
+    store: Arc<dyn DCBEventStoreAsync>,
+    query: Option<DCBQuery>,
+    /// Head of the event store when the runner started, events up to which are replayed.
+    replay_head: Option<u64>,
+    /// Latest known head of the event store, polled every `head_poll_interval`.
     head: Option<u64>,
+    head_poll_interval: Duration,
+    head_polled_at: Instant,
     stream: Box<dyn DCBReadResponseAsync + Send + 'static>,
     transaction: Option<PgTransaction<'static>>,
     position: Option<u64>,
@@ -41,40 +64,89 @@ where
     C: Checkpoint,
     ProjectionError<H::Error>: From<C::Error>,
 {
+    #[allow(clippy::too_many_arguments)]
     async fn new(
         pool: PgPool,
-        event_store: &AsyncUmaDBClient,
+        name: Arc<str>,
+        store: Arc<dyn DCBEventStoreAsync>,
         handler: H,
         checkpoint: C,
         query: Option<DCBQuery>,
         flush_config: FlushConfig,
+        head_poll_interval: Duration,
     ) -> Result<Self, ProjectionError<H::Error>> {
         let position = checkpoint.load().await?;
 
-        let head = event_store.head().await?;
-        let stream = event_store
-            .read(query, position.map(|pos| pos + 1), false, None, true)
+        let head = store.head().await?;
+        let stream = store
+            .read(
+                query.clone(),
+                position.map(|pos| pos + 1),
+                false,
+                None,
+                true,
+            )
             .await?;
 
-        Ok(ProjectionRunner {
+        let runner = ProjectionRunner {
             pool,
+            status: ProjectionStatus::new(name),
             handler,
             checkpoint,
             flush_config,
+            store,
+            query,
+            replay_head: head,
             head,
+            head_poll_interval,
+            head_polled_at: Instant::now(),
             stream,
             transaction: None,
             position,
             last_flushed_position: position,
             events_since_flush: 0,
             last_flushed_at: Instant::now(),
-        })
+        };
+        runner.record_positions();
+
+        Ok(runner)
     }
 
     pub fn builder(checkpoint: C) -> ProjectionRunnerBuilder<C> {
         ProjectionRunnerBuilder::new(checkpoint)
     }
 
+    /// The name of the projection, used to label its metrics.
+    pub fn name(&self) -> &str {
+        self.status.name()
+    }
+
+    /// Returns a handle to observe the progress of this projection from elsewhere,
+    /// such as a readiness check.
+    pub fn status(&self) -> ProjectionStatus {
+        self.status.clone()
+    }
+
+    /// The last position committed to the checkpoint.
+    pub fn checkpoint_position(&self) -> Option<u64> {
+        self.last_flushed_position
+    }
+
+    /// The latest known head position of the event store.
+    ///
+    /// The head is polled from the event store every second by default, and moves with the events
+    /// received in between.
+    pub fn head_position(&self) -> Option<u64> {
+        self.head
+    }
+
+    /// Number of events between the checkpoint and the head of the event store.
+    pub fn lag(&self) -> u64 {
+        self.head
+            .unwrap_or(0)
+            .saturating_sub(self.last_flushed_position.unwrap_or(0))
+    }
+
     pub async fn run(&mut self) -> Result<(), ProjectionError<H::Error>> {
         while self.next().await? {}
 
@@ -82,24 +154,27 @@ where
     }
 
     pub async fn next(&mut self) -> Result<bool, ProjectionError<H::Error>> {
-        let event = if self.events_since_flush > 0 {
-            let is_replaying = self.position <= self.head;
-            let interval = if is_replaying {
+        if self.head_polled_at.elapsed() >= self.head_poll_interval {
+            self.poll_head().await?;
+        }
+
+        let mut deadline = self.head_polled_at + self.head_poll_interval;
+        if self.events_since_flush > 0 {
+            let interval = if self.is_replaying() {
                 self.flush_config.replay_time_interval
             } else {
                 self.flush_config.live_time_interval
             };
-            match tokio::time::timeout_at(self.last_flushed_at + interval, self.stream.try_next())
-                .await
-            {
-                Ok(res) => res?,
-                Err(_) => {
+            deadline = deadline.min(self.last_flushed_at + interval);
+        }
+        let event = match tokio::time::timeout_at(deadline, self.stream.try_next()).await {
+            Ok(res) => res?,
+            Err(_) => {
+                if self.events_since_flush > 0 {
                     self.flush_if_necessary().await?;
-                    return Ok(true);
                 }
+                return Ok(true);
             }
-        } else {
-            self.stream.try_next().await?
         };
         let Some(event) = event else {
             return Ok(false);
@@ -125,6 +200,7 @@ where
                 correlation_id: event_data.correlation_id,
                 causation_id: event_data.causation_id,
                 triggered_by: event_data.triggered_by,
+                handler_version: event_data.handler_version,
                 data,
             };
             self.handler
@@ -135,6 +211,7 @@ where
             warn!("received event which was not deserialized into the query");
         }
         self.position = Some(event.position);
+        self.head = self.head.max(Some(event.position));
         self.events_since_flush += 1;
 
         self.flush_if_necessary().await?;
@@ -151,6 +228,8 @@ where
             return Ok(());
         };
 
+        let started_at = Instant::now();
+
         self.checkpoint
             .save(&mut tx, self.last_flushed_position, position)
             .await?;
@@ -171,11 +250,62 @@ where
         self.last_flushed_at = Instant::now();
         self.last_flushed_position = Some(position);
 
+        histogram!(PROJECTION_FLUSH_DURATION, "projection" => self.status.name().to_string())
+            .record(started_at.elapsed());
+        self.record_positions();
+
+        Ok(())
+    }
+
+    /// Returns whether the runner is still handling events from before it started.
+    fn is_replaying(&self) -> bool {
+        self.position <= self.replay_head
+    }
+
+    /// Polls the head of the event store, which events the projection doesn't match move too.
+    ///
+    /// When every event the projection matches up to the head has been handled, the checkpoint
+    /// skips ahead to the head, so the lag reaches zero and waits for later positions finish.
+    async fn poll_head(&mut self) -> Result<(), ProjectionError<H::Error>> {
+        let head = self.store.head().await?;
+        self.head = head.max(self.head);
+        self.head_polled_at = Instant::now();
+
+        if self.events_since_flush == 0 && head > self.last_flushed_position {
+            let last_matching = self
+                .store
+                .read(self.query.clone(), head, true, Some(1), false)
+                .await?
+                .try_next()
+                .await?
+                .map(|event| event.position);
+            if let Some(position) = skip_position(self.last_flushed_position, last_matching, head) {
+                let mut tx = self.pool.begin().await?;
+                self.checkpoint
+                    .save(&mut tx, self.last_flushed_position, position)
+                    .await?;
+                tx.commit().await?;
+                self.last_flushed_position = Some(position);
+            }
+        }
+        self.record_positions();
+
         Ok(())
     }
 
+    fn record_positions(&self) {
+        self.status.update(self.last_flushed_position, self.head);
+
+        let projection = self.status.name().to_string();
+        gauge!(PROJECTION_POSITION, "projection" => projection.clone())
+            .set(self.last_flushed_position.unwrap_or(0) as f64);
+        gauge!(PROJECTION_HEAD_POSITION, "projection" => projection.clone())
+            .set(self.head.unwrap_or(0) as f64);
+        gauge!(PROJECTION_LAG, "projection" => projection).set(self.lag() as f64);
+    }
+
     async fn flush_if_necessary(&mut self) -> Result<(), ProjectionError<H::Error>> {
-        let is_replaying = self.position <= self.head;
+        let is_replaying = self.is_replaying();
 
         if self.flush_config.should_flush(
             self.events_since_flush,
@@ -189,25 +319,138 @@ where
     }
 }
 
+/// Returns the position the checkpoint can skip ahead to, if the last event the projection matches
+/// up to the head has been handled.
+fn skip_position(
+    checkpoint: Option<u64>,
+    last_matching: Option<u64>,
+    head: Option<u64>,
+) -> Option<u64> {
+    if last_matching <= checkpoint && head > checkpoint {
+        head
+    } else {
+        None
+    }
+}
+
+/// A shared view of a projection's progress, updated by its [`ProjectionRunner`].
+#[derive(Clone, Debug)]
+pub struct ProjectionStatus {
+    inner: Arc<ProjectionStatusInner>,
+}
+
+#[derive(Debug)]
+struct ProjectionStatusInner {
+    name: Arc<str>,
+    // Positions start at 1, so 0 represents no position.
+    position: AtomicU64,
+    head: AtomicU64,
+    position_changed: Notify,
+}
+
+impl ProjectionStatus {
+    fn new(name: Arc<str>) -> Self {
+        ProjectionStatus {
+            inner: Arc::new(ProjectionStatusInner {
+                name,
+                position: AtomicU64::new(0),
+                head: AtomicU64::new(0),
+                position_changed: Notify::new(),
+            }),
+        }
+    }
+
+    /// The name of the projection.
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// The last position committed to the checkpoint.
+    pub fn position(&self) -> Option<u64> {
+        Some(self.inner.position.load(Ordering::Acquire)).filter(|pos| *pos > 0)
+    }
+
+    /// The latest known head position of the event store.
+    pub fn head_position(&self) -> Option<u64> {
+        Some(self.inner.head.load(Ordering::Acquire)).filter(|pos| *pos > 0)
+    }
+
+    /// Number of events between the checkpoint and the head of the event store.
+    ///
+    /// Reaches zero once the projection has handled every event it matches, as the checkpoint then
+    /// skips past the events it doesn't.
+    pub fn lag(&self) -> u64 {
+        self.inner
+            .head
+            .load(Ordering::Acquire)
+            .saturating_sub(self.inner.position.load(Ordering::Acquire))
+    }
+
+    /// Returns true if the checkpoint is within `max_lag` events of the head.
+    pub fn is_caught_up(&self, max_lag: u64) -> bool {
+        self.lag() <= max_lag
+    }
+
+    /// Waits until the checkpoint reaches `position`, such as the position returned by a command.
+    ///
+    /// Positions of events the projection doesn't match are reached once the runner polls the
+    /// head of the event store and skips past them.
+    pub async fn wait_for_position(
+        &self,
+        position: u64,
+        timeout: Duration,
+    ) -> Result<(), WaitTimeout> {
+        let reached = async {
+            loop {
+                let mut changed = pin!(self.inner.position_changed.notified());
+                changed.as_mut().enable();
+                if self.inner.position.load(Ordering::Acquire) >= position {
+                    return;
+                }
+                changed.await;
+            }
+        };
+
+        tokio::time::timeout(timeout, reached)
+            .await
+            .map_err(|_| WaitTimeout {
+                position,
+                current: self.position(),
+            })
+    }
+
+    fn update(&self, position: Option<u64>, head: Option<u64>) {
+        self.inner
+            .position
+            .store(position.unwrap_or(0), Ordering::Release);
+        self.inner.head.store(head.unwrap_or(0), Ordering::Release);
+        self.inner.position_changed.notify_waiters();
+    }
+}
+
 pub struct ProjectionRunnerBuilder<C> {
+    name: Option<Arc<str>>,
     checkpoint: C,
     query: Option<Option<DCBQuery>>,
     flush_config: FlushConfig,
+    head_poll_interval: Duration,
 }
 
 impl<C> ProjectionRunnerBuilder<C> {
     pub fn new(checkpoint: C) -> Self {
         ProjectionRunnerBuilder {
+            name: None,
             checkpoint,
             query: None,
             flush_config: FlushConfig::default(),
+            head_poll_interval: DEFAULT_HEAD_POLL_INTERVAL,
         }
     }
 
     pub async fn build<H>(
         self,
         pool: PgPool,
-        event_store: &AsyncUmaDBClient,
+        event_store: Arc<impl DCBEventStoreAsync + 'static>,
         handler: H,
     ) -> Result<ProjectionRunner<H, C>, ProjectionError<H::Error>>
     where
@@ -215,8 +458,13 @@ impl<C> ProjectionRunnerBuilder<C> {
         C: Checkpoint,
         ProjectionError<H::Error>: From<C::Error>,
     {
+        let name = self
+            .name
+            .unwrap_or_else(|| default_projection_name::<H>().into());
+
         ProjectionRunner::new(
             pool,
+            name,
             event_store,
             handler,
             self.checkpoint,
@@ -226,18 +474,29 @@ impl<C> ProjectionRunnerBuilder<C> {
                 ]))
             }),
             self.flush_config,
+            self.head_poll_interval,
         )
         .await
     }
 
     pub fn checkpoint<T>(self, checkpoint: T) -> ProjectionRunnerBuilder<T> {
         ProjectionRunnerBuilder {
+            name: self.name,
             checkpoint,
             query: self.query,
             flush_config: self.flush_config,
+            head_poll_interval: self.head_poll_interval,
         }
     }
 
+    /// Sets the name of the projection, used to label its metrics.
+    ///
+    /// Defaults to the type name of the event handler.
+    pub fn name(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
     pub fn query(mut self, query: Option<DCBQuery>) -> Self {
         self.query = Some(query);
         self
@@ -262,6 +521,18 @@ impl<C> ProjectionRunnerBuilder<C> {
         self.flush_config.replay_time_interval = flush_time_interval;
         self
     }
+
+    /// Sets how often the head of the event store is polled, which defaults to every second.
+    pub fn head_poll_interval(mut self, head_poll_interval: Duration) -> Self {
+        self.head_poll_interval = head_poll_interval;
+        self
+    }
+}
+
+fn default_projection_name<H>() -> &'static str {
+    let name = std::any::type_name::<H>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
 }
 
 pub trait EventHandler {
@@ -334,6 +605,77 @@ impl CheckpointTable {
         self.projection_id_col = projection_id_col.into();
         self
     }
+
+    /// Returns the position of every projection in the checkpoint table, sorted by projection ID.
+    pub async fn positions(&self) -> Result<Vec<(String, u64)>, sqlx::Error> {
+        let positions = sqlx::query_as::<_, (String, i64)>(&format!(
+            "SELECT {}, {} FROM {} ORDER BY {}",
+            self.projection_id_col, self.position_col, self.table, self.projection_id_col,
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(positions
+            .into_iter()
+            .map(|(projection_id, position)| (projection_id, position as u64))
+            .collect())
+    }
+
+    /// Deletes the checkpoint, so the projection replays every event when next run.
+    ///
+    /// Returns whether a checkpoint existed. The projection's own tables are left untouched.
+    pub async fn reset(&self) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE {} = $1",
+            self.table, self.projection_id_col,
+        ))
+        .bind(self.projection_id.as_ref())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Polls the checkpoint until it reaches `position`.
+    ///
+    /// Unlike [`ProjectionStatus::wait_for_position`], this works across processes,
+    /// such as an API server reading a projection run by a separate worker.
+    pub async fn wait_for_position(
+        &self,
+        position: u64,
+        timeout: Duration,
+    ) -> Result<(), WaitError> {
+        poll_for_position(|| self.load(), position, timeout).await
+    }
+}
+
+/// Polls a checkpoint with exponential backoff until it reaches `position`.
+async fn poll_for_position<F, Fut>(
+    mut load: F,
+    position: u64,
+    timeout: Duration,
+) -> Result<(), WaitError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<u64>, sqlx::Error>>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut poll_interval = MIN_CHECKPOINT_POLL_INTERVAL;
+
+    loop {
+        let current = load().await?;
+        if current >= Some(position) {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(WaitTimeout { position, current }.into());
+        }
+
+        tokio::time::sleep(poll_interval.min(deadline - now)).await;
+        poll_interval = (poll_interval * 2).min(MAX_CHECKPOINT_POLL_INTERVAL);
+    }
 }
 
 impl Checkpoint for CheckpointTable {
@@ -426,6 +768,22 @@ impl Default for FlushConfig {
     }
 }
 
+/// A projection did not reach a position in time.
+#[derive(Debug, Error)]
+#[error("timed out waiting for position {position}, checkpoint is at {current:?}")]
+pub struct WaitTimeout {
+    pub position: u64,
+    pub current: Option<u64>,
+}
+
+#[derive(Debug, Error)]
+pub enum WaitError {
+    #[error(transparent)]
+    Timeout(#[from] WaitTimeout),
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
 #[derive(Debug, Error)]
 pub enum ProjectionError<E> {
     #[error(transparent)]
@@ -439,3 +797,86 @@ pub enum ProjectionError<E> {
     #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_trailing_events_the_projection_does_not_match() {
+        // Events 6 and 7 are of types the projection doesn't consume.
+        assert_eq!(skip_position(Some(5), Some(5), Some(7)), Some(7));
+        assert_eq!(skip_position(None, None, Some(3)), Some(3));
+
+        let status = ProjectionStatus::new("accounts".into());
+        status.update(Some(5), Some(7));
+        assert!(!status.is_caught_up(0));
+        status.update(skip_position(Some(5), Some(5), Some(7)), Some(7));
+        assert_eq!(status.lag(), 0);
+    }
+
+    #[tokio::test]
+    async fn waits_for_positions_of_events_the_projection_does_not_match() {
+        let status = ProjectionStatus::new("accounts".into());
+        status.update(Some(5), Some(5));
+
+        // The command's last event, at position 7, is of a type the projection doesn't consume.
+        let waiter = status.clone();
+        let wait =
+            tokio::spawn(async move { waiter.wait_for_position(7, Duration::from_secs(5)).await });
+        tokio::task::yield_now().await;
+        status.update(skip_position(Some(5), Some(5), Some(7)), Some(7));
+
+        wait.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_for_position_times_out_with_the_current_position() {
+        let status = ProjectionStatus::new("accounts".into());
+        status.update(Some(5), Some(7));
+
+        let err = status
+            .wait_for_position(7, Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        assert_eq!(err.position, 7);
+        assert_eq!(err.current, Some(5));
+        status.wait_for_position(5, Duration::ZERO).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn polls_checkpoints_until_the_position_is_reached() {
+        let positions = [None, Some(3), Some(7)];
+        let mut polls = 0;
+        poll_for_position(
+            || {
+                polls += 1;
+                let position = positions[polls - 1];
+                async move { Ok(position) }
+            },
+            7,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(polls, 3);
+
+        let err = poll_for_position(|| async { Ok(Some(6)) }, 7, Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            WaitError::Timeout(WaitTimeout {
+                position: 7,
+                current: Some(6)
+            })
+        ));
+    }
+
+    #[test]
+    fn does_not_skip_unhandled_events() {
+        assert_eq!(skip_position(Some(5), Some(6), Some(7)), None);
+        assert_eq!(skip_position(Some(7), Some(7), Some(7)), None);
+        assert_eq!(skip_position(None, Some(1), Some(3)), None);
+    }
+}
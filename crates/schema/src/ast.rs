@@ -0,0 +1,139 @@
+//! Typed syntax tree of an ESDL schema.
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::diagnostic::Span;
+
+/// A parsed and validated ESDL schema.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schema {
+    /// Enums, in order of declaration.
+    pub enums: Vec<EnumDef>,
+    /// Events, in order of declaration.
+    pub events: Vec<EventDef>,
+}
+
+impl Schema {
+    /// Returns an event by name.
+    pub fn event(&self, name: &str) -> Option<&EventDef> {
+        self.events.iter().find(|event| event.name == name)
+    }
+
+    /// Returns an enum by name.
+    pub fn enum_def(&self, name: &str) -> Option<&EnumDef> {
+        self.enums.iter().find(|enum_def| enum_def.name == name)
+    }
+}
+
+/// An `event Name { ... }` definition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventDef {
+    pub name: String,
+    pub name_span: Span,
+    pub fields: Vec<Field>,
+}
+
+impl EventDef {
+    /// Returns a field by name.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Returns the names of the domain ID fields, in order of declaration.
+    pub fn domain_ids(&self) -> impl Iterator<Item = &str> {
+        self.fields
+            .iter()
+            .filter(|field| field.domain_id)
+            .map(|field| field.name.as_str())
+    }
+}
+
+/// A field of an event, prefixed with `@` when it is a domain ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub name_span: Span,
+    pub ty: Type,
+    pub ty_span: Span,
+    pub domain_id: bool,
+}
+
+/// An `enum Name { A, B }` definition of unit variants.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnumDef {
+    pub name: String,
+    pub name_span: Span,
+    pub variants: Vec<String>,
+}
+
+/// The type of a field.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    String,
+    Int,
+    Float,
+    Bool,
+    Uuid,
+    Timestamp,
+    Json,
+    /// An enum declared in the schema.
+    Enum(String),
+    /// `T?`, which may be absent or null.
+    Optional(Box<Type>),
+    /// `[T]`
+    List(Box<Type>),
+}
+
+impl Type {
+    /// Returns the built-in type with a name.
+    pub fn builtin(name: &str) -> Option<Type> {
+        match name {
+            "String" => Some(Type::String),
+            "Int" => Some(Type::Int),
+            "Float" => Some(Type::Float),
+            "Bool" => Some(Type::Bool),
+            "Uuid" => Some(Type::Uuid),
+            "Timestamp" => Some(Type::Timestamp),
+            "Json" => Some(Type::Json),
+            _ => None,
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        matches!(self, Type::Optional(_))
+    }
+
+    /// Returns the name of the enum referenced by the type, if any.
+    pub fn enum_name(&self) -> Option<&str> {
+        match self {
+            Type::Enum(name) => Some(name),
+            Type::Optional(ty) | Type::List(ty) => ty.enum_name(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::String => write!(f, "String"),
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Uuid => write!(f, "Uuid"),
+            Type::Timestamp => write!(f, "Timestamp"),
+            Type::Json => write!(f, "Json"),
+            Type::Enum(name) => write!(f, "{name}"),
+            Type::Optional(ty) => write!(f, "{ty}?"),
+            Type::List(ty) => write!(f, "[{ty}]"),
+        }
+    }
+}
+
+impl Serialize for Type {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
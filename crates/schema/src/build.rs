@@ -0,0 +1,191 @@
+//! Generates Rust types from an ESDL schema in a build script.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     esruntime_schema::build::generate("schema.esdl").unwrap();
+//! }
+//!
+//! // src/events.rs
+//! include!(concat!(env!("OUT_DIR"), "/schema.rs"));
+//! ```
+//!
+//! Each event becomes a struct deriving `Event`, `Serialize` and `Deserialize`, with its domain
+//! IDs marked `#[domain_id]`, and each enum becomes a unit enum. The crate including the code
+//! depends on `esruntime-sdk` and `serde`, as well as `uuid`, `chrono` or `serde_json` if the
+//! schema uses `Uuid`, `Timestamp` or `Json`.
+
+use std::{
+    env,
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    ast::{EnumDef, EventDef, Schema, Type},
+    diagnostic::Diagnostics,
+    parse,
+};
+
+/// Error returned when generating code from a schema.
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("failed to read or write the schema: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid schema {}:\n{diagnostics}", path.display())]
+    Schema {
+        path: PathBuf,
+        diagnostics: Diagnostics,
+    },
+    #[error("OUT_DIR is not set, so code can only be generated from a build script")]
+    MissingOutDir,
+}
+
+/// Generates the types of the schema at `path` into `$OUT_DIR/{file stem}.rs`.
+///
+/// Cargo is told to rerun the build script when the schema changes.
+pub fn generate(path: impl AsRef<Path>) -> Result<PathBuf, BuildError> {
+    let path = path.as_ref();
+    let out_dir = env::var_os("OUT_DIR").ok_or(BuildError::MissingOutDir)?;
+    let file_name = path.file_stem().unwrap_or(path.as_os_str());
+    let out = Path::new(&out_dir).join(file_name).with_extension("rs");
+
+    println!("cargo:rerun-if-changed={}", path.display());
+    generate_to(path, &out)?;
+    Ok(out)
+}
+
+/// Generates the types of the schema at `path` into the file `out`.
+pub fn generate_to(path: impl AsRef<Path>, out: impl AsRef<Path>) -> Result<(), BuildError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let schema = parse(&source).map_err(|diagnostics| BuildError::Schema {
+        path: path.to_path_buf(),
+        diagnostics,
+    })?;
+
+    fs::write(out, render(&schema))?;
+    Ok(())
+}
+
+/// Renders the Rust types of a schema.
+pub fn render(schema: &Schema) -> String {
+    let mut code =
+        String::from("// @generated by esruntime-schema. Changes are made in the schema.\n");
+    for enum_def in &schema.enums {
+        render_enum(&mut code, enum_def);
+    }
+    for event in &schema.events {
+        render_event(&mut code, event);
+    }
+    code
+}
+
+fn render_enum(code: &mut String, enum_def: &EnumDef) {
+    code.push_str(
+        "\n#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ::serde::Serialize, ::serde::Deserialize)]\n",
+    );
+    writeln!(code, "pub enum {} {{", ident(&enum_def.name)).unwrap();
+    for variant in &enum_def.variants {
+        writeln!(code, "    {},", ident(variant)).unwrap();
+    }
+    code.push_str("}\n");
+}
+
+fn render_event(code: &mut String, event: &EventDef) {
+    code.push_str(
+        "\n#[derive(Clone, Debug, PartialEq, ::esruntime_sdk::Event, ::serde::Serialize, ::serde::Deserialize)]\n",
+    );
+    writeln!(code, "#[event_type({:?})]", event.name).unwrap();
+    writeln!(code, "pub struct {} {{", ident(&event.name)).unwrap();
+    for field in &event.fields {
+        if field.domain_id {
+            code.push_str("    #[domain_id]\n");
+        }
+        writeln!(
+            code,
+            "    pub {}: {},",
+            ident(&field.name),
+            rust_type(&field.ty)
+        )
+        .unwrap();
+    }
+    code.push_str("}\n");
+}
+
+fn rust_type(ty: &Type) -> String {
+    match ty {
+        Type::String => "::std::string::String".to_string(),
+        Type::Int => "i64".to_string(),
+        Type::Float => "f64".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Uuid => "::uuid::Uuid".to_string(),
+        Type::Timestamp => "::chrono::DateTime<::chrono::Utc>".to_string(),
+        Type::Json => "::serde_json::Value".to_string(),
+        Type::Enum(name) => ident(name),
+        Type::Optional(ty) => format!("::std::option::Option<{}>", rust_type(ty)),
+        Type::List(ty) => format!("::std::vec::Vec<{}>", rust_type(ty)),
+    }
+}
+
+/// Escapes names which are Rust keywords as raw identifiers.
+///
+/// Names which cannot be raw identifiers, like `self`, are rejected when the schema is parsed.
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do",
+        "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in",
+        "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+        "return", "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe",
+        "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_events_and_enums() {
+        let schema = parse(
+            "enum Currency { Aud, Usd }\n\
+             event SentFunds {\n  @account_id: String\n  amount: Float\n  currency: Currency\n  \
+             type: String?\n  @transfer_id: Uuid\n  labels: [String]\n}",
+        )
+        .unwrap();
+        let code = render(&schema);
+
+        assert!(code.contains("pub enum Currency {\n    Aud,\n    Usd,\n}\n"));
+        assert!(code.contains(
+            "#[event_type(\"SentFunds\")]\n\
+             pub struct SentFunds {\n    \
+             #[domain_id]\n    pub account_id: ::std::string::String,\n    \
+             pub amount: f64,\n    \
+             pub currency: Currency,\n    \
+             pub r#type: ::std::option::Option<::std::string::String>,\n    \
+             #[domain_id]\n    pub transfer_id: ::uuid::Uuid,\n    \
+             pub labels: ::std::vec::Vec<::std::string::String>,\n}\n"
+        ));
+    }
+
+    #[test]
+    fn escapes_type_names() {
+        let schema = parse("enum match { Aud }\nevent type { currency: match }").unwrap();
+        let code = render(&schema);
+
+        assert!(code.contains("pub enum r#match {\n"));
+        assert!(code.contains(
+            "#[event_type(\"type\")]\n\
+             pub struct r#type {\n    pub currency: r#match,\n}\n"
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Changes between two versions of a schema.
+
+use serde::Serialize;
+
+use crate::ast::{EventDef, Schema, Type};
+
+/// Events added, modified and removed by a new version of a schema.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SchemaChanges {
+    /// Events only in the new schema, in order of declaration.
+    pub added_events: Vec<String>,
+    /// Events whose fields, or the enums they use, changed.
+    pub modified_events: Vec<String>,
+    /// Events only in the old schema.
+    pub removed_events: Vec<String>,
+    /// Changes which stored events, or readers of the old schema, are incompatible with.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub breaking_changes: Vec<BreakingChange>,
+}
+
+impl SchemaChanges {
+    /// Returns whether any events changed.
+    pub fn is_empty(&self) -> bool {
+        self.added_events.is_empty()
+            && self.modified_events.is_empty()
+            && self.removed_events.is_empty()
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        !self.breaking_changes.is_empty()
+    }
+}
+
+/// A change to an event which stored events are incompatible with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BreakingChange {
+    pub event: String,
+    pub change: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Human-readable description of the change
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The event was removed, so stored events can no longer be read.
+    RemovedEvent,
+    /// A field was removed.
+    RemovedField,
+    /// A required field was added, which stored events do not have.
+    AddedRequiredField,
+    /// The type of a field changed, other than becoming optional.
+    ChangedType,
+    /// A field became or stopped being a domain ID, so stored events are tagged differently.
+    ChangedDomainId,
+    /// A variant was removed from an enum used by a field.
+    RemovedVariant,
+}
+
+/// Computes the changes from `old` to `new`.
+pub fn diff(old: &Schema, new: &Schema) -> SchemaChanges {
+    let mut changes = SchemaChanges::default();
+
+    for event in &new.events {
+        let Some(old_event) = old.event(&event.name) else {
+            changes.added_events.push(event.name.clone());
+            continue;
+        };
+        let before = changes.breaking_changes.len();
+        let modified = diff_event(old, old_event, new, event, &mut changes.breaking_changes);
+        if modified || changes.breaking_changes.len() > before {
+            changes.modified_events.push(event.name.clone());
+        }
+    }
+
+    for event in &old.events {
+        if new.event(&event.name).is_none() {
+            changes.removed_events.push(event.name.clone());
+            changes.breaking_changes.push(BreakingChange {
+                event: event.name.clone(),
+                change: ChangeKind::RemovedEvent,
+                field: None,
+                message: format!("Cannot remove event '{}'", event.name),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Records the breaking changes to an event, returning whether it changed at all.
+fn diff_event(
+    old_schema: &Schema,
+    old: &EventDef,
+    new_schema: &Schema,
+    new: &EventDef,
+    breaking_changes: &mut Vec<BreakingChange>,
+) -> bool {
+    let mut modified = false;
+    let mut breaking = |change, field: &str, message: String| {
+        breaking_changes.push(BreakingChange {
+            event: new.name.clone(),
+            change,
+            field: Some(field.to_string()),
+            message,
+        });
+    };
+
+    for old_field in &old.fields {
+        if new.field(&old_field.name).is_none() {
+            breaking(
+                ChangeKind::RemovedField,
+                &old_field.name,
+                format!("Cannot remove field '{}'", old_field.name),
+            );
+        }
+    }
+
+    for field in &new.fields {
+        let Some(old_field) = old.field(&field.name) else {
+            modified = true;
+            if !field.ty.is_optional() {
+                breaking(
+                    ChangeKind::AddedRequiredField,
+                    &field.name,
+                    format!(
+                        "Cannot add required field '{}' - make it optional with `{}?`",
+                        field.name, field.ty
+                    ),
+                );
+            }
+            continue;
+        };
+
+        if field.ty != old_field.ty {
+            modified = true;
+            if !is_optional_of(&field.ty, &old_field.ty) {
+                breaking(
+                    ChangeKind::ChangedType,
+                    &field.name,
+                    format!(
+                        "Cannot change the type of '{}' from `{}` to `{}`",
+                        field.name, old_field.ty, field.ty
+                    ),
+                );
+            }
+        }
+        if field.domain_id != old_field.domain_id {
+            breaking(
+                ChangeKind::ChangedDomainId,
+                &field.name,
+                format!(
+                    "Cannot change whether '{}' is a domain ID - stored events are tagged by it",
+                    field.name
+                ),
+            );
+        }
+
+        let enums = field
+            .ty
+            .enum_name()
+            .and_then(|name| Some((old_schema.enum_def(name)?, new_schema.enum_def(name)?)));
+        if let Some((old_enum, new_enum)) = enums
+            && old_enum.variants != new_enum.variants
+        {
+            modified = true;
+            for variant in &old_enum.variants {
+                if !new_enum.variants.contains(variant) {
+                    breaking(
+                        ChangeKind::RemovedVariant,
+                        &field.name,
+                        format!(
+                            "Cannot remove variant '{variant}' from '{}', used by '{}'",
+                            new_enum.name, field.name
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    modified
+}
+
+/// Returns whether `new` is `old` made optional, which every stored value still matches.
+fn is_optional_of(new: &Type, old: &Type) -> bool {
+    matches!(new, Type::Optional(ty) if ty.as_ref() == old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn changes(old: &str, new: &str) -> SchemaChanges {
+        diff(&parse(old).unwrap(), &parse(new).unwrap())
+    }
+
+    #[test]
+    fn lists_added_modified_and_removed_events() {
+        let changes = changes(
+            "event A { @id: String }\nevent B { @id: String }\nevent C { x: Int }",
+            "event A { @id: String }\nevent B { @id: String, note: String? }\nevent D { x: Int }",
+        );
+        assert_eq!(changes.added_events, ["D"]);
+        assert_eq!(changes.modified_events, ["B"]);
+        assert_eq!(changes.removed_events, ["C"]);
+        assert_eq!(changes.breaking_changes.len(), 1);
+        assert_eq!(changes.breaking_changes[0].change, ChangeKind::RemovedEvent);
+    }
+
+    #[test]
+    fn flags_breaking_field_changes() {
+        let changes = changes(
+            "enum S { X, Y }\nevent A { @id: String, a: Int, b: Int, c: String, s: S }",
+            "enum S { X, Z }\nevent A { id: String, a: Int?, c: Float, d: Bool, s: S }",
+        );
+        let breaking: Vec<_> = changes
+            .breaking_changes
+            .iter()
+            .map(|change| (change.change, change.field.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            breaking,
+            [
+                (ChangeKind::RemovedField, "b"),
+                (ChangeKind::ChangedDomainId, "id"),
+                (ChangeKind::ChangedType, "c"),
+                (ChangeKind::AddedRequiredField, "d"),
+                (ChangeKind::RemovedVariant, "s"),
+            ]
+        );
+        assert_eq!(changes.modified_events, ["A"]);
+    }
+
+    #[test]
+    fn ignores_unchanged_schemas() {
+        let source = "enum S { X }\nevent A { @id: Uuid, s: S? }";
+        let changes = changes(source, &format!("// comment\n{source}"));
+        assert!(changes.is_empty());
+        assert!(!changes.is_breaking());
+    }
+}
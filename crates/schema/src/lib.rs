@@ -0,0 +1,42 @@
+//! # ESRuntime Schema
+//!
+//! Parser and validator for ESDL, the language describing the events of an event store,
+//! so the schema can be reviewed and shared instead of being implicit in Rust structs.
+//!
+//! ```text
+//! // Domain IDs are prefixed with `@`, and are used to tag the events.
+//! event OpenedAccount {
+//!   @account_id: String
+//!   initial_balance: Float
+//! }
+//!
+//! enum Currency { Aud, Usd }
+//!
+//! event SentFunds {
+//!   @account_id: String
+//!   amount: Float
+//!   currency: Currency
+//!   recipient_id: String
+//!   memo: String?
+//!   labels: [String]
+//! }
+//! ```
+//!
+//! Fields have the built-in types `String`, `Int`, `Float`, `Bool`, `Uuid`, `Timestamp` and
+//! `Json`, or an enum declared in the schema. `T?` is optional and `[T]` is a list. Domain IDs
+//! must be a `String`, `String?` or `Uuid`.
+//!
+//! [`parse`] returns every error in the source with its location, and [`diff`] computes the
+//! changes between two versions, flagging those incompatible with the stored events.
+//! [`build::generate`] generates the Rust types of a schema from a build script.
+
+pub mod ast;
+pub mod build;
+pub mod diagnostic;
+pub mod diff;
+mod parser;
+
+pub use ast::{EnumDef, EventDef, Field, Schema, Type};
+pub use diagnostic::{Diagnostic, Diagnostics, Span};
+pub use diff::{BreakingChange, ChangeKind, SchemaChanges, diff};
+pub use parser::parse;
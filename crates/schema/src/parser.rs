@@ -0,0 +1,592 @@
+//! Lexer, parser and validator for ESDL.
+
+use std::collections::HashSet;
+
+use crate::{
+    ast::{EnumDef, EventDef, Field, Schema, Type},
+    diagnostic::{Diagnostic, Diagnostics, Span},
+};
+
+/// Maximum nesting of list types, which are parsed recursively.
+const MAX_TYPE_DEPTH: usize = 32;
+
+/// Names which cannot be Rust identifiers, even as raw identifiers.
+const RESERVED_NAMES: &[&str] = &["_", "crate", "self", "Self", "super"];
+
+/// Parses and validates an ESDL schema, returning every error found.
+pub fn parse(source: &str) -> Result<Schema, Diagnostics> {
+    let (tokens, mut diagnostics) = lex(source);
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        diagnostics: Vec::new(),
+    };
+    let schema = parser.schema();
+    diagnostics.extend(parser.diagnostics);
+    if diagnostics.is_empty() {
+        validate(&schema, &mut diagnostics);
+    }
+
+    if diagnostics.is_empty() {
+        Ok(schema)
+    } else {
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        Err(Diagnostics(diagnostics))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Ident(String),
+    At,
+    Colon,
+    Comma,
+    Question,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Eof,
+}
+
+impl TokenKind {
+    fn describe(&self) -> String {
+        match self {
+            TokenKind::Ident(ident) => format!("`{ident}`"),
+            TokenKind::At => "`@`".to_string(),
+            TokenKind::Colon => "`:`".to_string(),
+            TokenKind::Comma => "`,`".to_string(),
+            TokenKind::Question => "`?`".to_string(),
+            TokenKind::LBrace => "`{`".to_string(),
+            TokenKind::RBrace => "`}`".to_string(),
+            TokenKind::LBracket => "`[`".to_string(),
+            TokenKind::RBracket => "`]`".to_string(),
+            TokenKind::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+fn lex(source: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    while let Some((start, c)) = chars.next() {
+        let (start_line, start_column) = (line, column);
+        let span = move |end: usize| Span {
+            start,
+            end,
+            line: start_line,
+            column: start_column,
+        };
+        let kind = match c {
+            '\n' => {
+                line += 1;
+                column = 1;
+                continue;
+            }
+            c if c.is_whitespace() => None,
+            '/' if chars.peek().is_some_and(|(_, next)| *next == '/') => {
+                while chars.next_if(|(_, next)| *next != '\n').is_some() {}
+                continue;
+            }
+            '@' => Some(TokenKind::At),
+            ':' => Some(TokenKind::Colon),
+            ',' => Some(TokenKind::Comma),
+            '?' => Some(TokenKind::Question),
+            '{' => Some(TokenKind::LBrace),
+            '}' => Some(TokenKind::RBrace),
+            '[' => Some(TokenKind::LBracket),
+            ']' => Some(TokenKind::RBracket),
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    end = i + c.len_utf8();
+                }
+                let ident = &source[start..end];
+                tokens.push(Token {
+                    kind: TokenKind::Ident(ident.to_string()),
+                    span: span(end),
+                });
+                column += ident.chars().count();
+                continue;
+            }
+            c => {
+                diagnostics.push(Diagnostic::new(
+                    "unexpected_character",
+                    format!("unexpected character `{c}`"),
+                    span(start + c.len_utf8()),
+                ));
+                None
+            }
+        };
+        if let Some(kind) = kind {
+            tokens.push(Token {
+                kind,
+                span: span(start + c.len_utf8()),
+            });
+        }
+        column += 1;
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        span: Span {
+            start: source.len(),
+            end: source.len(),
+            line,
+            column,
+        },
+    });
+    (tokens, diagnostics)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Marks a syntax error which has been reported, and from which the parser must recover.
+struct Recover;
+
+impl Parser {
+    fn schema(&mut self) -> Schema {
+        let mut schema = Schema::default();
+        loop {
+            let token = self.peek().clone();
+            let result = match &token.kind {
+                TokenKind::Eof => break,
+                TokenKind::Ident(keyword) if keyword == "event" => {
+                    self.event().map(|event| schema.events.push(event))
+                }
+                TokenKind::Ident(keyword) if keyword == "enum" => {
+                    self.enum_def().map(|enum_def| schema.enums.push(enum_def))
+                }
+                _ => Err(self.error(&token, "`event` or `enum`")),
+            };
+            if result.is_err() {
+                self.recover_item();
+            }
+        }
+        schema
+    }
+
+    fn event(&mut self) -> Result<EventDef, Recover> {
+        self.bump();
+        let (name, name_span) = self.ident("an event name")?;
+        self.expect(TokenKind::LBrace)?;
+
+        let mut fields = Vec::new();
+        while !self.eat(&TokenKind::RBrace) {
+            if self.at_item() {
+                let token = self.peek().clone();
+                self.error(&token, "`}`");
+                break;
+            }
+            match self.field() {
+                Ok(field) => fields.push(field),
+                Err(Recover) => {
+                    if self.recover_field() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(EventDef {
+            name,
+            name_span,
+            fields,
+        })
+    }
+
+    fn field(&mut self) -> Result<Field, Recover> {
+        let domain_id = self.eat(&TokenKind::At);
+        let (name, name_span) = self.ident("a field name")?;
+        self.expect(TokenKind::Colon)?;
+        let (ty, ty_span) = self.ty(0)?;
+        self.eat(&TokenKind::Comma);
+
+        Ok(Field {
+            name,
+            name_span,
+            ty,
+            ty_span,
+            domain_id,
+        })
+    }
+
+    fn ty(&mut self, depth: usize) -> Result<(Type, Span), Recover> {
+        let start = self.peek().span;
+        if depth > MAX_TYPE_DEPTH {
+            self.diagnostics.push(Diagnostic::new(
+                "type_too_deep",
+                format!("types cannot be nested more than {MAX_TYPE_DEPTH} lists deep"),
+                start,
+            ));
+            return Err(Recover);
+        }
+        let ty = if self.eat(&TokenKind::LBracket) {
+            let (ty, _) = self.ty(depth + 1)?;
+            self.expect(TokenKind::RBracket)?;
+            Type::List(Box::new(ty))
+        } else {
+            let (name, _) = self.ident("a type")?;
+            Type::builtin(&name).unwrap_or(Type::Enum(name))
+        };
+        let ty = if self.eat(&TokenKind::Question) {
+            Type::Optional(Box::new(ty))
+        } else {
+            ty
+        };
+
+        let end = self.tokens[self.pos - 1].span.end;
+        Ok((ty, Span { end, ..start }))
+    }
+
+    fn enum_def(&mut self) -> Result<EnumDef, Recover> {
+        self.bump();
+        let (name, name_span) = self.ident("an enum name")?;
+        self.expect(TokenKind::LBrace)?;
+
+        let mut variants = Vec::new();
+        while !self.eat(&TokenKind::RBrace) {
+            let (variant, _) = self.ident("a variant")?;
+            variants.push(variant);
+            self.eat(&TokenKind::Comma);
+        }
+
+        Ok(EnumDef {
+            name,
+            name_span,
+            variants,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        let matches = &self.peek().kind == kind;
+        if matches {
+            self.bump();
+        }
+        matches
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), Recover> {
+        if self.eat(&kind) {
+            return Ok(());
+        }
+        let token = self.peek().clone();
+        Err(self.error(&token, &kind.describe()))
+    }
+
+    fn ident(&mut self, expected: &str) -> Result<(String, Span), Recover> {
+        let token = self.peek().clone();
+        match token.kind {
+            TokenKind::Ident(ident) => {
+                self.bump();
+                Ok((ident, token.span))
+            }
+            _ => Err(self.error(&token, expected)),
+        }
+    }
+
+    fn error(&mut self, found: &Token, expected: &str) -> Recover {
+        self.diagnostics.push(Diagnostic::new(
+            "unexpected_token",
+            format!("expected {expected}, found {}", found.kind.describe()),
+            found.span,
+        ));
+        Recover
+    }
+
+    /// Skips to the next `event` or `enum` after a syntax error outside of a field.
+    fn recover_item(&mut self) {
+        self.bump();
+        while !self.at_item() {
+            self.bump();
+        }
+    }
+
+    /// Skips to the next field after a syntax error, returning whether the event ended.
+    fn recover_field(&mut self) -> bool {
+        loop {
+            match &self.peek().kind {
+                TokenKind::Eof => return true,
+                TokenKind::RBrace => {
+                    self.bump();
+                    return true;
+                }
+                TokenKind::At => return false,
+                TokenKind::Ident(_) if self.at_item() => return true,
+                TokenKind::Ident(_)
+                    if self.tokens.get(self.pos + 1).map(|token| &token.kind)
+                        == Some(&TokenKind::Colon) =>
+                {
+                    return false;
+                }
+                _ => {
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    /// Returns whether the next tokens start an `event` or `enum` definition.
+    fn at_item(&self) -> bool {
+        let keyword = matches!(
+            &self.peek().kind,
+            TokenKind::Ident(keyword) if keyword == "event" || keyword == "enum"
+        );
+        let named = matches!(
+            self.tokens.get(self.pos + 1).map(|token| &token.kind),
+            Some(TokenKind::Ident(_))
+        );
+        (keyword && named) || self.peek().kind == TokenKind::Eof
+    }
+}
+
+/// Checks names are unique, types are declared and domain IDs can be used as tags.
+fn validate(schema: &Schema, diagnostics: &mut Vec<Diagnostic>) {
+    let mut names = HashSet::new();
+    for enum_def in &schema.enums {
+        check_name(&enum_def.name, enum_def.name_span, diagnostics);
+        if !names.insert(enum_def.name.as_str()) {
+            diagnostics.push(Diagnostic::new(
+                "duplicate_type",
+                format!("`{}` is defined more than once", enum_def.name),
+                enum_def.name_span,
+            ));
+        }
+        if enum_def.variants.is_empty() {
+            diagnostics.push(Diagnostic::new(
+                "empty_enum",
+                format!("enum `{}` has no variants", enum_def.name),
+                enum_def.name_span,
+            ));
+        }
+        let mut variants = HashSet::new();
+        for variant in &enum_def.variants {
+            check_name(variant, enum_def.name_span, diagnostics);
+            if !variants.insert(variant) {
+                diagnostics.push(Diagnostic::new(
+                    "duplicate_variant",
+                    format!("enum `{}` has more than one `{variant}`", enum_def.name),
+                    enum_def.name_span,
+                ));
+            }
+        }
+    }
+
+    for event in &schema.events {
+        check_name(&event.name, event.name_span, diagnostics);
+        if !names.insert(event.name.as_str()) {
+            diagnostics.push(Diagnostic::new(
+                "duplicate_type",
+                format!("`{}` is defined more than once", event.name),
+                event.name_span,
+            ));
+        }
+
+        let mut fields = HashSet::new();
+        for field in &event.fields {
+            check_name(&field.name, field.name_span, diagnostics);
+            if !fields.insert(field.name.as_str()) {
+                diagnostics.push(Diagnostic::new(
+                    "duplicate_field",
+                    format!("`{}` has more than one `{}` field", event.name, field.name),
+                    field.name_span,
+                ));
+            }
+            if let Some(name) = field.ty.enum_name()
+                && schema.enum_def(name).is_none()
+            {
+                diagnostics.push(Diagnostic::new(
+                    "unknown_type",
+                    format!("unknown type `{name}`"),
+                    field.ty_span,
+                ));
+            }
+            let tag_type = match &field.ty {
+                Type::String | Type::Uuid => true,
+                Type::Optional(ty) => **ty == Type::String,
+                _ => false,
+            };
+            if field.domain_id && !tag_type {
+                diagnostics.push(Diagnostic::new(
+                    "invalid_domain_id",
+                    format!(
+                        "domain ID `{}` must be a `String`, `String?` or `Uuid`, found `{}`",
+                        field.name, field.ty
+                    ),
+                    field.ty_span,
+                ));
+            }
+        }
+    }
+}
+
+fn check_name(name: &str, span: Span, diagnostics: &mut Vec<Diagnostic>) {
+    if RESERVED_NAMES.contains(&name) {
+        diagnostics.push(Diagnostic::new(
+            "reserved_name",
+            format!("`{name}` cannot be used as a name"),
+            span,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BANK: &str = r#"
+// Events of the bank account example.
+event OpenedAccount {
+  @account_id: String
+  initial_balance: Float
+}
+
+event SentFunds {
+  @account_id: String, amount: Float, recipient_id: String
+  memo: String?
+}
+
+enum Status { Todo, Doing, Done }
+
+event TaskCreated {
+  @task_id: Uuid
+  status: Status
+  labels: [String]
+}
+"#;
+
+    #[test]
+    fn parses_events_and_enums() {
+        let schema = parse(BANK).unwrap();
+        assert_eq!(schema.events.len(), 3);
+        assert_eq!(schema.enums[0].variants, ["Todo", "Doing", "Done"]);
+
+        let opened = schema.event("OpenedAccount").unwrap();
+        assert_eq!(opened.name_span.line, 3);
+        assert_eq!(opened.name_span.column, 7);
+        assert_eq!(opened.domain_ids().collect::<Vec<_>>(), ["account_id"]);
+        assert_eq!(opened.field("initial_balance").unwrap().ty, Type::Float);
+
+        let sent = schema.event("SentFunds").unwrap();
+        let types: Vec<_> = sent
+            .fields
+            .iter()
+            .map(|field| field.ty.to_string())
+            .collect();
+        assert_eq!(types, ["String", "Float", "String", "String?"]);
+
+        let task = schema.event("TaskCreated").unwrap();
+        assert_eq!(
+            task.field("status").unwrap().ty,
+            Type::Enum("Status".to_string())
+        );
+        assert_eq!(
+            task.field("labels").unwrap().ty,
+            Type::List(Box::new(Type::String))
+        );
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let err =
+            parse("event A {\n  a String\n  b: Int\n}\nevent { }\nevent C { c: $ }").unwrap_err();
+        let errors: Vec<_> = err
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.code,
+                    diagnostic.span.line,
+                    diagnostic.span.column,
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("unexpected_token", 2, 5),
+                ("unexpected_token", 5, 7),
+                ("unexpected_character", 6, 14),
+                ("unexpected_token", 6, 16),
+            ]
+        );
+        assert_eq!(
+            err.0[0].to_string(),
+            "2:5: expected `:`, found `String` [unexpected_token]"
+        );
+    }
+
+    #[test]
+    fn validates_types_and_names() {
+        let err = parse(
+            "event A { @id: Float, x: Missing }\nevent A { b: Int, b: Int }\nenum E { X, X }",
+        )
+        .unwrap_err();
+        let codes: Vec<_> = err.iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(
+            codes,
+            [
+                "invalid_domain_id",
+                "unknown_type",
+                "duplicate_type",
+                "duplicate_field",
+                "duplicate_variant",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_names() {
+        let err = parse("enum Self { _ }\nevent crate { self: Int }").unwrap_err();
+        let codes: Vec<_> = err.iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(codes, ["reserved_name"; 4]);
+
+        assert!(parse("enum Kind { Type }\nevent type { match: Kind }").is_ok());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_types() {
+        let depth = 100_000;
+        let source = format!(
+            "event A {{ a: {}String{} }}",
+            "[".repeat(depth),
+            "]".repeat(depth)
+        );
+        let err = parse(&source).unwrap_err();
+        let codes: Vec<_> = err.iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(codes, ["type_too_deep"]);
+
+        let nested = format!(
+            "event A {{ a: {}String{} }}",
+            "[".repeat(MAX_TYPE_DEPTH),
+            "]".repeat(MAX_TYPE_DEPTH)
+        );
+        assert!(parse(&nested).is_ok());
+    }
+}
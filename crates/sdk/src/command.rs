@@ -3,16 +3,17 @@ use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use futures_util::FutureExt;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tracing::warn;
 use umadb_dcb::{
-    DCBAppendCondition, DCBEvent, DCBEventStoreAsync, DCBEventStoreSync, DCBQuery, DCBQueryItem,
-    DCBSequencedEvent,
+    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreAsync, DCBEventStoreSync, DCBQuery,
+    DCBQueryItem, DCBSequencedEvent,
 };
 use uuid::Uuid;
 
 use crate::{
     domain_id::DomainIdBindings,
-    emit::Emit,
+    emit::{Emit, EmittedEvent},
     error::{ExecuteError, SerializationError},
     event::{EventEnvelope, EventSet, StoredEventData},
 };
@@ -38,6 +39,9 @@ use crate::{
 /// This generates a query for events where `account_id` is either
 /// `source_account` or `dest_account`.
 pub trait CommandInput {
+    /// Input fields bound to domain IDs, as `(field, domain_id)` pairs.
+    const DOMAIN_ID_BINDINGS: &'static [(&'static str, &'static str)] = &[];
+
     /// Returns the domain ID bindings for this input.
     ///
     /// Maps domain ID field names to the values to query for.
@@ -78,7 +82,7 @@ pub trait CommandInput {
 /// impl Command for Withdraw {
 ///     type Query = Query;
 ///     type Input = Input;
-///     type Command = CommandError;
+///     type Error = CommandError;
 ///
 ///     fn apply(&mut self, event: Query) {
 ///         match event {
@@ -100,7 +104,21 @@ pub trait CommandInput {
 ///     }
 /// }
 /// ```
-pub trait Command: Default + Send {
+///
+/// Commands which return a value to the caller, eg. a generated ID, implement `Command<Output>`
+/// and set it with [`Emit::output`]:
+///
+/// ```ignore
+/// impl Command<Uuid> for CreateTask {
+///     // ...
+///
+///     fn handle(&self, input: &Input) -> Result<Emit<Uuid>, CommandError> {
+///         let task_id = Uuid::new_v4();
+///         Ok(Emit::new().event(CreatedTask { task_id, ... }).output(task_id))
+///     }
+/// }
+/// ```
+pub trait Command<Output: Send = ()>: Default + Send {
     /// The set of event types this handler reads.
     /// Defines the event type filter for the query.
     type Query: EventSet;
@@ -112,6 +130,12 @@ pub trait Command: Default + Send {
     /// The error type returned when handling the command.
     type Error;
 
+    /// Event types emitted by `handle`.
+    ///
+    /// Declared by WASM handlers so their emitted events can be checked against the event schema
+    /// when uploaded. Empty when undeclared.
+    const EMITTED_EVENT_TYPES: &'static [&'static str] = &[];
+
     /// Validate the input before querying anything.
     #[allow(unused_variables)]
     fn validate(input: &Self::Input) -> Result<(), Self::Error> {
@@ -136,17 +160,17 @@ pub trait Command: Default + Send {
     ///
     /// Called after all historical events have been applied.
     /// Should validate the command against current state and either:
-    /// - Return new events to persist
+    /// - Return new events to persist, and optionally an output with [`Emit::output`]
     /// - Return an error rejecting the command
-    fn handle(&self, input: &Self::Input) -> Result<Emit, Self::Error>;
+    fn handle(&self, input: &Self::Input) -> Result<Emit<Output>, Self::Error>;
 
     /// Runs async code before committing the events to the event store.
     #[allow(unused_variables)]
     fn before_commit(
         &self,
         input: &Self::Input,
-        events: Emit,
-    ) -> impl Future<Output = Result<Emit, Self::Error>> + Send {
+        events: Emit<Output>,
+    ) -> impl Future<Output = Result<Emit<Output>, Self::Error>> + Send {
         async { Ok(events) }
     }
 
@@ -154,7 +178,7 @@ pub trait Command: Default + Send {
     fn execute(
         store: &impl DCBEventStoreAsync,
         input: Self::Input,
-    ) -> impl Future<Output = Result<ExecuteResult, ExecuteError<Self::Error>>> + Send {
+    ) -> impl Future<Output = Result<ExecuteResult<Output>, ExecuteError<Self::Error>>> + Send {
         Self::execute_with(store, input, CommandContext::new())
     }
 
@@ -162,18 +186,24 @@ pub trait Command: Default + Send {
     fn execute_with(
         store: &impl DCBEventStoreAsync,
         input: Self::Input,
-        context: CommandContext,
-    ) -> impl Future<Output = Result<ExecuteResult, ExecuteError<Self::Error>>> + Send {
+        mut context: CommandContext,
+    ) -> impl Future<Output = Result<ExecuteResult<Output>, ExecuteError<Self::Error>>> + Send {
         async move {
+            context.command.get_or_insert_with(command_name::<Self>);
             Self::validate(&input).map_err(ExecuteError::Validation)?;
             let mut handler = Self::default();
-            let query = handler.query(&input);
+            let query = context.idempotent_query(handler.query(&input));
             let (events, head) = store
                 .read(Some(query.clone()), Some(0), false, None, false)
                 .await?
                 .collect_with_head()
                 .await?;
 
+            if let Some(result) = replay_idempotent(&context, &events) {
+                return Ok(result);
+            }
+            check_expected_position(&context, &events)?;
+
             for DCBSequencedEvent { position: _, event } in events {
                 let StoredEventData {
                     data, timestamp, ..
@@ -193,17 +223,11 @@ pub trait Command: Default + Send {
                 .before_commit(&input, emit)
                 .await
                 .map_err(ExecuteError::Command)?;
-            let append_events: Vec<_> = emit
-                .into_events()
-                .into_iter()
-                .map(|event| event.into_dcb_event(context.into_event_envelope(timestamp)))
-                .collect();
+            let (events, output) = emit.into_parts();
+            let append_events = context.to_dcb_events(events, timestamp);
 
             if append_events.is_empty() {
-                return Ok(ExecuteResult {
-                    position: head,
-                    events: Vec::new(),
-                });
+                return Ok(ExecuteResult::new(head, Vec::new(), output));
             }
 
             let new_position = store
@@ -211,15 +235,17 @@ pub trait Command: Default + Send {
                     append_events.clone(),
                     Some(DCBAppendCondition {
                         fail_if_events_match: query,
-                        after: head,
+                        after: context.append_after(head),
                     }),
                 )
-                .await?;
+                .await
+                .map_err(|err| context.stale_position_error(err))?;
 
-            Ok(ExecuteResult {
-                position: Some(new_position),
-                events: append_events,
-            })
+            Ok(ExecuteResult::new(
+                Some(new_position),
+                append_events,
+                output,
+            ))
         }
     }
 
@@ -227,7 +253,7 @@ pub trait Command: Default + Send {
     fn execute_blocking(
         store: &impl DCBEventStoreSync,
         input: Self::Input,
-    ) -> Result<ExecuteResult, ExecuteError<Self::Error>> {
+    ) -> Result<ExecuteResult<Output>, ExecuteError<Self::Error>> {
         Self::execute_blocking_with(store, input, CommandContext::new())
     }
 
@@ -235,15 +261,21 @@ pub trait Command: Default + Send {
     fn execute_blocking_with(
         store: &impl DCBEventStoreSync,
         input: Self::Input,
-        context: CommandContext,
-    ) -> Result<ExecuteResult, ExecuteError<Self::Error>> {
+        mut context: CommandContext,
+    ) -> Result<ExecuteResult<Output>, ExecuteError<Self::Error>> {
+        context.command.get_or_insert_with(command_name::<Self>);
         Self::validate(&input).map_err(ExecuteError::Validation)?;
         let mut handler = Self::default();
-        let query = handler.query(&input);
+        let query = context.idempotent_query(handler.query(&input));
         let (events, head) = store
             .read(Some(query.clone()), Some(0), false, None, false)?
             .collect_with_head()?;
 
+        if let Some(result) = replay_idempotent(&context, &events) {
+            return Ok(result);
+        }
+        check_expected_position(&context, &events)?;
+
         for DCBSequencedEvent { position: _, event } in events {
             let StoredEventData {
                 data, timestamp, ..
@@ -263,39 +295,94 @@ pub trait Command: Default + Send {
             .now_or_never()
             .expect("async before_commit is not supportd when executing as blocking")
             .map_err(ExecuteError::Command)?;
-        let append_events: Vec<_> = emit
-            .into_events()
-            .into_iter()
-            .map(|event| event.into_dcb_event(context.into_event_envelope(timestamp)))
-            .collect();
+        let (events, output) = emit.into_parts();
+        let append_events = context.to_dcb_events(events, timestamp);
 
         if append_events.is_empty() {
-            return Ok(ExecuteResult {
-                position: head,
-                events: Vec::new(),
-            });
+            return Ok(ExecuteResult::new(head, Vec::new(), output));
         }
 
-        let new_position = store.append(
-            append_events.clone(),
-            Some(DCBAppendCondition {
-                fail_if_events_match: query,
-                after: head,
-            }),
-        )?;
+        let new_position = store
+            .append(
+                append_events.clone(),
+                Some(DCBAppendCondition {
+                    fail_if_events_match: query,
+                    after: context.append_after(head),
+                }),
+            )
+            .map_err(|err| context.stale_position_error(err))?;
+
+        Ok(ExecuteResult::new(
+            Some(new_position),
+            append_events,
+            output,
+        ))
+    }
+}
 
-        Ok(ExecuteResult {
-            position: Some(new_position),
-            events: append_events,
-        })
+/// Name of a handler type without its module path, used as the default command name.
+fn command_name<C>() -> String {
+    let name = std::any::type_name::<C>();
+    let path = name.split('<').next().unwrap_or(name);
+    path.rsplit("::").next().unwrap_or(path).to_string()
+}
+
+/// Converts a type or variant name to snake case, keeping acronyms together, eg. `HTTPRequest`
+/// to `http_request`.
+///
+/// Used for the default reasons of a derived `CommandError`, and the default command names of
+/// the client.
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev != '_' && (!prev.is_uppercase() || next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
     }
+    snake
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Tag category used to record idempotency keys on emitted events.
+pub const IDEMPOTENCY_KEY_TAG: &str = "idempotency_key";
+
+/// Metadata of a command execution, recorded on the emitted events.
+///
+/// Not `Copy`, as it owns the idempotency key and handler version strings. Construct it with
+/// [`CommandContext::new`], [`CommandContext::with_correlation_id`] or
+/// [`CommandContext::triggered_by_event`], as more fields may be added.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct CommandContext {
     pub command_id: Uuid,           // This execution's ID
     pub correlation_id: Uuid,       // Original request ID (flows through everything)
     pub triggered_by: Option<Uuid>, // Event ID that triggered this command (for sagas)
+    /// Client-supplied idempotency key, recorded as a tag on the emitted events.
+    ///
+    /// When set, executing a command whose key has already been recorded returns the
+    /// original events instead of running the handler again, and a concurrent duplicate
+    /// is rejected by the append condition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Position the caller last observed, for optimistic concurrency.
+    ///
+    /// When set, the command fails with [`ExecuteError::StalePosition`] if any event matching
+    /// its query was appended after this position, instead of the head read by the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_position: Option<u64>,
+    /// Version of the handler executing the command, recorded on the emitted events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler_version: Option<String>,
+    /// Name of the command being executed, scoping the idempotency key.
+    ///
+    /// Defaults to the name of the handler type when executed with [`Command::execute_with`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
 }
 
 impl CommandContext {
@@ -307,6 +394,10 @@ impl CommandContext {
             command_id: id,
             correlation_id: id,
             triggered_by: None,
+            idempotency_key: None,
+            expected_position: None,
+            handler_version: None,
+            command: None,
         }
     }
 
@@ -316,6 +407,10 @@ impl CommandContext {
             command_id: Uuid::new_v4(),
             correlation_id,
             triggered_by: None,
+            idempotency_key: None,
+            expected_position: None,
+            handler_version: None,
+            command: None,
         }
     }
 
@@ -325,18 +420,148 @@ impl CommandContext {
             command_id: Uuid::new_v4(),
             correlation_id,
             triggered_by: Some(event_id),
+            idempotency_key: None,
+            expected_position: None,
+            handler_version: None,
+            command: None,
         }
     }
 
+    /// Sets the idempotency key recorded on the emitted events.
+    pub fn idempotency_key(mut self, idempotency_key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(idempotency_key.into());
+        self
+    }
+
+    /// Sets the position the caller last observed, rejecting the command if it is stale.
+    pub fn expected_position(mut self, expected_position: u64) -> Self {
+        self.expected_position = Some(expected_position);
+        self
+    }
+
+    /// Sets the version of the handler recorded on the emitted events.
+    pub fn handler_version(mut self, handler_version: impl Into<String>) -> Self {
+        self.handler_version = Some(handler_version.into());
+        self
+    }
+
+    /// Sets the name of the command, so the same idempotency key used by another command does
+    /// not replay its events.
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// Returns the tag recording the idempotency key, if one is set.
+    ///
+    /// The tag is `idempotency_key:{command}:{key}`, or `idempotency_key:{key}` without a command.
+    pub fn idempotency_tag(&self) -> Option<String> {
+        let key = self.idempotency_key.as_ref()?;
+        Some(match &self.command {
+            Some(command) => format!("{IDEMPOTENCY_KEY_TAG}:{command}:{key}"),
+            None => format!("{IDEMPOTENCY_KEY_TAG}:{key}"),
+        })
+    }
+
     /// Convert into an `EventEnvelope` with a timestamp.
     pub fn into_event_envelope(self, timestamp: DateTime<Utc>) -> EventEnvelope {
+        self.event_envelope(timestamp)
+    }
+
+    /// Builds an `EventEnvelope` with a timestamp.
+    pub fn event_envelope(&self, timestamp: DateTime<Utc>) -> EventEnvelope {
         EventEnvelope {
             timestamp,
             correlation_id: self.correlation_id,
             causation_id: self.command_id,
             triggered_by: self.triggered_by,
+            handler_version: self.handler_version.clone(),
+        }
+    }
+
+    /// Converts emitted events into DCB events, tagging them with the idempotency key if set.
+    pub fn to_dcb_events(
+        &self,
+        events: Vec<EmittedEvent>,
+        timestamp: DateTime<Utc>,
+    ) -> Vec<DCBEvent> {
+        let envelope = self.event_envelope(timestamp);
+        let idempotency_tag = self.idempotency_tag();
+        events
+            .into_iter()
+            .map(|event| {
+                let mut event = event.into_dcb_event(&envelope);
+                if let Some(tag) = &idempotency_tag {
+                    event.tags.push(tag.clone());
+                }
+                event
+            })
+            .collect()
+    }
+
+    /// Position after which events matching the query fail the append.
+    ///
+    /// Uses the expected position when set, unless it is ahead of the head read by the command.
+    pub fn append_after(&self, head: Option<u64>) -> Option<u64> {
+        match (self.expected_position, head) {
+            (Some(expected), Some(head)) => Some(expected.min(head)),
+            (_, head) => head,
         }
     }
+
+    /// Reports a failed append condition as a stale position when an expected position is set.
+    pub fn stale_position_error<E>(&self, err: DCBError) -> ExecuteError<E> {
+        match (self.expected_position, err) {
+            (Some(expected), DCBError::IntegrityError(_)) => {
+                ExecuteError::StalePosition { expected }
+            }
+            (_, err) => err.into(),
+        }
+    }
+
+    /// Extends a command query to also match events recorded with the idempotency key.
+    pub fn idempotent_query(&self, query: DCBQuery) -> DCBQuery {
+        match self.idempotency_tag() {
+            Some(tag) => query.item(DCBQueryItem::new().tags([tag])),
+            None => query,
+        }
+    }
+}
+
+/// Returns the previously recorded events when the idempotency key has already been used.
+///
+/// Outputs are not recorded with the events, so replays have no output.
+pub fn replay_idempotent<O>(
+    context: &CommandContext,
+    events: &[DCBSequencedEvent],
+) -> Option<ExecuteResult<O>> {
+    let tag = context.idempotency_tag()?;
+    let replayed: Vec<_> = events
+        .iter()
+        .filter(|event| event.event.tags.contains(&tag))
+        .collect();
+    let position = replayed.last()?.position;
+
+    Some(ExecuteResult::replay(
+        Some(position),
+        replayed
+            .into_iter()
+            .map(|event| event.event.clone())
+            .collect(),
+    ))
+}
+
+/// Fails when events matching the query were appended after the expected position.
+pub fn check_expected_position<E>(
+    context: &CommandContext,
+    events: &[DCBSequencedEvent],
+) -> Result<(), ExecuteError<E>> {
+    match context.expected_position {
+        Some(expected) if events.iter().any(|event| event.position > expected) => {
+            Err(ExecuteError::StalePosition { expected })
+        }
+        _ => Ok(()),
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -345,9 +570,51 @@ pub struct EventMeta {
 }
 
 #[derive(Clone, Debug)]
-pub struct ExecuteResult {
+#[non_exhaustive]
+pub struct ExecuteResult<O = ()> {
     pub position: Option<u64>,
     pub events: Vec<DCBEvent>,
+    /// The output returned by the handler with [`Emit::output`].
+    ///
+    /// Outputs are not recorded with the events, so replays from the event store have none.
+    pub output: Option<O>,
+    /// Whether the events were previously recorded under the same idempotency key.
+    pub replayed: bool,
+}
+
+impl<O> ExecuteResult<O> {
+    /// Result of running the handler.
+    pub fn new(position: Option<u64>, events: Vec<DCBEvent>, output: Option<O>) -> Self {
+        ExecuteResult {
+            position,
+            events,
+            output,
+            replayed: false,
+        }
+    }
+
+    /// Result replaying the events previously recorded under the same idempotency key.
+    pub fn replay(position: Option<u64>, events: Vec<DCBEvent>) -> Self {
+        ExecuteResult {
+            position,
+            events,
+            output: None,
+            replayed: true,
+        }
+    }
+
+    /// Serializes the output, eg. to return it in a response.
+    pub fn serialize_output(self) -> Result<ExecuteResult<Value>, SerializationError>
+    where
+        O: Serialize,
+    {
+        Ok(ExecuteResult {
+            position: self.position,
+            events: self.events,
+            output: self.output.map(serde_json::to_value).transpose()?,
+            replayed: self.replayed,
+        })
+    }
 }
 
 pub fn build_query_items<Q: EventSet>(bindings: &DomainIdBindings) -> Vec<DCBQueryItem> {
@@ -427,7 +694,12 @@ fn cartesian_product(bindings: &DomainIdBindings) -> Vec<Vec<String>> {
 mod tests {
     use serde_json::Value;
 
-    use crate::error::SerializationError;
+    use crate::{
+        domain_id::{DomainIdValue, DomainIdValues},
+        error::SerializationError,
+        event::Event,
+        memory::MemoryEventStore,
+    };
 
     use super::*;
 
@@ -457,6 +729,29 @@ mod tests {
         v
     }
 
+    fn sequenced(position: u64, tags: &[&str]) -> DCBSequencedEvent {
+        DCBSequencedEvent {
+            position,
+            event: DCBEvent::new()
+                .event_type("TestEvent")
+                .tags(tags.iter().copied()),
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestEvent {
+        user_id: String,
+    }
+
+    impl Event for TestEvent {
+        const EVENT_TYPE: &'static str = "TestEvent";
+        const DOMAIN_ID_FIELDS: &'static [&'static str] = &["user_id"];
+
+        fn domain_ids(&self) -> DomainIdValues {
+            HashMap::from([("user_id", DomainIdValue::from(self.user_id.as_str()))])
+        }
+    }
+
     // =========================================================================
     // Mock EventSet implementations for testing
     // =========================================================================
@@ -522,6 +817,173 @@ mod tests {
         }
     }
 
+    // =========================================================================
+    // Tests: Idempotency keys
+    // =========================================================================
+
+    #[test]
+    fn idempotency_key_tags_emitted_events() {
+        let context = CommandContext::new().idempotency_key("abc");
+        let emit = Emit::new().event(TestEvent {
+            user_id: "alice".to_string(),
+        });
+
+        let events = context.to_dcb_events(emit.into_events(), Utc::now());
+
+        assert_eq!(events.len(), 1);
+        assert!(events[0].tags.contains(&"user_id:alice".to_string()));
+        assert!(events[0].tags.contains(&"idempotency_key:abc".to_string()));
+    }
+
+    #[test]
+    fn idempotent_query_matches_key_tag() {
+        let query = CommandContext::new()
+            .idempotency_key("abc")
+            .idempotent_query(DCBQuery::new());
+
+        assert_eq!(query.items.len(), 1);
+        assert_eq!(query.items[0].tags, vec!["idempotency_key:abc".to_string()]);
+        assert!(query.items[0].types.is_empty());
+    }
+
+    #[test]
+    fn replays_events_recorded_with_idempotency_key() {
+        let context = CommandContext::new().idempotency_key("abc");
+        let events = vec![
+            sequenced(1, &["user_id:alice"]),
+            sequenced(2, &["user_id:alice", "idempotency_key:abc"]),
+            sequenced(3, &["user_id:alice", "idempotency_key:abc"]),
+        ];
+
+        let result = replay_idempotent::<()>(&context, &events).expect("should replay");
+
+        assert!(result.replayed);
+        assert_eq!(result.position, Some(3));
+        assert_eq!(result.events.len(), 2);
+    }
+
+    #[test]
+    fn no_replay_without_recorded_key() {
+        let events = vec![sequenced(1, &["user_id:alice", "idempotency_key:other"])];
+
+        assert!(replay_idempotent::<()>(&CommandContext::new(), &events).is_none());
+        assert!(
+            replay_idempotent::<()>(&CommandContext::new().idempotency_key("abc"), &events)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn idempotency_keys_are_scoped_by_command() {
+        let context = CommandContext::new()
+            .idempotency_key("abc")
+            .command("Deposit");
+        let events = vec![
+            sequenced(1, &["user_id:alice", "idempotency_key:Withdraw:abc"]),
+            sequenced(2, &["user_id:alice", "idempotency_key:abc"]),
+        ];
+
+        assert_eq!(
+            context.idempotency_tag().as_deref(),
+            Some("idempotency_key:Deposit:abc")
+        );
+        assert!(replay_idempotent::<()>(&context, &events).is_none());
+
+        let events = vec![sequenced(3, &["idempotency_key:Deposit:abc"])];
+        let result = replay_idempotent::<()>(&context, &events).expect("should replay");
+        assert_eq!(result.position, Some(3));
+    }
+
+    struct RegisterUserInput;
+    impl CommandInput for RegisterUserInput {
+        fn domain_id_bindings(&self) -> DomainIdBindings {
+            DomainIdBindings::new()
+        }
+    }
+
+    #[derive(Default)]
+    struct RegisterUser;
+    impl Command<Uuid> for RegisterUser {
+        type Query = NoDomainsEvent;
+        type Input = RegisterUserInput;
+        type Error = ();
+
+        fn apply(&mut self, _: NoDomainsEvent, _: EventMeta) {}
+
+        fn handle(&self, _: &RegisterUserInput) -> Result<Emit<Uuid>, ()> {
+            let user_id = Uuid::new_v4();
+            Ok(Emit::new()
+                .event(TestEvent {
+                    user_id: user_id.to_string(),
+                })
+                .output(user_id))
+        }
+    }
+
+    #[tokio::test]
+    async fn outputs_are_returned_but_not_recorded() {
+        let store = MemoryEventStore::new();
+        let context = || CommandContext::new().idempotency_key("abc");
+
+        let result = RegisterUser::execute_with(&store, RegisterUserInput, context())
+            .await
+            .unwrap();
+        let user_id: Uuid = result.output.unwrap();
+        assert!(!result.replayed);
+        let data: Value = serde_json::from_slice(&store.events()[0].event.data).unwrap();
+        assert!(data.get("output").is_none());
+        assert_eq!(data["data"]["user_id"], user_id.to_string());
+
+        let replay = RegisterUser::execute_with(&store, RegisterUserInput, context())
+            .await
+            .unwrap();
+        assert!(replay.replayed);
+        assert_eq!(replay.output, None);
+        assert_eq!(store.events().len(), 1);
+    }
+
+    #[test]
+    fn snake_case_keeps_acronyms_together() {
+        assert_eq!(snake_case("InsufficientFunds"), "insufficient_funds");
+        assert_eq!(snake_case("HTTPRequest"), "http_request");
+        assert_eq!(snake_case("InvalidID"), "invalid_id");
+        assert_eq!(snake_case("Http2Error"), "http2_error");
+        assert_eq!(snake_case("Timeout"), "timeout");
+    }
+
+    #[test]
+    fn command_name_strips_module_path() {
+        struct Deposit<T>(T);
+
+        assert_eq!(command_name::<TestEvent>(), "TestEvent");
+        assert_eq!(command_name::<Deposit<TestEvent>>(), "Deposit");
+    }
+
+    #[test]
+    fn stale_when_events_appended_after_expected_position() {
+        let events = vec![sequenced(3, &["task_id:a"]), sequenced(5, &["task_id:a"])];
+
+        assert!(check_expected_position::<()>(&CommandContext::new(), &events).is_ok());
+        assert!(
+            check_expected_position::<()>(&CommandContext::new().expected_position(5), &events)
+                .is_ok()
+        );
+        assert!(matches!(
+            check_expected_position::<()>(&CommandContext::new().expected_position(4), &events),
+            Err(ExecuteError::StalePosition { expected: 4 })
+        ));
+    }
+
+    #[test]
+    fn appends_after_expected_position() {
+        let context = CommandContext::new().expected_position(4);
+
+        assert_eq!(CommandContext::new().append_after(Some(7)), Some(7));
+        assert_eq!(context.append_after(Some(7)), Some(4));
+        assert_eq!(context.append_after(Some(2)), Some(2));
+        assert_eq!(context.append_after(None), None);
+    }
+
     // =========================================================================
     // Tests: Basic cases
     // =========================================================================
@@ -8,7 +8,7 @@ use crate::{
     event::{Event, EventEnvelope, StoredEventData},
 };
 
-/// A collection of events to be emitted by a command.
+/// A collection of events to be emitted by a command, along with its output.
 ///
 /// Built using the builder pattern:
 ///
@@ -17,9 +17,17 @@ use crate::{
 ///     .event(SentFunds { ... })
 ///     .event(ReceivedFunds { ... }))
 /// ```
-#[derive(Debug, Default)]
-pub struct Emit {
+///
+/// Commands implementing `Command<Output>` return a value to the caller, eg. a generated ID,
+/// with [`Emit::output`]:
+///
+/// ```rust,ignore
+/// Ok(Emit::new().event(CreatedTask { task_id, ... }).output(task_id))
+/// ```
+#[derive(Debug)]
+pub struct Emit<O = ()> {
     events: Vec<EmittedEvent>,
+    output: Option<O>,
 }
 
 /// A serialized event ready for persistence.
@@ -36,7 +44,36 @@ pub struct EmittedEvent {
 impl Emit {
     /// Create a new empty emit collection.
     pub fn new() -> Self {
-        Self { events: Vec::new() }
+        Self::default()
+    }
+}
+
+impl<O> Default for Emit<O> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            output: None,
+        }
+    }
+}
+
+impl<O> Emit<O> {
+    /// Sets the output returned to the caller alongside the events.
+    pub fn output<T>(self, output: T) -> Emit<T> {
+        Emit {
+            events: self.events,
+            output: Some(output),
+        }
+    }
+
+    /// Returns the output, if set.
+    pub fn get_output(&self) -> Option<&O> {
+        self.output.as_ref()
+    }
+
+    /// Consume and return the collected events and output.
+    pub fn into_parts(self) -> (Vec<EmittedEvent>, Option<O>) {
+        (self.events, self.output)
     }
 
     /// Add an event to be emitted.
@@ -101,35 +138,40 @@ impl EmittedEvent {
         }
     }
 
-    pub fn into_dcb_event(self, envelope: EventEnvelope) -> DCBEvent {
+    pub fn into_dcb_event(self, envelope: &EventEnvelope) -> DCBEvent {
         DCBEvent {
+            tags: self.tags(),
             event_type: self.event_type,
-            tags: self
-                .domain_ids
-                .into_iter()
-                .filter_map(|(category, id)| {
-                    assert!(
-                        !category.contains(':'),
-                        "domain id categories cannot contain a colon character"
-                    );
-                    match id {
-                        DomainIdValue::Value(id) => Some(format!("{category}:{id}")),
-                        DomainIdValue::None => None,
-                    }
-                })
-                .collect(),
             data: encode_with_envelope(envelope, self.data),
             uuid: Some(Uuid::new_v4()),
         }
     }
+
+    /// Formats the domain IDs as `category:value` tags.
+    pub fn tags(&self) -> Vec<String> {
+        self.domain_ids
+            .iter()
+            .filter_map(|(category, id)| {
+                assert!(
+                    !category.contains(':'),
+                    "domain id categories cannot contain a colon character"
+                );
+                match id {
+                    DomainIdValue::Value(id) => Some(format!("{category}:{id}")),
+                    DomainIdValue::None => None,
+                }
+            })
+            .collect()
+    }
 }
 
-pub fn encode_with_envelope(envelope: EventEnvelope, data: Value) -> Vec<u8> {
+pub fn encode_with_envelope(envelope: &EventEnvelope, data: Value) -> Vec<u8> {
     serde_json::to_vec(&StoredEventData {
         timestamp: envelope.timestamp,
         correlation_id: envelope.correlation_id,
         causation_id: envelope.causation_id,
         triggered_by: envelope.triggered_by,
+        handler_version: envelope.handler_version.clone(),
         data,
     })
     .unwrap()
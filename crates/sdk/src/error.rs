@@ -1,14 +1,48 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 use umadb_dcb::DCBError;
 
 /// Error returned when a command is rejected or fails.
-#[derive(Clone, Debug, Error)]
+///
+/// Handlers with their own error type convert it into a `CommandError`,
+/// typically with `#[derive(CommandError)]`.
+#[derive(Clone, Debug, Error, Serialize, Deserialize)]
 #[error("{code}: {message}")]
 pub struct CommandError {
     /// The error classification
     pub code: ErrorCode,
     /// Human-readable error message
     pub message: String,
+    /// Machine-readable reason, eg. `insufficient_funds`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Structured details, eg. the available and requested balance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+    /// Errors of individual input fields, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+/// Error for a single field of the command input.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// JSON pointer to the field, eg. `/amount` or `/items/0/name`.
+    pub path: String,
+    /// Human-readable error message
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
 }
 
 /// Error returned when a command is rejected or fails.
@@ -22,10 +56,14 @@ pub enum ExecuteError<E> {
     DCB(#[from] DCBError),
     #[error(transparent)]
     Serialization(#[from] SerializationError),
+    /// Events matching the command's query were appended after the expected position.
+    #[error("events were appended after the expected position {expected}")]
+    StalePosition { expected: u64 },
 }
 
 /// Classification of command errors.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ErrorCode {
     /// Business rule violation - the command was understood but rejected.
     /// Example: "Insufficient funds"
@@ -37,35 +75,108 @@ pub enum ErrorCode {
     #[error("invalid_input")]
     InvalidInput,
 
+    /// The caller is not authenticated.
+    #[error("unauthorized")]
+    Unauthorized,
+
+    /// The caller is not allowed to execute the command.
+    #[error("forbidden")]
+    Forbidden,
+
+    /// An entity referenced by the input does not exist.
+    /// Example: "Task not found"
+    #[error("not_found")]
+    NotFound,
+
+    /// The command conflicts with the current state and may succeed if retried.
+    #[error("conflict")]
+    Conflict,
+
+    /// The state changed since the position the caller expected.
+    #[error("precondition_failed")]
+    PreconditionFailed,
+
     /// An unexpected error occurred in the handler.
     /// Example: Deserialization failure, logic bug
     #[error("internal")]
     Internal,
+
+    /// A dependency of the handler is temporarily unavailable.
+    #[error("unavailable")]
+    Unavailable,
+}
+
+impl FromStr for ErrorCode {
+    type Err = UnknownErrorCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rejected" => Ok(ErrorCode::Rejected),
+            "invalid_input" => Ok(ErrorCode::InvalidInput),
+            "unauthorized" => Ok(ErrorCode::Unauthorized),
+            "forbidden" => Ok(ErrorCode::Forbidden),
+            "not_found" => Ok(ErrorCode::NotFound),
+            "conflict" => Ok(ErrorCode::Conflict),
+            "precondition_failed" => Ok(ErrorCode::PreconditionFailed),
+            "internal" => Ok(ErrorCode::Internal),
+            "unavailable" => Ok(ErrorCode::Unavailable),
+            _ => Err(UnknownErrorCode(s.to_string())),
+        }
+    }
 }
 
+/// Error returned when parsing an unknown [`ErrorCode`].
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("unknown error code `{0}`")]
+pub struct UnknownErrorCode(pub String);
+
 impl CommandError {
-    /// Create a rejection error for business rule violations.
-    pub fn rejected(message: impl Into<String>) -> Self {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
         Self {
-            code: ErrorCode::Rejected,
+            code,
             message: message.into(),
+            reason: None,
+            details: None,
+            fields: Vec::new(),
         }
     }
 
+    /// Create a rejection error for business rule violations.
+    pub fn rejected(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Rejected, message)
+    }
+
     /// Create an invalid input error.
     pub fn invalid_input(message: impl Into<String>) -> Self {
-        Self {
-            code: ErrorCode::InvalidInput,
-            message: message.into(),
-        }
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    /// Create an invalid input error for a single field, eg. `/amount`.
+    pub fn invalid_field(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::invalid_input("invalid command input").with_field(path, message)
+    }
+
+    /// Adds an error for a field of the input, eg. `/amount`.
+    pub fn with_field(mut self, path: impl Into<String>, message: impl Into<String>) -> Self {
+        self.fields.push(FieldError::new(path, message));
+        self
     }
 
     /// Create an internal error.
     pub fn internal(message: impl Into<String>) -> Self {
-        Self {
-            code: ErrorCode::Internal,
-            message: message.into(),
-        }
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    /// Sets the machine-readable reason, eg. `insufficient_funds`.
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Sets structured details, eg. `{"available": 30.0, "requested": 50.0}`.
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
     }
 }
 
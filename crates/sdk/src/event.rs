@@ -5,13 +5,16 @@ use uuid::Uuid;
 
 use crate::{domain_id::DomainIdValues, error::SerializationError};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EventEnvelope {
     pub timestamp: DateTime<Utc>,
     pub correlation_id: Uuid,
     pub causation_id: Uuid,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub triggered_by: Option<Uuid>,
+    /// Version of the handler which emitted the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler_version: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,15 +27,18 @@ pub struct StoredEvent<T> {
     pub correlation_id: Uuid,
     pub causation_id: Uuid,
     pub triggered_by: Option<Uuid>,
+    pub handler_version: Option<String>,
     pub data: T,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StoredEventData<T> {
     pub timestamp: DateTime<Utc>,
     pub correlation_id: Uuid,
     pub causation_id: Uuid,
     pub triggered_by: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler_version: Option<String>,
     pub data: T,
 }
 
@@ -0,0 +1,407 @@
+//! Exports events to JSON Lines and imports them into an empty store.
+//!
+//! Each line is an [`ExportedEvent`] with the position, ID, type and tags of the event, and the
+//! [`StoredEventData`] envelope of events appended by commands decoded into top-level fields:
+//!
+//! ```json
+//! {"position":1,"id":"…","type":"OpenedAccount","tags":["account_id:alice"],"timestamp":"…","correlation_id":"…","causation_id":"…","triggered_by":null,"data":{"account_id":"alice","initial_balance":100.0}}
+//! ```
+//!
+//! Importing appends the events in order with their IDs, so a full export imported into an empty
+//! store keeps the positions too.
+
+use std::io::{self, BufRead, Write};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use umadb_dcb::{
+    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreAsync, DCBQuery, DCBSequencedEvent,
+};
+use uuid::Uuid;
+
+use crate::event::StoredEventData;
+
+/// Number of events appended at a time by [`import`].
+pub(crate) const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// A line of an export.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedEvent {
+    pub position: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub data: ExportedData,
+}
+
+/// Data of an exported event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExportedData {
+    /// An event appended by a command, with its envelope decoded.
+    Envelope {
+        #[serde(flatten)]
+        envelope: StoredEventData<Value>,
+        /// Fields of the envelope which are not known, kept so they are imported unchanged.
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    /// JSON which is not an envelope.
+    Json { data: Value },
+    /// Data which is not JSON.
+    Binary { data_base64: String },
+}
+
+impl ExportedData {
+    /// Returns the JSON data of the event, unless it is binary.
+    pub fn json(&self) -> Option<&Value> {
+        match self {
+            ExportedData::Envelope { envelope, .. } => Some(&envelope.data),
+            ExportedData::Json { data } => Some(data),
+            ExportedData::Binary { .. } => None,
+        }
+    }
+
+    pub fn json_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            ExportedData::Envelope { envelope, .. } => Some(&mut envelope.data),
+            ExportedData::Json { data } => Some(data),
+            ExportedData::Binary { .. } => None,
+        }
+    }
+}
+
+impl From<DCBSequencedEvent> for ExportedEvent {
+    fn from(event: DCBSequencedEvent) -> Self {
+        let data = match serde_json::from_slice(&event.event.data) {
+            Ok(data @ ExportedData::Envelope { .. }) => data,
+            _ => match serde_json::from_slice(&event.event.data) {
+                Ok(data) => ExportedData::Json { data },
+                Err(_) => ExportedData::Binary {
+                    data_base64: BASE64_STANDARD.encode(&event.event.data),
+                },
+            },
+        };
+
+        ExportedEvent {
+            position: event.position,
+            id: event.event.uuid,
+            event_type: event.event.event_type,
+            tags: event.event.tags,
+            data,
+        }
+    }
+}
+
+impl ExportedEvent {
+    /// Converts the event back into a DCB event to append.
+    pub fn into_dcb_event(self) -> Result<DCBEvent, base64::DecodeError> {
+        let data = match self.data {
+            data @ ExportedData::Envelope { .. } => serde_json::to_vec(&data).unwrap(),
+            ExportedData::Json { data } => serde_json::to_vec(&data).unwrap(),
+            ExportedData::Binary { data_base64 } => BASE64_STANDARD.decode(data_base64)?,
+        };
+
+        Ok(DCBEvent {
+            event_type: self.event_type,
+            tags: self.tags,
+            data,
+            uuid: self.id,
+        })
+    }
+}
+
+/// Events to export.
+#[derive(Clone, Debug, Default)]
+pub struct ExportRange {
+    /// Only exports events matching the query, if set.
+    pub query: Option<DCBQuery>,
+    /// Only exports events after this position.
+    pub after: Option<u64>,
+    /// Only exports events up to and including this position.
+    pub until: Option<u64>,
+}
+
+/// Error returned when exporting or importing events.
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("failed to read or write events: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    DCB(#[from] DCBError),
+    #[error("invalid event on line {line}: {source}")]
+    InvalidEvent {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("invalid base64 data on line {line}: {source}")]
+    InvalidData {
+        line: usize,
+        source: base64::DecodeError,
+    },
+    #[error("event on line {line} at position {position} is not after position {previous}")]
+    OutOfOrder {
+        line: usize,
+        position: u64,
+        previous: u64,
+    },
+    /// Events can only be imported into an empty store, so their order and positions are kept.
+    #[error("the store is not empty, its head is at position {head}")]
+    NotEmpty { head: u64 },
+}
+
+/// Writes the events in `range` to `writer` as JSON Lines, returning how many were exported.
+pub async fn export(
+    store: &impl DCBEventStoreAsync,
+    range: &ExportRange,
+    mut writer: impl Write,
+) -> Result<u64, ExportError> {
+    let start = range.after.map(|after| after.saturating_add(1));
+    let mut events = store
+        .read(range.query.clone(), start, false, None, false)
+        .await?;
+
+    let mut exported = 0;
+    while let Some(event) = events.next().await {
+        let event = event?;
+        if range.until.is_some_and(|until| event.position > until) {
+            break;
+        }
+        serde_json::to_writer(&mut writer, &ExportedEvent::from(event)).map_err(io::Error::from)?;
+        writer.write_all(b"\n")?;
+        exported += 1;
+    }
+
+    writer.flush()?;
+    Ok(exported)
+}
+
+/// Appends the events of a JSON Lines export to an empty store, returning how many were imported.
+///
+/// Events are appended in batches, each failing if other events were appended since the last.
+pub async fn import(
+    store: &impl DCBEventStoreAsync,
+    reader: impl BufRead,
+) -> Result<u64, ExportError> {
+    if let Some(head) = store.head().await? {
+        return Err(ExportError::NotEmpty { head });
+    }
+
+    let mut head = None;
+    let mut imported = 0;
+    let mut previous = None;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event: ExportedEvent =
+            serde_json::from_str(&line).map_err(|source| ExportError::InvalidEvent {
+                line: line_number,
+                source,
+            })?;
+        if let Some(previous) = previous
+            && event.position <= previous
+        {
+            return Err(ExportError::OutOfOrder {
+                line: line_number,
+                position: event.position,
+                previous,
+            });
+        }
+        previous = Some(event.position);
+
+        batch.push(
+            event
+                .into_dcb_event()
+                .map_err(|source| ExportError::InvalidData {
+                    line: line_number,
+                    source,
+                })?,
+        );
+        if batch.len() == IMPORT_BATCH_SIZE {
+            imported += batch.len() as u64;
+            head = Some(append_batch(store, &mut batch, head).await?);
+        }
+    }
+
+    if !batch.is_empty() {
+        imported += batch.len() as u64;
+        append_batch(store, &mut batch, head).await?;
+    }
+    Ok(imported)
+}
+
+/// Appends a batch, failing if any events were appended after `head`.
+pub(crate) async fn append_batch(
+    store: &impl DCBEventStoreAsync,
+    batch: &mut Vec<DCBEvent>,
+    head: Option<u64>,
+) -> Result<u64, DCBError> {
+    let condition = DCBAppendCondition::new(DCBQuery::new()).after(head);
+    store.append(std::mem::take(batch), Some(condition)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use umadb_dcb::DCBQueryItem;
+
+    use super::*;
+    use crate::memory::MemoryEventStore;
+
+    async fn source() -> MemoryEventStore {
+        let envelope = StoredEventData {
+            timestamp: Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap(),
+            correlation_id: Uuid::from_u128(1),
+            causation_id: Uuid::from_u128(2),
+            triggered_by: None,
+            handler_version: Some("v2".to_string()),
+            data: json!({ "account_id": "alice", "amount": 10.5 }),
+        };
+        let store = MemoryEventStore::new();
+        store
+            .append(
+                vec![
+                    DCBEvent::new()
+                        .event_type("SentFunds")
+                        .tags(["account_id:alice"])
+                        .data(serde_json::to_vec(&envelope).unwrap())
+                        .uuid(Uuid::from_u128(10)),
+                    DCBEvent::new()
+                        .event_type("Raw")
+                        .data(b"{\"x\":1}".to_vec())
+                        .uuid(Uuid::from_u128(11)),
+                    DCBEvent::new()
+                        .event_type("SentFunds")
+                        .tags(["account_id:bob"])
+                        .data(vec![0, 159, 146, 150])
+                        .uuid(Uuid::from_u128(12)),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn round_trips_events_through_an_empty_store() {
+        let source = source().await;
+        let mut jsonl = Vec::new();
+        let exported = export(&source, &ExportRange::default(), &mut jsonl)
+            .await
+            .unwrap();
+        assert_eq!(exported, 3);
+
+        let lines: Vec<Value> = jsonl
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["type"], "SentFunds");
+        assert_eq!(lines[0]["handler_version"], "v2");
+        assert_eq!(lines[0]["data"]["account_id"], "alice");
+        assert_eq!(lines[1]["data"], json!({ "x": 1 }));
+        assert_eq!(lines[2]["data_base64"], "AJ+Slg==");
+
+        let target = MemoryEventStore::new();
+        assert_eq!(import(&target, jsonl.as_slice()).await.unwrap(), 3);
+        for (source, target) in source.events().iter().zip(target.events()) {
+            assert_eq!(source.position, target.position);
+            assert_eq!(source.event.uuid, target.event.uuid);
+            assert_eq!(source.event.event_type, target.event.event_type);
+            assert_eq!(source.event.tags, target.event.tags);
+            assert_eq!(
+                ExportedEvent::from(source.clone()),
+                ExportedEvent::from(target)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_unknown_envelope_fields() {
+        let data = json!({
+            "timestamp": "2025-01-02T03:04:05Z",
+            "correlation_id": Uuid::from_u128(1),
+            "causation_id": Uuid::from_u128(2),
+            "triggered_by": null,
+            "data": { "account_id": "alice" },
+            "schema_version": 3,
+        });
+        let source = MemoryEventStore::new();
+        source
+            .append(
+                vec![
+                    DCBEvent::new()
+                        .event_type("OpenedAccount")
+                        .data(serde_json::to_vec(&data).unwrap()),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let mut jsonl = Vec::new();
+        export(&source, &ExportRange::default(), &mut jsonl)
+            .await
+            .unwrap();
+        let event: ExportedEvent = serde_json::from_slice(jsonl.trim_ascii_end()).unwrap();
+        assert!(matches!(event.data, ExportedData::Envelope { .. }));
+
+        let target = MemoryEventStore::new();
+        import(&target, jsonl.as_slice()).await.unwrap();
+        let imported: Value = serde_json::from_slice(&target.events()[0].event.data).unwrap();
+        assert_eq!(imported, data);
+    }
+
+    #[tokio::test]
+    async fn exports_a_filtered_range() {
+        let source = source().await;
+        let range = ExportRange {
+            query: Some(DCBQuery::new().item(DCBQueryItem::new().types(["SentFunds"]))),
+            after: Some(1),
+            until: Some(3),
+        };
+        let mut jsonl = Vec::new();
+        assert_eq!(export(&source, &range, &mut jsonl).await.unwrap(), 1);
+        let event: ExportedEvent = serde_json::from_slice(jsonl.trim_ascii_end()).unwrap();
+        assert_eq!(event.position, 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_imports_into_non_empty_stores_and_out_of_order_events() {
+        let source = source().await;
+        let mut jsonl = Vec::new();
+        export(&source, &ExportRange::default(), &mut jsonl)
+            .await
+            .unwrap();
+
+        let err = import(&source, jsonl.as_slice()).await.unwrap_err();
+        assert!(matches!(err, ExportError::NotEmpty { head: 3 }));
+
+        let mut lines: Vec<_> = jsonl.split(|byte| *byte == b'\n').collect();
+        lines.swap(0, 1);
+        let err = import(&MemoryEventStore::new(), lines.join(&b'\n').as_slice())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ExportError::OutOfOrder {
+                line: 2,
+                position: 1,
+                previous: 2
+            }
+        ));
+    }
+}
@@ -75,14 +75,26 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## WASM handlers
+//!
+//! Commands can be compiled to `wasm32-unknown-unknown` and uploaded to the server with
+//! `PUT /handlers/{name}`. Enable the SDK's `guest` feature, which adds the exports the server
+//! calls into, then export the command with [`export_command!`]. The SDK has no other features.
+//! Modules cannot import anything from the host, so IDs generated in handlers need a
+//! `getrandom` backend which works without imports.
 
-pub use esruntime_sdk_macros::{CommandInput, Event, EventSet};
+pub use esruntime_sdk_macros::{CommandError, CommandInput, Event, EventSet};
 
 pub mod command;
 pub mod domain_id;
 pub mod emit;
 pub mod error;
 pub mod event;
+pub mod export;
+pub mod memory;
+pub mod migrate;
+pub mod wasm;
 #[macro_use]
 mod macros;
 
@@ -93,7 +105,7 @@ pub mod prelude {
     pub use crate::emit::*;
     pub use crate::error::*;
     pub use crate::event::*;
-    pub use esruntime_sdk_macros::{CommandInput, Event, EventSet};
+    pub use esruntime_sdk_macros::{CommandError, CommandInput, Event, EventSet};
 }
 
 #[doc(hidden)]
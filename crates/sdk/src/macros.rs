@@ -36,3 +36,65 @@ macro_rules! emit {
             $(.event($event))+
     };
 }
+
+/// Exports a [`Command`](crate::command::Command) as a WASM handler module.
+///
+/// Generates the exports described in [`wasm`](crate::wasm), so the crate can be built with
+/// `crate-type = ["cdylib"]` for `wasm32-unknown-unknown` and uploaded with
+/// `PUT /handlers/{name}`. Requires the `guest` feature.
+///
+/// Declare [`Command::EMITTED_EVENT_TYPES`](crate::command::Command::EMITTED_EVENT_TYPES) so the
+/// emitted events are checked against the event schema when uploaded.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// esruntime_sdk::export_command!(TransferFunds);
+/// ```
+#[cfg(feature = "guest")]
+#[macro_export]
+macro_rules! export_command {
+    ($command:ty) => {
+        const _: () = {
+            ::std::thread_local! {
+                static HANDLER: ::std::cell::RefCell<::std::option::Option<$command>> =
+                    const { ::std::cell::RefCell::new(::std::option::Option::None) };
+            }
+
+            #[unsafe(no_mangle)]
+            pub extern "C" fn esruntime_abi_version() -> i32 {
+                $crate::wasm::ABI_VERSION
+            }
+
+            #[unsafe(no_mangle)]
+            pub extern "C" fn esruntime_alloc(len: i32) -> i32 {
+                $crate::wasm::guest::alloc(len)
+            }
+
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn esruntime_dealloc(ptr: i32, len: i32) {
+                unsafe { $crate::wasm::guest::dealloc(ptr, len) }
+            }
+
+            #[unsafe(no_mangle)]
+            pub extern "C" fn esruntime_describe() -> i64 {
+                $crate::wasm::guest::describe::<$command, _>()
+            }
+
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn esruntime_query(ptr: i32, len: i32) -> i64 {
+                unsafe { $crate::wasm::guest::query::<$command, _>(&HANDLER, ptr, len) }
+            }
+
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn esruntime_apply(ptr: i32, len: i32) -> i64 {
+                unsafe { $crate::wasm::guest::apply::<$command, _>(&HANDLER, ptr, len) }
+            }
+
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn esruntime_handle(ptr: i32, len: i32) -> i64 {
+                unsafe { $crate::wasm::guest::handle::<$command, _>(&HANDLER, ptr, len) }
+            }
+        };
+    };
+}
@@ -0,0 +1,239 @@
+//! In-memory event store, for tests and for reproducing bugs locally from exported events.
+
+use std::{
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    vec,
+};
+
+use async_trait::async_trait;
+use futures_util::Stream;
+use umadb_dcb::{
+    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreAsync, DCBQuery, DCBReadResponseAsync,
+    DCBResult, DCBSequencedEvent,
+};
+
+/// Event store keeping events in memory, with the same query and append condition semantics as
+/// UmaDB.
+///
+/// Subscriptions are not supported, so reads always end at the head.
+#[derive(Debug, Default)]
+pub struct MemoryEventStore {
+    events: Mutex<Vec<DCBSequencedEvent>>,
+}
+
+impl MemoryEventStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns every stored event in order.
+    pub fn events(&self) -> Vec<DCBSequencedEvent> {
+        self.events
+            .lock()
+            .expect("memory event store lock poisoned")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl DCBEventStoreAsync for MemoryEventStore {
+    async fn read<'a>(
+        &'a self,
+        query: Option<DCBQuery>,
+        start: Option<u64>,
+        backwards: bool,
+        limit: Option<u32>,
+        subscribe: bool,
+    ) -> DCBResult<Box<dyn DCBReadResponseAsync + Send + 'static>> {
+        if subscribe {
+            return Err(DCBError::InternalError(
+                "the in-memory event store does not support subscriptions".to_string(),
+            ));
+        }
+
+        let events = self
+            .events
+            .lock()
+            .expect("memory event store lock poisoned");
+        let head = events.last().map(|event| event.position);
+        let in_range = |event: &&DCBSequencedEvent| match start {
+            Some(start) if backwards => event.position <= start,
+            Some(start) => event.position >= start,
+            None => true,
+        };
+        let matching = |event: &&DCBSequencedEvent| {
+            query
+                .as_ref()
+                .is_none_or(|query| matches(query, &event.event))
+        };
+        let limit = limit.map_or(usize::MAX, |limit| limit as usize);
+
+        let events: Vec<_> = if backwards {
+            events
+                .iter()
+                .rev()
+                .filter(in_range)
+                .filter(matching)
+                .take(limit)
+                .cloned()
+                .collect()
+        } else {
+            events
+                .iter()
+                .filter(in_range)
+                .filter(matching)
+                .take(limit)
+                .cloned()
+                .collect()
+        };
+
+        Ok(Box::new(MemoryReadResponse {
+            events: events.into_iter(),
+            head,
+        }))
+    }
+
+    async fn head(&self) -> DCBResult<Option<u64>> {
+        let events = self
+            .events
+            .lock()
+            .expect("memory event store lock poisoned");
+        Ok(events.last().map(|event| event.position))
+    }
+
+    async fn append(
+        &self,
+        new_events: Vec<DCBEvent>,
+        condition: Option<DCBAppendCondition>,
+    ) -> DCBResult<u64> {
+        let mut events = self
+            .events
+            .lock()
+            .expect("memory event store lock poisoned");
+        if let Some(condition) = condition {
+            let after = condition.after.unwrap_or(0);
+            let conflict = events.iter().find(|event| {
+                event.position > after && matches(&condition.fail_if_events_match, &event.event)
+            });
+            if let Some(conflict) = conflict {
+                return Err(DCBError::IntegrityError(format!(
+                    "matching event at position {}",
+                    conflict.position
+                )));
+            }
+        }
+
+        let mut position = events.last().map_or(0, |event| event.position);
+        for event in new_events {
+            position += 1;
+            events.push(DCBSequencedEvent { position, event });
+        }
+        Ok(position)
+    }
+}
+
+/// Returns whether an event matches any item of the query, or the query has no items.
+fn matches(query: &DCBQuery, event: &DCBEvent) -> bool {
+    query.items.is_empty()
+        || query.items.iter().any(|item| {
+            (item.types.is_empty() || item.types.contains(&event.event_type))
+                && item.tags.iter().all(|tag| event.tags.contains(tag))
+        })
+}
+
+struct MemoryReadResponse {
+    events: vec::IntoIter<DCBSequencedEvent>,
+    head: Option<u64>,
+}
+
+impl Stream for MemoryReadResponse {
+    type Item = DCBResult<DCBSequencedEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.events.next().map(Ok))
+    }
+}
+
+#[async_trait]
+impl DCBReadResponseAsync for MemoryReadResponse {
+    async fn head(&mut self) -> DCBResult<Option<u64>> {
+        Ok(self.head)
+    }
+
+    async fn next_batch(&mut self) -> DCBResult<Vec<DCBSequencedEvent>> {
+        Ok(self.events.by_ref().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umadb_dcb::DCBQueryItem;
+
+    use super::*;
+
+    fn event(event_type: &str, tags: &[&str]) -> DCBEvent {
+        DCBEvent::new()
+            .event_type(event_type)
+            .tags(tags.iter().copied())
+    }
+
+    #[tokio::test]
+    async fn reads_matching_events_in_order() {
+        let store = MemoryEventStore::new();
+        let head = store
+            .append(
+                vec![
+                    event("Opened", &["account:a"]),
+                    event("Sent", &["account:a"]),
+                    event("Opened", &["account:b"]),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(head, 3);
+
+        let query = DCBQuery::new().item(DCBQueryItem::new().tags(["account:a"]));
+        let (events, head) = store
+            .read_with_head(Some(query), None, false, None)
+            .await
+            .unwrap();
+        let positions: Vec<_> = events.iter().map(|event| event.position).collect();
+        assert_eq!(positions, [1, 2]);
+        assert_eq!(head, Some(3));
+
+        let (events, _) = store
+            .read_with_head(None, Some(2), true, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(events[0].position, 2);
+    }
+
+    #[tokio::test]
+    async fn fails_appends_when_matching_events_follow_the_condition() {
+        let store = MemoryEventStore::new();
+        store
+            .append(vec![event("Opened", &["account:a"])], None)
+            .await
+            .unwrap();
+
+        let condition =
+            DCBAppendCondition::new(DCBQuery::new().item(DCBQueryItem::new().tags(["account:a"])));
+        let err = store
+            .append(vec![event("Sent", &["account:a"])], Some(condition.clone()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DCBError::IntegrityError(_)));
+
+        let head = store
+            .append(
+                vec![event("Sent", &["account:a"])],
+                Some(condition.after(Some(1))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(head, 2);
+    }
+}
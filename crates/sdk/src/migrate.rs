@@ -0,0 +1,473 @@
+//! Copies events from one store to a fresh store, transforming them on the way.
+//!
+//! Tags are derived from `#[domain_id]` fields when events are appended, so changes such as
+//! tagging existing events with a new domain ID need the events to be copied to a new store:
+//!
+//! ```rust,ignore
+//! use esruntime_sdk::migrate::{self, Migration};
+//!
+//! let report = Migration::new()
+//!     .transform(migrate::add_domain_id("SentFunds", "recipient_id"))
+//!     .transform(migrate::rename_type("SentFunds", "FundsSent"))
+//!     .run(&source, &target)
+//!     .await?;
+//! report.positions.write_jsonl(File::create("positions.jsonl")?)?;
+//! ```
+//!
+//! Events are read in order from the source and passed through each [`Transform`] in turn, which
+//! can change, split or drop them. The position of every source event in the target store is
+//! recorded in a [`PositionMap`], used to translate checkpoints of projections.
+
+use std::io::{self, Write};
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use umadb_dcb::{DCBError, DCBEvent, DCBEventStoreAsync};
+use uuid::Uuid;
+
+use crate::export::{self, ExportedEvent, IMPORT_BATCH_SIZE};
+
+/// A transformation of events, returning the events to write in place of each event.
+///
+/// Returning no events drops the event. Implemented for closures taking an event.
+pub trait Transform {
+    fn transform(&mut self, event: ExportedEvent) -> Vec<ExportedEvent>;
+}
+
+impl<F> Transform for F
+where
+    F: FnMut(ExportedEvent) -> Vec<ExportedEvent>,
+{
+    fn transform(&mut self, event: ExportedEvent) -> Vec<ExportedEvent> {
+        self(event)
+    }
+}
+
+/// Renames events of type `from` to `to`.
+pub fn rename_type(from: impl Into<String>, to: impl Into<String>) -> impl Transform {
+    let (from, to) = (from.into(), to.into());
+    move |mut event: ExportedEvent| {
+        if event.event_type == from {
+            event.event_type.clone_from(&to);
+        }
+        vec![event]
+    }
+}
+
+/// Replaces events of a type with the events returned by `split`.
+pub fn split<F>(event_type: impl Into<String>, mut split: F) -> impl Transform
+where
+    F: FnMut(ExportedEvent) -> Vec<ExportedEvent>,
+{
+    let event_type = event_type.into();
+    move |event: ExportedEvent| {
+        if event.event_type == event_type {
+            split(event)
+        } else {
+            vec![event]
+        }
+    }
+}
+
+/// Tags events of a type with the string value of `field`, as if it were a `#[domain_id]`.
+///
+/// Events without a string value for the field are left untagged, like absent optional domain
+/// IDs.
+pub fn add_domain_id(event_type: impl Into<String>, field: impl Into<String>) -> impl Transform {
+    let (event_type, field) = (event_type.into(), field.into());
+    move |mut event: ExportedEvent| {
+        if event.event_type == event_type {
+            let value = event.data.json().and_then(|data| data.get(&field));
+            if let Some(Value::String(value)) = value {
+                let tag = format!("{field}:{value}");
+                if !event.tags.contains(&tag) {
+                    event.tags.push(tag);
+                }
+            }
+        }
+        vec![event]
+    }
+}
+
+/// Removes the tags of a domain ID `category` from events of a type.
+pub fn remove_domain_id(
+    event_type: impl Into<String>,
+    category: impl Into<String>,
+) -> impl Transform {
+    let event_type = event_type.into();
+    let prefix = format!("{}:", category.into());
+    move |mut event: ExportedEvent| {
+        if event.event_type == event_type {
+            event.tags.retain(|tag| !tag.starts_with(&prefix));
+        }
+        vec![event]
+    }
+}
+
+/// Replaces the value of `field` in events of a type with `replacement`.
+///
+/// Events without the field are left unchanged.
+pub fn redact(
+    event_type: impl Into<String>,
+    field: impl Into<String>,
+    replacement: Value,
+) -> impl Transform {
+    let (event_type, field) = (event_type.into(), field.into());
+    move |mut event: ExportedEvent| {
+        if event.event_type == event_type
+            && let Some(value) = event.data.json_mut().and_then(|data| data.get_mut(&field))
+        {
+            *value = replacement.clone();
+        }
+        vec![event]
+    }
+}
+
+/// Positions in the target store of the events written for a source event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionMapping {
+    pub source: u64,
+    /// Empty if the event was dropped.
+    pub targets: Vec<u64>,
+}
+
+/// Positions of source events in the target store, in order of source position.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PositionMap {
+    mappings: Vec<PositionMapping>,
+}
+
+impl PositionMap {
+    /// Returns the target positions of the events written for a source event.
+    pub fn targets(&self, source: u64) -> &[u64] {
+        match self
+            .mappings
+            .binary_search_by_key(&source, |mapping| mapping.source)
+        {
+            Ok(index) => &self.mappings[index].targets,
+            Err(_) => &[],
+        }
+    }
+
+    /// Translates a checkpoint at a source position into the target store.
+    ///
+    /// Returns the last target position written for events up to and including `source`, or
+    /// `None` if none were.
+    pub fn translate(&self, source: u64) -> Option<u64> {
+        let end = self
+            .mappings
+            .partition_point(|mapping| mapping.source <= source);
+        self.mappings[..end]
+            .iter()
+            .rev()
+            .find_map(|mapping| mapping.targets.last().copied())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PositionMapping> {
+        self.mappings.iter()
+    }
+
+    /// Writes the mappings as JSON Lines.
+    pub fn write_jsonl(&self, mut writer: impl Write) -> io::Result<()> {
+        for mapping in &self.mappings {
+            serde_json::to_writer(&mut writer, mapping)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()
+    }
+}
+
+/// Result of a migration.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Number of events read from the source store.
+    pub read: u64,
+    /// Number of events written to the target store.
+    pub written: u64,
+    pub positions: PositionMap,
+}
+
+/// Error returned when migrating events.
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error(transparent)]
+    DCB(#[from] DCBError),
+    #[error("invalid base64 data in an event transformed from position {position}: {source}")]
+    InvalidData {
+        position: u64,
+        source: base64::DecodeError,
+    },
+    /// Events are only written to an empty store, so the position map covers every event.
+    #[error("the target store is not empty, its head is at position {head}")]
+    NotEmpty { head: u64 },
+}
+
+/// Copies every event of a store to a fresh store through a sequence of transformations.
+#[derive(Default)]
+pub struct Migration {
+    transforms: Vec<Box<dyn Transform + Send>>,
+}
+
+impl Migration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a transformation, applied to the events returned by the previous ones.
+    pub fn transform(mut self, transform: impl Transform + Send + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Runs the transformations on a single event.
+    ///
+    /// Events returned after the first with the ID of the source event are given new IDs, so
+    /// split events stay unique.
+    pub fn apply(&mut self, event: ExportedEvent) -> Vec<ExportedEvent> {
+        let id = event.id;
+        let mut events = vec![event];
+        for transform in &mut self.transforms {
+            events = events
+                .into_iter()
+                .flat_map(|event| transform.transform(event))
+                .collect();
+        }
+
+        let mut kept_id = false;
+        for event in &mut events {
+            if event.id.is_some() && event.id == id {
+                if kept_id {
+                    event.id = Some(Uuid::new_v4());
+                }
+                kept_id = true;
+            }
+        }
+        events
+    }
+
+    /// Copies every event of `source` to the empty `target` store.
+    ///
+    /// Events are appended in batches, each failing if other events were appended to the target
+    /// since the last.
+    pub async fn run(
+        mut self,
+        source: &impl DCBEventStoreAsync,
+        target: &impl DCBEventStoreAsync,
+    ) -> Result<MigrationReport, MigrationError> {
+        if let Some(head) = target.head().await? {
+            return Err(MigrationError::NotEmpty { head });
+        }
+
+        let mut report = MigrationReport::default();
+        let mut head = None;
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        // Source positions with the number of events written for them in the batch.
+        let mut pending = Vec::new();
+        let mut events = source.read(None, None, false, None, false).await?;
+        while let Some(event) = events.next().await {
+            let event = event?;
+            let position = event.position;
+            report.read += 1;
+
+            let transformed = self.apply(event.into());
+            pending.push((position, transformed.len()));
+            for event in transformed {
+                batch.push(
+                    event
+                        .into_dcb_event()
+                        .map_err(|source| MigrationError::InvalidData { position, source })?,
+                );
+            }
+
+            if batch.len() >= IMPORT_BATCH_SIZE {
+                head = append(target, &mut batch, &mut pending, head, &mut report).await?;
+            }
+        }
+        append(target, &mut batch, &mut pending, head, &mut report).await?;
+
+        Ok(report)
+    }
+}
+
+/// Appends a batch and records the positions of its events, returning the new head.
+async fn append(
+    target: &impl DCBEventStoreAsync,
+    batch: &mut Vec<DCBEvent>,
+    pending: &mut Vec<(u64, usize)>,
+    head: Option<u64>,
+    report: &mut MigrationReport,
+) -> Result<Option<u64>, DCBError> {
+    let mut next = match batch.len() {
+        0 => head.map_or(1, |head| head.saturating_add(1)),
+        len => {
+            let last = export::append_batch(target, batch, head).await?;
+            report.written += len as u64;
+            last + 1 - len as u64
+        }
+    };
+
+    for (source, count) in pending.drain(..) {
+        report.positions.mappings.push(PositionMapping {
+            source,
+            targets: (next..next + count as u64).collect(),
+        });
+        next += count as u64;
+    }
+    Ok(next.checked_sub(1).filter(|head| *head > 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{export::ExportedData, memory::MemoryEventStore};
+
+    async fn source() -> MemoryEventStore {
+        let event = |event_type: &str, tags: &[&str], data: Value| {
+            DCBEvent::new()
+                .event_type(event_type)
+                .tags(tags.iter().copied())
+                .data(serde_json::to_vec(&data).unwrap())
+                .uuid(Uuid::new_v4())
+        };
+        let store = MemoryEventStore::new();
+        store
+            .append(
+                vec![
+                    event(
+                        "OpenedAccount",
+                        &["account_id:alice"],
+                        json!({ "account_id": "alice", "email": "alice@example.com" }),
+                    ),
+                    event(
+                        "SentFunds",
+                        &["account_id:alice"],
+                        json!({ "account_id": "alice", "recipient_id": "bob", "amount": 5 }),
+                    ),
+                    event("Noise", &[], json!({})),
+                    event(
+                        "SentFunds",
+                        &["account_id:alice"],
+                        json!({ "account_id": "alice", "recipient_id": null, "amount": 7 }),
+                    ),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        store
+    }
+
+    fn data(event: &DCBEvent) -> Value {
+        serde_json::from_slice(&event.data).unwrap()
+    }
+
+    #[tokio::test]
+    async fn transforms_events_into_a_fresh_store() {
+        let source = source().await;
+        let target = MemoryEventStore::new();
+        let report = Migration::new()
+            .transform(redact("OpenedAccount", "email", json!("[redacted]")))
+            .transform(add_domain_id("SentFunds", "recipient_id"))
+            .transform(|event: ExportedEvent| match event.event_type.as_str() {
+                "Noise" => vec![],
+                _ => vec![event],
+            })
+            .transform(split("SentFunds", |event: ExportedEvent| {
+                let mut received = event.clone();
+                received.event_type = "ReceivedFunds".to_string();
+                vec![event, received]
+            }))
+            .transform(remove_domain_id("ReceivedFunds", "account_id"))
+            .transform(rename_type("SentFunds", "FundsSent"))
+            .run(&source, &target)
+            .await
+            .unwrap();
+
+        assert_eq!(report.read, 4);
+        assert_eq!(report.written, 5);
+
+        let events = target.events();
+        let types: Vec<_> = events
+            .iter()
+            .map(|event| event.event.event_type.as_str())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "OpenedAccount",
+                "FundsSent",
+                "ReceivedFunds",
+                "FundsSent",
+                "ReceivedFunds"
+            ]
+        );
+        assert_eq!(data(&events[0].event)["email"], "[redacted]");
+        assert_eq!(
+            events[1].event.tags,
+            ["account_id:alice", "recipient_id:bob"]
+        );
+        assert_eq!(events[3].event.tags, ["account_id:alice"]);
+        assert_eq!(events[2].event.tags, ["recipient_id:bob"]);
+        assert!(events[4].event.tags.is_empty());
+
+        let source_events = source.events();
+        assert_eq!(events[1].event.uuid, source_events[1].event.uuid);
+        assert_ne!(events[2].event.uuid, source_events[1].event.uuid);
+        assert!(events[2].event.uuid.is_some());
+
+        let mappings: Vec<_> = report.positions.iter().cloned().collect();
+        assert_eq!(
+            mappings,
+            [
+                PositionMapping {
+                    source: 1,
+                    targets: vec![1]
+                },
+                PositionMapping {
+                    source: 2,
+                    targets: vec![2, 3]
+                },
+                PositionMapping {
+                    source: 3,
+                    targets: vec![]
+                },
+                PositionMapping {
+                    source: 4,
+                    targets: vec![4, 5]
+                },
+            ]
+        );
+        assert_eq!(report.positions.targets(2), [2, 3]);
+        assert_eq!(report.positions.translate(3), Some(3));
+        assert_eq!(report.positions.translate(4), Some(5));
+    }
+
+    #[tokio::test]
+    async fn rejects_non_empty_targets() {
+        let source = source().await;
+        let err = Migration::new().run(&source, &source).await.unwrap_err();
+        assert!(matches!(err, MigrationError::NotEmpty { head: 4 }));
+    }
+
+    #[test]
+    fn leaves_binary_events_unchanged() {
+        let event = ExportedEvent {
+            position: 1,
+            id: None,
+            event_type: "Blob".to_string(),
+            tags: vec![],
+            data: ExportedData::Binary {
+                data_base64: "AA==".to_string(),
+            },
+        };
+        let events = Migration::new()
+            .transform(redact("Blob", "secret", Value::Null))
+            .transform(add_domain_id("Blob", "id"))
+            .apply(event.clone());
+        assert_eq!(events, [event]);
+    }
+}
@@ -0,0 +1,187 @@
+//! ABI between the server's WASM runtime and command handler modules.
+//!
+//! Handler modules are core WASM modules without imports, built for `wasm32-unknown-unknown`.
+//! Other targets, such as `wasm32-wasip2` components described with WIT, are not supported.
+//! Modules export their linear `memory` and the functions below, exchanging JSON encoded values:
+//!
+//! | Export | Signature | Description |
+//! |--------|-----------|-------------|
+//! | `esruntime_abi_version` | `() -> i32` | Returns [`ABI_VERSION`] |
+//! | `esruntime_alloc` | `(len: i32) -> i32` | Allocates `len` bytes, returning the pointer, or 0 if `len` is negative |
+//! | `esruntime_dealloc` | `(ptr: i32, len: i32)` | Frees a buffer allocated with `esruntime_alloc` |
+//! | `esruntime_describe` | `() -> i64` | Returns a [`WasmDescription`] of the events the handler reads and emits |
+//! | `esruntime_query` | `(ptr: i32, len: i32) -> i64` | Validates the command input, returning a [`WasmResult`] of [`WasmQuery`] |
+//! | `esruntime_apply` | `(ptr: i32, len: i32) -> i64` | Applies a [`WasmEvent`], returning a [`WasmResult`] of `()` |
+//! | `esruntime_handle` | `(ptr: i32, len: i32) -> i64` | Handles the command input, returning a [`WasmResult`] of [`WasmEmit`] |
+//!
+//! The host writes arguments into buffers allocated with `esruntime_alloc`, and results are
+//! returned as a pointer and length packed into an `i64` (see [`pack`]). The host frees both
+//! with `esruntime_dealloc` once read. A fresh instance is used for every execution, so
+//! handlers keep the state rebuilt by `esruntime_apply` in memory until `esruntime_handle`.
+//!
+//! With the `guest` feature, [`export_command!`](crate::export_command) generates these exports
+//! for a [`Command`](crate::command::Command).
+
+#[cfg(feature = "guest")]
+pub mod guest;
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use umadb_dcb::{DCBQuery, DCBQueryItem};
+
+use crate::{emit::EmittedEvent, error::CommandError};
+
+/// Version of the ABI implemented by handler modules.
+pub const ABI_VERSION: i32 = 2;
+
+pub const EXPORT_MEMORY: &str = "memory";
+pub const EXPORT_ABI_VERSION: &str = "esruntime_abi_version";
+pub const EXPORT_ALLOC: &str = "esruntime_alloc";
+pub const EXPORT_DEALLOC: &str = "esruntime_dealloc";
+pub const EXPORT_DESCRIBE: &str = "esruntime_describe";
+pub const EXPORT_QUERY: &str = "esruntime_query";
+pub const EXPORT_APPLY: &str = "esruntime_apply";
+pub const EXPORT_HANDLE: &str = "esruntime_handle";
+
+/// Result of a handler export.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WasmResult<T> {
+    Ok(T),
+    /// The command was rejected by `handle`.
+    Rejected(CommandError),
+    /// The command input failed to deserialize or validate.
+    Invalid(CommandError),
+}
+
+/// The events and domain IDs read by a handler, and the events it emits.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmDescription {
+    /// Event types read by the handler's query
+    pub event_types: Vec<String>,
+    /// Event types emitted by the handler, empty when undeclared
+    #[serde(default)]
+    pub emitted_event_types: Vec<String>,
+    /// Domain ID fields of each event type read
+    pub event_domain_ids: BTreeMap<String, Vec<String>>,
+    /// Input fields bound to domain IDs, mapped to the domain ID
+    pub input_domain_ids: BTreeMap<String, String>,
+}
+
+/// The events read by a command.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmQuery {
+    pub items: Vec<WasmQueryItem>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmQueryItem {
+    /// Event types to match
+    pub types: Vec<String>,
+    /// Tags which must all be present on the event
+    pub tags: Vec<String>,
+}
+
+impl From<DCBQuery> for WasmQuery {
+    fn from(query: DCBQuery) -> Self {
+        WasmQuery {
+            items: query
+                .items
+                .into_iter()
+                .map(|item| WasmQueryItem {
+                    types: item.types,
+                    tags: item.tags,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<WasmQuery> for DCBQuery {
+    fn from(query: WasmQuery) -> Self {
+        DCBQuery::with_items(
+            query
+                .items
+                .into_iter()
+                .map(|item| DCBQueryItem::new().types(item.types).tags(item.tags)),
+        )
+    }
+}
+
+/// A historical event passed to `esruntime_apply`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WasmEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub data: Value,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The events and output returned by `esruntime_handle`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WasmEmit {
+    pub events: Vec<WasmEmittedEvent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+}
+
+/// An event emitted by a handler module, with its domain IDs formatted as tags.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WasmEmittedEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub data: Value,
+    /// Domain IDs formatted as `category:value`
+    pub tags: Vec<String>,
+}
+
+impl From<EmittedEvent> for WasmEmittedEvent {
+    fn from(event: EmittedEvent) -> Self {
+        WasmEmittedEvent {
+            tags: event.tags(),
+            event_type: event.event_type,
+            data: event.data,
+        }
+    }
+}
+
+/// Packs a pointer and length into the `i64` returned by handler exports.
+pub fn pack(ptr: u32, len: u32) -> i64 {
+    ((u64::from(ptr) << 32) | u64::from(len)) as i64
+}
+
+/// Unpacks a pointer and length returned by a handler export.
+pub fn unpack(packed: i64) -> (u32, u32) {
+    let packed = packed as u64;
+    ((packed >> 32) as u32, packed as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn pack_round_trips() {
+        assert_eq!(unpack(pack(0xdead_beef, 42)), (0xdead_beef, 42));
+        assert_eq!(unpack(pack(0, 0)), (0, 0));
+    }
+
+    #[test]
+    fn results_are_externally_tagged() {
+        let ok: WasmResult<()> = serde_json::from_value(json!({ "ok": null })).unwrap();
+        assert!(matches!(ok, WasmResult::Ok(())));
+
+        let rejected: WasmResult<()> = serde_json::from_value(json!({
+            "rejected": { "code": "rejected", "message": "task already created" },
+        }))
+        .unwrap();
+        assert!(
+            matches!(rejected, WasmResult::Rejected(err) if err.message == "task already created")
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Guest side of the ABI, used by the exports generated with [`export_command!`](crate::export_command).
+//!
+//! Handler state lives in a thread local between `esruntime_query`, `esruntime_apply` and
+//! `esruntime_handle`, which is safe as the host uses a fresh instance for every execution.
+
+use std::{cell::RefCell, ptr, slice, thread::LocalKey};
+
+use futures_util::FutureExt;
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{WasmDescription, WasmEmit, WasmEvent, WasmQuery, WasmResult, pack};
+use crate::{
+    command::{Command, CommandInput, EventMeta},
+    error::CommandError,
+    event::EventSet,
+};
+
+/// Thread local holding the handler between exports.
+pub type HandlerState<C> = LocalKey<RefCell<Option<C>>>;
+
+/// Allocates a zeroed buffer for the host to write an argument into, returning 0 if `len` is
+/// negative.
+pub fn alloc(len: i32) -> i32 {
+    if len < 0 {
+        return 0;
+    }
+
+    let buf = vec![0u8; len as usize].into_boxed_slice();
+    Box::into_raw(buf) as *mut u8 as i32
+}
+
+/// Frees a buffer returned by [`alloc`] or an export.
+///
+/// # Safety
+///
+/// `ptr` and `len` must describe a buffer allocated by this module which has not been freed.
+pub unsafe fn dealloc(ptr: i32, len: i32) {
+    let buf = ptr::slice_from_raw_parts_mut(ptr as *mut u8, len as usize);
+    drop(unsafe { Box::from_raw(buf) });
+}
+
+/// Describes the events and domain IDs read by a command, and the events it emits.
+pub fn describe<C: Command<O>, O: Send>() -> i64 {
+    output(&description::<C, O>())
+}
+
+/// Builds the description of a command from its query, input and emitted event types.
+pub fn description<C: Command<O>, O: Send>() -> WasmDescription {
+    WasmDescription {
+        event_types: C::Query::EVENT_TYPES
+            .iter()
+            .map(|event_type| event_type.to_string())
+            .collect(),
+        emitted_event_types: C::EMITTED_EVENT_TYPES
+            .iter()
+            .map(|event_type| event_type.to_string())
+            .collect(),
+        event_domain_ids: C::Query::EVENT_DOMAIN_IDS
+            .iter()
+            .map(|(event_type, domain_ids)| {
+                let domain_ids = domain_ids.iter().map(|id| id.to_string()).collect();
+                (event_type.to_string(), domain_ids)
+            })
+            .collect(),
+        input_domain_ids: C::Input::DOMAIN_ID_BINDINGS
+            .iter()
+            .map(|(field, domain_id)| (field.to_string(), domain_id.to_string()))
+            .collect(),
+    }
+}
+
+/// Validates the input and returns the command's query.
+///
+/// # Safety
+///
+/// `ptr` and `len` must describe a buffer allocated with [`alloc`].
+pub unsafe fn query<C, O>(state: &'static HandlerState<C>, ptr: i32, len: i32) -> i64
+where
+    C: Command<O> + 'static,
+    O: Send,
+    C::Input: DeserializeOwned,
+    C::Error: Into<CommandError>,
+{
+    let result = unsafe { input::<C::Input, _>(ptr, len) }.and_then(|input| {
+        C::validate(&input).map_err(|err| WasmResult::Invalid(err.into()))?;
+        let command = C::default();
+        let query = command.query(&input);
+        state.set(Some(command));
+        Ok(WasmQuery::from(query))
+    });
+    finish(result)
+}
+
+/// Applies a historical event to the handler, ignoring events outside of its query.
+///
+/// # Safety
+///
+/// `ptr` and `len` must describe a buffer allocated with [`alloc`].
+pub unsafe fn apply<C, O>(state: &'static HandlerState<C>, ptr: i32, len: i32) -> i64
+where
+    C: Command<O> + 'static,
+    O: Send,
+{
+    let result = unsafe { input::<WasmEvent, _>(ptr, len) }.and_then(|event| {
+        let Some(query_event) = C::Query::from_event(&event.event_type, event.data) else {
+            return Ok(());
+        };
+        let query_event = query_event.map_err(|err| {
+            WasmResult::Rejected(CommandError::internal(format!(
+                "failed to deserialize {}: {}",
+                event.event_type, err.message
+            )))
+        })?;
+        let meta = EventMeta {
+            timestamp: event.timestamp,
+        };
+        state.with_borrow_mut(|command| {
+            command
+                .get_or_insert_with(C::default)
+                .apply(query_event, meta)
+        });
+        Ok(())
+    });
+    finish(result)
+}
+
+/// Handles the command, returning the emitted events and output.
+///
+/// `before_commit` is run to completion without an async runtime, so must not await.
+///
+/// # Safety
+///
+/// `ptr` and `len` must describe a buffer allocated with [`alloc`].
+pub unsafe fn handle<C, O>(state: &'static HandlerState<C>, ptr: i32, len: i32) -> i64
+where
+    C: Command<O> + 'static,
+    O: Serialize + Send,
+    C::Input: DeserializeOwned,
+    C::Error: Into<CommandError>,
+{
+    let result = unsafe { input::<C::Input, _>(ptr, len) }.and_then(|input| {
+        let command = state.take().unwrap_or_default();
+        let emit = command
+            .handle(&input)
+            .map_err(|err| WasmResult::Rejected(err.into()))?;
+        let emit = command
+            .before_commit(&input, emit)
+            .now_or_never()
+            .ok_or_else(|| {
+                WasmResult::Rejected(CommandError::internal(
+                    "async before_commit is not supported in WASM handlers",
+                ))
+            })?
+            .map_err(|err| WasmResult::Rejected(err.into()))?;
+        let (events, output) = emit.into_parts();
+        let output = output
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|err| {
+                WasmResult::Rejected(CommandError::internal(format!(
+                    "failed to serialize output: {err}"
+                )))
+            })?;
+
+        Ok(WasmEmit {
+            events: events.into_iter().map(Into::into).collect(),
+            output,
+        })
+    });
+    finish(result)
+}
+
+/// Reads and deserializes an argument written by the host.
+unsafe fn input<T: DeserializeOwned, R>(ptr: i32, len: i32) -> Result<T, WasmResult<R>> {
+    let buf = unsafe { slice::from_raw_parts(ptr as *const u8, len as usize) };
+    serde_json::from_slice(buf)
+        .map_err(|err| WasmResult::Invalid(CommandError::invalid_input(err.to_string())))
+}
+
+fn finish<T: Serialize>(result: Result<T, WasmResult<T>>) -> i64 {
+    match result {
+        Ok(value) => output(&WasmResult::Ok(value)),
+        Err(err) => output(&err),
+    }
+}
+
+/// Serializes a result into a leaked buffer, freed by the host with [`dealloc`].
+fn output<T: Serialize>(value: &T) -> i64 {
+    let buf = serde_json::to_vec(value)
+        .expect("results serialize to json")
+        .into_boxed_slice();
+    let len = buf.len();
+    let ptr = Box::into_raw(buf) as *mut u8;
+    pack(ptr as u32, len as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{domain_id::DomainIdBindings, emit::Emit, error::SerializationError};
+
+    struct TransferEvents;
+    impl EventSet for TransferEvents {
+        const EVENT_TYPES: &'static [&'static str] = &["SentFunds", "ReceivedFunds"];
+        const EVENT_DOMAIN_IDS: &'static [(&'static str, &'static [&'static str])] = &[
+            ("SentFunds", &["account_id"]),
+            ("ReceivedFunds", &["account_id"]),
+        ];
+
+        fn from_event(_: &str, _: Value) -> Option<Result<Self, SerializationError>> {
+            None
+        }
+    }
+
+    struct TransferInput;
+    impl CommandInput for TransferInput {
+        const DOMAIN_ID_BINDINGS: &'static [(&'static str, &'static str)] = &[
+            ("dest_account", "account_id"),
+            ("source_account", "account_id"),
+        ];
+
+        fn domain_id_bindings(&self) -> DomainIdBindings {
+            DomainIdBindings::new()
+        }
+    }
+
+    #[derive(Default)]
+    struct TransferFunds;
+    impl Command for TransferFunds {
+        type Query = TransferEvents;
+        type Input = TransferInput;
+        type Error = CommandError;
+
+        const EMITTED_EVENT_TYPES: &'static [&'static str] = &["SentFunds", "ReceivedFunds"];
+
+        fn apply(&mut self, _: TransferEvents, _: EventMeta) {}
+
+        fn handle(&self, _: &TransferInput) -> Result<Emit, CommandError> {
+            Ok(Emit::new())
+        }
+    }
+
+    #[test]
+    fn describes_query_and_input() {
+        let description = description::<TransferFunds, ()>();
+
+        assert_eq!(description.event_types, ["SentFunds", "ReceivedFunds"]);
+        assert_eq!(
+            description.emitted_event_types,
+            ["SentFunds", "ReceivedFunds"]
+        );
+        assert_eq!(description.event_domain_ids["SentFunds"], ["account_id"]);
+        assert_eq!(description.input_domain_ids["source_account"], "account_id");
+        assert_eq!(description.input_domain_ids.len(), 2);
+    }
+}
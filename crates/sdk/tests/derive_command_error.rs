@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use esruntime_sdk::{
+    CommandError,
+    error::{CommandError as SdkCommandError, ErrorCode},
+};
+use serde_json::json;
+
+#[derive(Debug, thiserror::Error, CommandError)]
+enum TransferError {
+    #[error("insufficient funds")]
+    #[command_error(details)]
+    InsufficientFunds { available: u64, details: String },
+    #[error("account {0} is closed")]
+    #[command_error(status = not_found, details)]
+    AccountClosed(String),
+    #[error("transfer is frozen")]
+    #[command_error(details)]
+    Frozen { err: String },
+    #[error("limits exceeded")]
+    #[command_error(details)]
+    LimitsExceeded { limits: HashMap<(u8, u8), u64> },
+}
+
+#[test]
+fn details_fields_do_not_clash_with_generated_locals() {
+    let err: SdkCommandError = TransferError::InsufficientFunds {
+        available: 10,
+        details: "overdraft disabled".to_string(),
+    }
+    .into();
+    assert_eq!(err.code, ErrorCode::Rejected);
+    assert_eq!(err.reason.as_deref(), Some("insufficient_funds"));
+    assert_eq!(err.message, "insufficient funds");
+    assert_eq!(
+        err.details,
+        Some(json!({ "available": 10, "details": "overdraft disabled" }))
+    );
+
+    let err: SdkCommandError = TransferError::Frozen {
+        err: "under review".to_string(),
+    }
+    .into();
+    assert_eq!(err.details, Some(json!({ "err": "under review" })));
+}
+
+#[test]
+fn unnamed_fields_are_reported_as_details() {
+    let err: SdkCommandError = TransferError::AccountClosed("acc-1".to_string()).into();
+    assert_eq!(err.code, ErrorCode::NotFound);
+    assert_eq!(err.reason.as_deref(), Some("account_closed"));
+    assert_eq!(err.message, "account acc-1 is closed");
+    assert_eq!(err.details, Some(json!("acc-1")));
+}
+
+#[test]
+fn unserializable_details_are_internal_errors() {
+    // Maps with non-string keys can't be serialized as JSON.
+    let err: SdkCommandError = TransferError::LimitsExceeded {
+        limits: HashMap::from([((1, 2), 3)]),
+    }
+    .into();
+    assert_eq!(err.code, ErrorCode::Internal);
+    assert!(
+        err.message
+            .starts_with("failed to serialize error details: ")
+    );
+    assert_eq!(err.details, None);
+}
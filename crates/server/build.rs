@@ -0,0 +1,8 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/esruntime/v1/esruntime.proto"], &["proto"])?;
+
+    Ok(())
+}
@@ -0,0 +1,704 @@
+use std::{collections::HashMap, time::Duration};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use thiserror::Error;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{
+    consistency::MIN_POSITION_HEADER,
+    context::{
+        CAUSATION_ID_HEADER, CORRELATION_ID_HEADER, HANDLER_VERSION_HEADER, TRACEPARENT_HEADER,
+    },
+    idempotency::{IDEMPOTENCY_REPLAYED_HEADER, IdempotencyConfig},
+    submission::SubmissionConfig,
+};
+
+/// Prefix of the environment variables read by [`CommandRouterConfig::from_env`].
+pub const ENV_PREFIX: &str = "ESRUNTIME_";
+
+/// Configuration for a [`CommandRouter`](crate::CommandRouter).
+///
+/// Built with the builder methods, or loaded from environment variables
+/// and TOML (with the `toml` feature).
+///
+/// # Example
+///
+/// ```toml
+/// body_limit = 262144
+/// timeout = 30
+/// compression = true
+/// shutdown_timeout = 10
+///
+/// [command_timeouts]
+/// transfer_funds = 5
+///
+/// [cors]
+/// allowed_origins = ["https://app.example.com"]
+///
+/// [idempotency]
+/// backend = "event_store"
+///
+/// [submission]
+/// workers = 16
+/// max_queued = 1024
+/// max_retries = 3
+/// ```
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandRouterConfig {
+    body_limit: usize,
+    #[serde(with = "duration_secs")]
+    timeout: Duration,
+    #[serde(with = "duration_secs_map")]
+    command_timeouts: HashMap<String, Duration>,
+    #[serde(deserialize_with = "validated_cors")]
+    cors: Option<CorsConfig>,
+    compression: bool,
+    deny_unknown_fields: bool,
+    idempotency: IdempotencyConfig,
+    submission: SubmissionConfig,
+    #[serde(with = "duration_secs")]
+    shutdown_timeout: Duration,
+}
+
+impl CommandRouterConfig {
+    pub fn new() -> Self {
+        CommandRouterConfig::default()
+    }
+
+    /// Loads the configuration from `ESRUNTIME_*` environment variables, using defaults for unset variables.
+    ///
+    /// | Variable | Description |
+    /// |----------|-------------|
+    /// | `ESRUNTIME_BODY_LIMIT` | Maximum request body size in bytes |
+    /// | `ESRUNTIME_TIMEOUT` | Default command timeout in seconds |
+    /// | `ESRUNTIME_COMMAND_TIMEOUTS` | Per-command timeouts, eg. `transfer_funds=5,open_account=2` |
+    /// | `ESRUNTIME_CORS_ORIGINS` | Comma separated allowed origins, or `*` for any |
+    /// | `ESRUNTIME_COMPRESSION` | Whether to compress responses |
+    /// | `ESRUNTIME_DENY_UNKNOWN_FIELDS` | Whether to reject command input with unknown fields |
+    /// | `ESRUNTIME_IDEMPOTENCY_BACKEND` | `memory`, `event_store` or `disabled` |
+    /// | `ESRUNTIME_IDEMPOTENCY_HEADER` | Header carrying the idempotency key |
+    /// | `ESRUNTIME_IDEMPOTENCY_EXPIRE_AFTER` | Seconds to cache idempotent responses for |
+    /// | `ESRUNTIME_SUBMISSION_WORKERS` | Number of asynchronously submitted commands executed concurrently |
+    /// | `ESRUNTIME_SUBMISSION_MAX_QUEUED` | Number of asynchronously submitted commands queued or running |
+    /// | `ESRUNTIME_SUBMISSION_MAX_RETRIES` | Retries of asynchronously submitted commands which conflict |
+    /// | `ESRUNTIME_SHUTDOWN_TIMEOUT` | Seconds to drain in-flight requests for on shutdown |
+    pub fn from_env() -> Result<Self, ConfigError> {
+        CommandRouterConfig::default().merge_env()
+    }
+
+    /// Overrides the configuration with any `ESRUNTIME_*` environment variables which are set.
+    ///
+    /// See [`CommandRouterConfig::from_env`] for the supported variables.
+    pub fn merge_env(self) -> Result<Self, ConfigError> {
+        self.merge_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Parses the configuration from a TOML string.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(s: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Reads and parses the configuration from a TOML file.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let s = std::fs::read_to_string(path)?;
+        CommandRouterConfig::from_toml_str(&s)
+    }
+
+    /// Sets the maximum request body size in bytes.
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    /// Sets the default timeout for commands.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Overrides the timeout for a single command.
+    pub fn with_command_timeout(mut self, command: impl Into<String>, timeout: Duration) -> Self {
+        self.command_timeouts.insert(command.into(), timeout);
+        self
+    }
+
+    /// Enables CORS, failing if the origins are invalid.
+    pub fn with_cors(mut self, cors: CorsConfig) -> Result<Self, ConfigError> {
+        cors.validate()?;
+        self.cors = Some(cors);
+        Ok(self)
+    }
+
+    /// Sets whether responses are gzip compressed when accepted by the client.
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets whether command input with unknown fields is rejected, reporting each unknown field.
+    ///
+    /// Unknown fields are ignored by default. A single command can reject them with
+    /// `#[serde(deny_unknown_fields)]` on its input.
+    pub fn with_deny_unknown_fields(mut self, deny_unknown_fields: bool) -> Self {
+        self.deny_unknown_fields = deny_unknown_fields;
+        self
+    }
+
+    /// Configures how idempotency keys are recorded.
+    pub fn with_idempotency(mut self, idempotency: IdempotencyConfig) -> Self {
+        self.idempotency = idempotency;
+        self
+    }
+
+    /// Configures the execution of commands submitted with `?async=true`.
+    pub fn with_submission(mut self, submission: SubmissionConfig) -> Self {
+        self.submission = submission;
+        self
+    }
+
+    /// Sets how long in-flight requests are given to complete on shutdown.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    pub fn body_limit(&self) -> usize {
+        self.body_limit
+    }
+
+    /// Returns the timeout for a command.
+    pub fn command_timeout(&self, command: &str) -> Duration {
+        self.command_timeouts
+            .get(command)
+            .copied()
+            .unwrap_or(self.timeout)
+    }
+
+    pub fn cors(&self) -> Option<&CorsConfig> {
+        self.cors.as_ref()
+    }
+
+    pub fn compression(&self) -> bool {
+        self.compression
+    }
+
+    pub fn deny_unknown_fields(&self) -> bool {
+        self.deny_unknown_fields
+    }
+
+    pub fn idempotency(&self) -> &IdempotencyConfig {
+        &self.idempotency
+    }
+
+    pub fn submission(&self) -> &SubmissionConfig {
+        &self.submission
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    fn merge_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let var = |name: &str| {
+            let name = format!("{ENV_PREFIX}{name}");
+            var(&name).map(|value| (name, value))
+        };
+
+        if let Some((name, value)) = var("BODY_LIMIT") {
+            self.body_limit = parse_env(&name, &value)?;
+        }
+        if let Some((name, value)) = var("TIMEOUT") {
+            self.timeout = parse_env_secs(&name, &value)?;
+        }
+        if let Some((name, value)) = var("COMMAND_TIMEOUTS") {
+            for entry in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+                let (command, secs) = entry
+                    .split_once('=')
+                    .ok_or_else(|| ConfigError::env(&name, "expected `command=seconds` pairs"))?;
+                self.command_timeouts.insert(
+                    command.trim().to_string(),
+                    parse_env_secs(&name, secs.trim())?,
+                );
+            }
+        }
+        if let Some((_, value)) = var("CORS_ORIGINS") {
+            let origins = value
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+            let cors = CorsConfig {
+                allowed_origins: origins,
+                ..self.cors.unwrap_or_default()
+            };
+            cors.validate()?;
+            self.cors = Some(cors);
+        }
+        if let Some((name, value)) = var("COMPRESSION") {
+            self.compression = parse_env(&name, &value)?;
+        }
+        if let Some((name, value)) = var("DENY_UNKNOWN_FIELDS") {
+            self.deny_unknown_fields = parse_env(&name, &value)?;
+        }
+        if let Some((name, value)) = var("IDEMPOTENCY_BACKEND") {
+            let backend = value
+                .parse()
+                .map_err(|err: String| ConfigError::env(&name, err))?;
+            self.idempotency = self.idempotency.with_backend(backend);
+        }
+        if let Some((name, value)) = var("IDEMPOTENCY_HEADER") {
+            let header = HeaderName::try_from(value)
+                .map_err(|err| ConfigError::env(&name, err.to_string()))?;
+            self.idempotency = self.idempotency.with_header(header);
+        }
+        if let Some((name, value)) = var("IDEMPOTENCY_EXPIRE_AFTER") {
+            self.idempotency = self
+                .idempotency
+                .with_expire_after(parse_env_secs(&name, &value)?);
+        }
+        if let Some((name, value)) = var("SUBMISSION_WORKERS") {
+            self.submission = self.submission.with_workers(parse_env(&name, &value)?);
+        }
+        if let Some((name, value)) = var("SUBMISSION_MAX_QUEUED") {
+            self.submission = self.submission.with_max_queued(parse_env(&name, &value)?);
+        }
+        if let Some((name, value)) = var("SUBMISSION_MAX_RETRIES") {
+            self.submission = self.submission.with_max_retries(parse_env(&name, &value)?);
+        }
+        if let Some((name, value)) = var("SHUTDOWN_TIMEOUT") {
+            self.shutdown_timeout = parse_env_secs(&name, &value)?;
+        }
+
+        Ok(self)
+    }
+}
+
+impl Default for CommandRouterConfig {
+    fn default() -> Self {
+        CommandRouterConfig {
+            body_limit: 256 * 1024,
+            timeout: Duration::from_secs(30),
+            command_timeouts: HashMap::new(),
+            cors: None,
+            compression: false,
+            deny_unknown_fields: false,
+            idempotency: IdempotencyConfig::default(),
+            submission: SubmissionConfig::default(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// CORS configuration.
+///
+/// Allows the command, correlation, idempotency, `If-Match` and `X-Min-Position` headers, and
+/// exposes the correlation, idempotency replay and `Retry-After` headers to the browser.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    allowed_origins: Vec<String>,
+    allow_credentials: bool,
+    #[serde(with = "duration_secs")]
+    max_age: Duration,
+}
+
+impl CorsConfig {
+    pub fn new() -> Self {
+        CorsConfig::default()
+    }
+
+    /// Allows requests from any origin.
+    pub fn any_origin() -> Self {
+        CorsConfig::new().allow_origin("*")
+    }
+
+    /// Adds an allowed origin, or `*` to allow any origin.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.allowed_origins.push(origin.into());
+        self
+    }
+
+    /// Sets whether credentials such as cookies are allowed.
+    pub fn allow_credentials(mut self, allow_credentials: bool) -> Self {
+        self.allow_credentials = allow_credentials;
+        self
+    }
+
+    /// Sets how long browsers may cache preflight responses for.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// Fails if an origin is not a valid header value, or credentials are allowed for any origin,
+    /// which browsers reject.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(origin) = self
+            .allowed_origins
+            .iter()
+            .find(|origin| HeaderValue::from_str(origin).is_err())
+        {
+            return Err(ConfigError::Cors(format!("invalid origin `{origin}`")));
+        }
+        if self.allow_credentials && self.allows_any_origin() {
+            return Err(ConfigError::Cors(
+                "credentials cannot be allowed for any origin".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn layer(&self, idempotency_header: &HeaderName) -> CorsLayer {
+        let allow_origin = if self.allows_any_origin() {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(self.allowed_origins.iter().map(|origin| {
+                HeaderValue::from_str(origin).expect("origins are validated when configured")
+            }))
+        };
+
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([
+                axum::http::header::CONTENT_TYPE,
+                axum::http::header::AUTHORIZATION,
+                axum::http::header::IF_MATCH,
+                MIN_POSITION_HEADER,
+                CORRELATION_ID_HEADER,
+                CAUSATION_ID_HEADER,
+                TRACEPARENT_HEADER,
+                HANDLER_VERSION_HEADER,
+                idempotency_header.clone(),
+            ])
+            .expose_headers([
+                CORRELATION_ID_HEADER,
+                CAUSATION_ID_HEADER,
+                HANDLER_VERSION_HEADER,
+                IDEMPOTENCY_REPLAYED_HEADER,
+                axum::http::header::RETRY_AFTER,
+            ])
+            .allow_credentials(self.allow_credentials)
+            .max_age(self.max_age)
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allow_credentials: false,
+            max_age: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("invalid environment variable {name}: {message}")]
+    Env { name: String, message: String },
+    #[error("invalid CORS configuration: {0}")]
+    Cors(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "toml")]
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+}
+
+impl ConfigError {
+    fn env(name: &str, message: impl Into<String>) -> Self {
+        ConfigError::Env {
+            name: name.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+fn parse_env<T>(name: &str, value: &str) -> Result<T, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|err: T::Err| ConfigError::env(name, err.to_string()))
+}
+
+fn parse_env_secs(name: &str, value: &str) -> Result<Duration, ConfigError> {
+    Duration::try_from_secs_f64(parse_env(name, value)?)
+        .map_err(|err| ConfigError::env(name, err.to_string()))
+}
+
+/// Deserializes the CORS configuration, failing if it is invalid.
+fn validated_cors<'de, D>(deserializer: D) -> Result<Option<CorsConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let cors = Option::<CorsConfig>::deserialize(deserializer)?;
+    if let Some(cors) = &cors {
+        cors.validate().map_err(serde::de::Error::custom)?;
+    }
+    Ok(cors)
+}
+
+/// (De)serializes a duration as a number of seconds.
+pub(crate) mod duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, de::Error};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(D::Error::custom)
+    }
+}
+
+mod duration_secs_map {
+    use std::{collections::HashMap, time::Duration};
+
+    use serde::{Deserialize, Deserializer, de::Error};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<HashMap<String, Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        HashMap::<String, f64>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(command, secs)| {
+                Duration::try_from_secs_f64(secs)
+                    .map(|timeout| (command, timeout))
+                    .map_err(D::Error::custom)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idempotency::IdempotencyBackend;
+
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn defaults_match_previous_hard_coded_values() {
+        let config = CommandRouterConfig::default();
+
+        assert_eq!(config.body_limit(), 256 * 1024);
+        assert_eq!(config.command_timeout("anything"), Duration::from_secs(30));
+        assert!(!config.deny_unknown_fields());
+        assert!(matches!(
+            config.idempotency().backend(),
+            IdempotencyBackend::Memory(_)
+        ));
+    }
+
+    #[test]
+    fn env_overrides_defaults() {
+        let config = CommandRouterConfig::default()
+            .merge_vars(vars(&[
+                ("ESRUNTIME_BODY_LIMIT", "1024"),
+                ("ESRUNTIME_TIMEOUT", "2.5"),
+                (
+                    "ESRUNTIME_COMMAND_TIMEOUTS",
+                    "transfer_funds=5, open_account=1",
+                ),
+                (
+                    "ESRUNTIME_CORS_ORIGINS",
+                    "https://a.example.com,https://b.example.com",
+                ),
+                ("ESRUNTIME_COMPRESSION", "true"),
+                ("ESRUNTIME_DENY_UNKNOWN_FIELDS", "true"),
+                ("ESRUNTIME_IDEMPOTENCY_BACKEND", "event_store"),
+                ("ESRUNTIME_SUBMISSION_WORKERS", "4"),
+                ("ESRUNTIME_SUBMISSION_MAX_QUEUED", "64"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.body_limit(), 1024);
+        assert_eq!(config.command_timeout("other"), Duration::from_millis(2500));
+        assert_eq!(
+            config.command_timeout("transfer_funds"),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            config.command_timeout("open_account"),
+            Duration::from_secs(1)
+        );
+        assert_eq!(config.cors().unwrap().allowed_origins.len(), 2);
+        assert!(config.compression());
+        assert!(config.deny_unknown_fields());
+        assert!(matches!(
+            config.idempotency().backend(),
+            IdempotencyBackend::EventStore
+        ));
+        assert_eq!(config.submission().workers(), 4);
+        assert_eq!(config.submission().max_queued(), 64);
+    }
+
+    #[test]
+    fn invalid_cors_is_rejected() {
+        let result = CommandRouterConfig::default().merge_vars(vars(&[(
+            "ESRUNTIME_CORS_ORIGINS",
+            "https://a.example.com,bad\norigin",
+        )]));
+        assert!(matches!(result, Err(ConfigError::Cors(message)) if message.contains("bad")));
+
+        let result =
+            CommandRouterConfig::new().with_cors(CorsConfig::any_origin().allow_credentials(true));
+        assert!(matches!(result, Err(ConfigError::Cors(_))));
+
+        let config = CommandRouterConfig::new()
+            .with_cors(
+                CorsConfig::new()
+                    .allow_origin("https://app.example.com")
+                    .allow_credentials(true),
+            )
+            .unwrap();
+        let _ = config
+            .cors()
+            .unwrap()
+            .layer(&HeaderName::from_static("x-idempotency-key"));
+    }
+
+    #[tokio::test]
+    async fn cors_allows_if_match_preflights() {
+        use axum::{Router, body::Body, http::Request, routing::post};
+        use tower::ServiceExt;
+
+        let cors = CorsConfig::new().allow_origin("https://app.example.com");
+        let router = Router::new()
+            .route("/commands/move_task", post(|| async { "ok" }))
+            .layer(cors.layer(&HeaderName::from_static("x-idempotency-key")));
+
+        let resp = router
+            .oneshot(
+                Request::options("/commands/move_task")
+                    .header("origin", "https://app.example.com")
+                    .header("access-control-request-method", "POST")
+                    .header(
+                        "access-control-request-headers",
+                        "if-match, x-min-position, x-handler-version",
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let allowed = resp.headers()["access-control-allow-headers"]
+            .to_str()
+            .unwrap();
+        assert!(allowed.contains("if-match"), "{allowed}");
+        assert!(allowed.contains("x-min-position"), "{allowed}");
+        assert!(allowed.contains("x-handler-version"), "{allowed}");
+    }
+
+    #[tokio::test]
+    async fn cors_exposes_retry_after() {
+        use axum::{Router, body::Body, http::Request, routing::get};
+        use tower::ServiceExt;
+
+        let cors = CorsConfig::new().allow_origin("https://app.example.com");
+        let router = Router::new()
+            .route("/accounts/alice", get(|| async { "ok" }))
+            .layer(cors.layer(&HeaderName::from_static("x-idempotency-key")));
+
+        let resp = router
+            .oneshot(
+                Request::get("/accounts/alice")
+                    .header("origin", "https://app.example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let exposed = resp.headers()["access-control-expose-headers"]
+            .to_str()
+            .unwrap();
+        assert!(exposed.contains("retry-after"), "{exposed}");
+        assert!(exposed.contains("x-handler-version"), "{exposed}");
+    }
+
+    #[test]
+    fn invalid_env_is_rejected() {
+        let result =
+            CommandRouterConfig::default().merge_vars(vars(&[("ESRUNTIME_BODY_LIMIT", "lots")]));
+
+        assert!(
+            matches!(result, Err(ConfigError::Env { name, .. }) if name == "ESRUNTIME_BODY_LIMIT")
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn parses_toml() {
+        let config = CommandRouterConfig::from_toml_str(
+            r#"
+            body_limit = 1024
+            timeout = 10
+            compression = true
+
+            [command_timeouts]
+            transfer_funds = 5
+
+            [cors]
+            allowed_origins = ["*"]
+
+            [idempotency]
+            backend = "disabled"
+            expire_after = 60
+            ignored_status_codes = [409, 422]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.body_limit(), 1024);
+        assert_eq!(config.command_timeout("other"), Duration::from_secs(10));
+        assert_eq!(
+            config.command_timeout("transfer_funds"),
+            Duration::from_secs(5)
+        );
+        assert!(config.compression());
+        assert!(matches!(
+            config.idempotency().backend(),
+            IdempotencyBackend::Disabled
+        ));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn rejects_credentials_for_any_origin_in_toml() {
+        let result = CommandRouterConfig::from_toml_str(
+            r#"
+            [cors]
+            allowed_origins = ["*"]
+            allow_credentials = true
+            "#,
+        );
+
+        assert!(matches!(result, Err(ConfigError::Toml(_))));
+    }
+}
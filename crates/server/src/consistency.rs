@@ -0,0 +1,220 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, header::RETRY_AFTER},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::error::{Error, ErrorStatus};
+
+/// Header carrying the position a read must reflect, typically the `position` of a command response.
+pub const MIN_POSITION_HEADER: HeaderName = HeaderName::from_static("x-min-position");
+
+/// Something which can wait for a read model to process a position.
+pub trait PositionWaiter: Send + Sync + 'static {
+    /// Waits until `position` has been processed, returning `false` if `timeout` elapses first.
+    fn wait_for_position(
+        &self,
+        position: u64,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+}
+
+#[cfg(feature = "postgres")]
+impl PositionWaiter for esruntime_postgres::ProjectionStatus {
+    async fn wait_for_position(&self, position: u64, timeout: Duration) -> Result<bool, Error> {
+        Ok(
+            esruntime_postgres::ProjectionStatus::wait_for_position(self, position, timeout)
+                .await
+                .is_ok(),
+        )
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl PositionWaiter for esruntime_postgres::CheckpointTable {
+    async fn wait_for_position(&self, position: u64, timeout: Duration) -> Result<bool, Error> {
+        match esruntime_postgres::CheckpointTable::wait_for_position(self, position, timeout).await
+        {
+            Ok(()) => Ok(true),
+            Err(esruntime_postgres::WaitError::Timeout(_)) => Ok(false),
+            Err(esruntime_postgres::WaitError::Sqlx(err)) => {
+                tracing::error!("failed to load checkpoint: {err}");
+                Err(Error::new(ErrorStatus::Internal, "checkpoint_unavailable"))
+            }
+        }
+    }
+}
+
+/// State for the [`require_min_position`] middleware.
+///
+/// Waits up to 5 seconds by default, asking clients to retry after 1 second.
+pub struct MinPosition<W> {
+    waiter: Arc<W>,
+    timeout: Duration,
+    retry_after: Duration,
+}
+
+impl<W: PositionWaiter> MinPosition<W> {
+    pub fn new(waiter: W) -> Self {
+        MinPosition {
+            waiter: Arc::new(waiter),
+            timeout: Duration::from_secs(5),
+            retry_after: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets how long a request waits for the position before failing.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the `Retry-After` duration sent when the position is not reached in time.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+}
+
+impl<W> Clone for MinPosition<W> {
+    fn clone(&self) -> Self {
+        MinPosition {
+            waiter: self.waiter.clone(),
+            timeout: self.timeout,
+            retry_after: self.retry_after,
+        }
+    }
+}
+
+/// Middleware delaying requests with an `X-Min-Position` header until the position has been processed.
+///
+/// Responds with `503 Service Unavailable` and a `Retry-After` header if the position
+/// is not reached in time. Requests without the header are passed straight through.
+///
+/// # Example
+///
+/// ```ignore
+/// let queries = Router::new()
+///     .route("/accounts/{id}", get(get_account))
+///     .layer(axum::middleware::from_fn_with_state(
+///         MinPosition::new(projection.status()),
+///         require_min_position,
+///     ));
+/// ```
+pub async fn require_min_position<W: PositionWaiter>(
+    State(min_position): State<MinPosition<W>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let Some(position) = min_position_header(request.headers())? else {
+        return Ok(next.run(request).await);
+    };
+
+    if !min_position
+        .waiter
+        .wait_for_position(position, min_position.timeout)
+        .await?
+    {
+        let retry_after = min_position.retry_after.as_secs().max(1);
+        return Err(Error::new(ErrorStatus::Unavailable, "position_not_reached")
+            .with_message(format!(
+                "position {position} was not processed within {:?}",
+                min_position.timeout
+            ))
+            .with_header(RETRY_AFTER, retry_after));
+    }
+
+    Ok(next.run(request).await)
+}
+
+fn min_position_header(headers: &HeaderMap) -> Result<Option<u64>, Error> {
+    let Some(value) = headers.get(&MIN_POSITION_HEADER) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .map(Some)
+        .ok_or_else(|| {
+            Error::new(ErrorStatus::InvalidInput, "invalid_header")
+                .with_message(format!("{MIN_POSITION_HEADER} header must be a position"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        middleware::from_fn_with_state,
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    struct FixedPosition(AtomicU64);
+
+    impl PositionWaiter for FixedPosition {
+        async fn wait_for_position(
+            &self,
+            position: u64,
+            _timeout: Duration,
+        ) -> Result<bool, Error> {
+            Ok(self.0.load(Ordering::Acquire) >= position)
+        }
+    }
+
+    fn router(position: u64) -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(from_fn_with_state(
+                MinPosition::new(FixedPosition(AtomicU64::new(position))),
+                require_min_position,
+            ))
+    }
+
+    async fn get_with_min_position(position: u64, min_position: Option<&str>) -> Response {
+        let mut request = Request::get("/");
+        if let Some(min_position) = min_position {
+            request = request.header(MIN_POSITION_HEADER, min_position);
+        }
+        router(position)
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn passes_through_without_header() {
+        let resp = get_with_min_position(0, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn passes_through_when_position_reached() {
+        let resp = get_with_min_position(10, Some("10")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn unavailable_when_position_not_reached() {
+        let resp = get_with_min_position(9, Some("10")).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[RETRY_AFTER], "1");
+    }
+
+    #[tokio::test]
+    async fn invalid_header_is_rejected() {
+        let resp = get_with_min_position(10, Some("soon")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
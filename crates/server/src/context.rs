@@ -0,0 +1,306 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderName, HeaderValue, header::IF_MATCH, request::Parts},
+};
+use esruntime_sdk::command::CommandContext;
+use uuid::Uuid;
+
+use crate::error::{Error, ErrorStatus};
+
+/// Header carrying the correlation ID shared by every command in a request chain.
+pub const CORRELATION_ID_HEADER: HeaderName = HeaderName::from_static("x-correlation-id");
+/// Header carrying the ID of the event or command which caused this request.
+pub const CAUSATION_ID_HEADER: HeaderName = HeaderName::from_static("x-causation-id");
+/// W3C trace context header, used as a fallback source for the correlation ID.
+pub const TRACEPARENT_HEADER: HeaderName = HeaderName::from_static("traceparent");
+/// Header selecting the version of a command handler, echoed with the version which executed.
+pub const HANDLER_VERSION_HEADER: HeaderName = HeaderName::from_static("x-handler-version");
+
+/// Extracts a [`CommandContext`] from the request headers.
+///
+/// - `X-Correlation-Id` continues an existing correlation.
+/// - `traceparent` is used when no correlation ID is given, taking the trace ID as the correlation ID.
+/// - `X-Causation-Id` marks the command as triggered by an upstream event or command.
+/// - `If-Match` sets the position the client last observed, eg. `If-Match: "12847"`, rejecting
+///   the command with `412 Precondition Failed` if matching events were appended since.
+///
+/// When no headers are present, a fresh context is generated.
+#[derive(Clone, Debug)]
+pub struct RequestContext(pub CommandContext);
+
+impl RequestContext {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, Error> {
+        let correlation_id = match parse_uuid_header(headers, &CORRELATION_ID_HEADER)? {
+            Some(correlation_id) => Some(correlation_id),
+            None => headers
+                .get(&TRACEPARENT_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_traceparent_trace_id),
+        };
+        let causation_id = parse_uuid_header(headers, &CAUSATION_ID_HEADER)?;
+        let expected_position = parse_if_match(headers)?;
+
+        let mut context = match (correlation_id, causation_id) {
+            (Some(correlation_id), Some(causation_id)) => {
+                CommandContext::triggered_by_event(causation_id, correlation_id)
+            }
+            (Some(correlation_id), None) => CommandContext::with_correlation_id(correlation_id),
+            (None, Some(causation_id)) => {
+                let mut context = CommandContext::new();
+                context.triggered_by = Some(causation_id);
+                context
+            }
+            (None, None) => CommandContext::new(),
+        };
+        context.expected_position = expected_position;
+
+        Ok(RequestContext(context))
+    }
+
+    /// Headers echoing the context back to the caller.
+    ///
+    /// `X-Causation-Id` is set to the command ID, which is the causation ID of any emitted events.
+    pub fn response_headers(&self) -> HeaderMap {
+        context_headers(&self.0)
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        RequestContext::from_headers(&parts.headers)
+    }
+}
+
+pub(crate) fn context_headers(context: &CommandContext) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CORRELATION_ID_HEADER,
+        uuid_header_value(context.correlation_id),
+    );
+    headers.insert(CAUSATION_ID_HEADER, uuid_header_value(context.command_id));
+    if let Some(version) = context
+        .handler_version
+        .as_deref()
+        .and_then(|version| HeaderValue::from_str(version).ok())
+    {
+        headers.insert(HANDLER_VERSION_HEADER, version);
+    }
+    headers
+}
+
+pub(crate) fn handler_version_not_found(command: &str, version: &str) -> Error {
+    Error::new(ErrorStatus::NotFound, "handler_version_not_found").with_message(format!(
+        "`{command}` has no handler with version `{version}`"
+    ))
+}
+
+/// Parses the handler version requested with an `X-Handler-Version` header.
+pub(crate) fn handler_version(headers: &HeaderMap) -> Result<Option<&str>, Error> {
+    let Some(value) = headers.get(HANDLER_VERSION_HEADER) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|version| !version.is_empty())
+        .map(Some)
+        .ok_or_else(|| {
+            Error::new(ErrorStatus::InvalidInput, "invalid_header")
+                .with_message(format!("{HANDLER_VERSION_HEADER} header must be a version"))
+        })
+}
+
+fn uuid_header_value(id: Uuid) -> HeaderValue {
+    HeaderValue::from_str(&id.to_string()).expect("uuids are valid header values")
+}
+
+fn parse_uuid_header(headers: &HeaderMap, name: &HeaderName) -> Result<Option<Uuid>, Error> {
+    let Some(value) = headers.get(name) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| Uuid::try_parse(value.trim()).ok())
+        .map(Some)
+        .ok_or_else(|| {
+            Error::new(ErrorStatus::InvalidInput, "invalid_header")
+                .with_message(format!("{name} header must be a valid uuid"))
+        })
+}
+
+/// Parses the expected position from an `If-Match` header, which may be quoted like an entity tag.
+fn parse_if_match(headers: &HeaderMap) -> Result<Option<u64>, Error> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .map(|value| value.trim().trim_start_matches("W/").trim_matches('"'))
+        .and_then(|value| value.parse().ok())
+        .map(Some)
+        .ok_or_else(|| {
+            Error::new(ErrorStatus::InvalidInput, "invalid_header")
+                .with_message(format!("{IF_MATCH} header must be an event position"))
+        })
+}
+
+/// Parses the trace ID from a `traceparent` header (`{version}-{trace-id}-{parent-id}-{flags}`).
+///
+/// Returns `None` for malformed headers or the all-zero trace ID, which the spec marks as invalid.
+fn parse_traceparent_trace_id(traceparent: &str) -> Option<Uuid> {
+    let mut parts = traceparent.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+
+    if version.len() != 2
+        || version == "ff"
+        || trace_id.len() != 32
+        || parent_id.len() != 16
+        || flags.len() != 2
+    {
+        return None;
+    }
+
+    let trace_id = Uuid::try_parse(trace_id).ok()?;
+    if trace_id.is_nil() {
+        return None;
+    }
+
+    Some(trace_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| ((*name).clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn generates_context_without_headers() {
+        let RequestContext(context) = RequestContext::from_headers(&HeaderMap::new()).unwrap();
+
+        assert_eq!(context.command_id, context.correlation_id);
+        assert_eq!(context.triggered_by, None);
+    }
+
+    #[test]
+    fn continues_correlation_id() {
+        let correlation_id = Uuid::new_v4();
+        let RequestContext(context) = RequestContext::from_headers(&headers(&[(
+            &CORRELATION_ID_HEADER,
+            &correlation_id.to_string(),
+        )]))
+        .unwrap();
+
+        assert_eq!(context.correlation_id, correlation_id);
+        assert_ne!(context.command_id, correlation_id);
+        assert_eq!(context.triggered_by, None);
+    }
+
+    #[test]
+    fn causation_id_sets_triggered_by() {
+        let correlation_id = Uuid::new_v4();
+        let causation_id = Uuid::new_v4();
+        let RequestContext(context) = RequestContext::from_headers(&headers(&[
+            (&CORRELATION_ID_HEADER, &correlation_id.to_string()),
+            (&CAUSATION_ID_HEADER, &causation_id.to_string()),
+        ]))
+        .unwrap();
+
+        assert_eq!(context.correlation_id, correlation_id);
+        assert_eq!(context.triggered_by, Some(causation_id));
+    }
+
+    #[test]
+    fn traceparent_is_correlation_fallback() {
+        let RequestContext(context) = RequestContext::from_headers(&headers(&[(
+            &TRACEPARENT_HEADER,
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )]))
+        .unwrap();
+
+        assert_eq!(
+            context.correlation_id,
+            Uuid::parse_str("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+    }
+
+    #[test]
+    fn correlation_header_takes_precedence_over_traceparent() {
+        let correlation_id = Uuid::new_v4();
+        let RequestContext(context) = RequestContext::from_headers(&headers(&[
+            (&CORRELATION_ID_HEADER, &correlation_id.to_string()),
+            (
+                &TRACEPARENT_HEADER,
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ),
+        ]))
+        .unwrap();
+
+        assert_eq!(context.correlation_id, correlation_id);
+    }
+
+    #[test]
+    fn invalid_traceparent_is_ignored() {
+        for traceparent in [
+            "garbage",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6-00f067aa0ba902b7-01",
+        ] {
+            let RequestContext(context) =
+                RequestContext::from_headers(&headers(&[(&TRACEPARENT_HEADER, traceparent)]))
+                    .unwrap();
+            assert_eq!(context.command_id, context.correlation_id);
+        }
+    }
+
+    #[test]
+    fn invalid_correlation_id_is_rejected() {
+        let result =
+            RequestContext::from_headers(&headers(&[(&CORRELATION_ID_HEADER, "not-a-uuid")]));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn if_match_sets_expected_position() {
+        for if_match in ["12847", "\"12847\"", "W/\"12847\""] {
+            let RequestContext(context) =
+                RequestContext::from_headers(&headers(&[(&IF_MATCH, if_match)])).unwrap();
+            assert_eq!(context.expected_position, Some(12847));
+        }
+
+        let result = RequestContext::from_headers(&headers(&[(&IF_MATCH, "*")]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn handler_version_is_echoed() {
+        let request = headers(&[(&HANDLER_VERSION_HEADER, " 1.2.0 ")]);
+        assert_eq!(handler_version(&request).unwrap(), Some("1.2.0"));
+        assert_eq!(handler_version(&HeaderMap::new()).unwrap(), None);
+
+        let context = CommandContext::new().handler_version("1.2.0");
+        let response = context_headers(&context);
+        assert_eq!(response[HANDLER_VERSION_HEADER], "1.2.0");
+    }
+}
@@ -5,16 +5,27 @@ use axum::{
     http::{HeaderMap, HeaderValue, StatusCode, header::IntoHeaderName},
     response::{IntoResponse, Response},
 };
-use esruntime_sdk::error::{CommandError, ErrorCode, ExecuteError, SerializationError};
+use esruntime_sdk::{
+    command::CommandContext,
+    error::{CommandError, ErrorCode, ExecuteError, FieldError, SerializationError},
+};
 use serde::Serialize;
+use serde_json::Value;
 use umadb_dcb::DCBError;
+use uuid::Uuid;
+
+use crate::context::context_headers;
 
+#[derive(Debug)]
 pub struct Error {
     status_code: StatusCode,
-    headers: HeaderMap,
+    headers: Box<HeaderMap>,
     status: ErrorStatus,
     code: String,
     message: Option<String>,
+    errors: Vec<FieldError>,
+    details: Option<Box<Value>>,
+    request_id: Option<Uuid>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -34,9 +45,15 @@ pub enum ErrorStatus {
     /// Request conflicts with current state (409)
     Conflict,
 
+    /// State changed since the position the client expected (412)
+    PreconditionFailed,
+
     /// Valid request but business rules rejected it (422)
     Rejected,
 
+    /// A command handler exceeded its resource limits (422)
+    ResourceExhausted,
+
     /// Server-side error (500)
     Internal,
 
@@ -45,6 +62,20 @@ pub enum ErrorStatus {
 }
 
 impl ErrorStatus {
+    /// Every status, in order of status code.
+    pub const ALL: [ErrorStatus; 10] = [
+        ErrorStatus::InvalidInput,
+        ErrorStatus::Unauthorized,
+        ErrorStatus::Forbidden,
+        ErrorStatus::NotFound,
+        ErrorStatus::Conflict,
+        ErrorStatus::PreconditionFailed,
+        ErrorStatus::Rejected,
+        ErrorStatus::ResourceExhausted,
+        ErrorStatus::Internal,
+        ErrorStatus::Unavailable,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             ErrorStatus::InvalidInput => "invalid_input",
@@ -52,7 +83,9 @@ impl ErrorStatus {
             ErrorStatus::Forbidden => "forbidden",
             ErrorStatus::NotFound => "not_found",
             ErrorStatus::Conflict => "conflict",
+            ErrorStatus::PreconditionFailed => "precondition_failed",
             ErrorStatus::Rejected => "rejected",
+            ErrorStatus::ResourceExhausted => "resource_exhausted",
             ErrorStatus::Internal => "internal",
             ErrorStatus::Unavailable => "unavailable",
         }
@@ -65,11 +98,30 @@ impl ErrorStatus {
             ErrorStatus::Forbidden => StatusCode::FORBIDDEN,
             ErrorStatus::NotFound => StatusCode::NOT_FOUND,
             ErrorStatus::Conflict => StatusCode::CONFLICT,
+            ErrorStatus::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
             ErrorStatus::Rejected => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorStatus::ResourceExhausted => StatusCode::UNPROCESSABLE_ENTITY,
             ErrorStatus::Internal => StatusCode::INTERNAL_SERVER_ERROR,
             ErrorStatus::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
+
+    #[cfg(feature = "grpc")]
+    pub fn grpc_code(&self) -> tonic::Code {
+        match self {
+            ErrorStatus::InvalidInput => tonic::Code::InvalidArgument,
+            ErrorStatus::Unauthorized => tonic::Code::Unauthenticated,
+            ErrorStatus::Forbidden => tonic::Code::PermissionDenied,
+            ErrorStatus::NotFound => tonic::Code::NotFound,
+            ErrorStatus::Conflict => tonic::Code::Aborted,
+            ErrorStatus::PreconditionFailed => tonic::Code::FailedPrecondition,
+            ErrorStatus::Rejected => tonic::Code::FailedPrecondition,
+            // Not `ResourceExhausted`, which gRPC clients treat as retryable.
+            ErrorStatus::ResourceExhausted => tonic::Code::FailedPrecondition,
+            ErrorStatus::Internal => tonic::Code::Internal,
+            ErrorStatus::Unavailable => tonic::Code::Unavailable,
+        }
+    }
 }
 
 impl fmt::Display for ErrorStatus {
@@ -82,13 +134,20 @@ impl Error {
     pub fn new(status: ErrorStatus, code: impl Into<String>) -> Self {
         Error {
             status_code: status.status_code(),
-            headers: HeaderMap::new(),
+            headers: Box::default(),
             status,
             code: code.into(),
             message: None,
+            errors: Vec::new(),
+            details: None,
+            request_id: None,
         }
     }
 
+    pub fn status(&self) -> ErrorStatus {
+        self.status
+    }
+
     pub fn with_status_code(mut self, status_code: StatusCode) -> Self {
         self.status_code = status_code;
         self
@@ -103,55 +162,136 @@ impl Error {
         self.message = Some(msg.into());
         self
     }
+
+    /// Attaches errors of individual input fields.
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// Attaches structured details, eg. the available and requested balance.
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(Box::new(details));
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: Uuid) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
+    /// Attaches the command ID as the request ID, and echoes the correlation headers.
+    pub fn with_context(mut self, context: &CommandContext) -> Self {
+        self.headers.extend(context_headers(context));
+        self.with_request_id(context.command_id)
+    }
+
+    /// Returns the body of the error response.
+    pub(crate) fn body(&self) -> ErrorBody<'_> {
+        ErrorBody {
+            status: self.status.as_str(),
+            code: &self.code,
+            message: self.message.as_deref(),
+            errors: &self.errors,
+            details: self.details.as_deref(),
+            request_id: self.request_id,
+        }
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        #[derive(Serialize)]
-        struct Body {
-            status: &'static str,
-            code: String,
-            #[serde(skip_serializing_if = "Option::is_none")]
-            message: Option<String>,
+        let body = Json(self.body()).into_response();
+        (self.status_code, *self.headers, body).into_response()
+    }
+}
+
+/// The JSON body of an error response.
+#[derive(Serialize)]
+pub(crate) struct ErrorBody<'a> {
+    status: &'static str,
+    code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [FieldError],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<Uuid>,
+}
+
+/// Converts to a gRPC status, with the error code in the `x-error-code` metadata.
+#[cfg(feature = "grpc")]
+impl From<Error> for tonic::Status {
+    fn from(err: Error) -> Self {
+        let mut headers = *err.headers;
+        if let Ok(code) = HeaderValue::from_str(&err.code) {
+            headers.insert(crate::grpc::ERROR_CODE_METADATA, code);
         }
 
-        (
-            self.status_code,
-            self.headers,
-            Json(Body {
-                status: self.status.as_str(),
-                code: self.code,
-                message: self.message,
-            }),
+        tonic::Status::with_metadata(
+            err.status.grpc_code(),
+            err.message.unwrap_or(err.code),
+            tonic::metadata::MetadataMap::from_headers(headers),
         )
-            .into_response()
     }
 }
 
-impl<E: std::error::Error> From<ExecuteError<E>> for Error {
+impl<E: Into<CommandError>> From<ExecuteError<E>> for Error {
     fn from(err: ExecuteError<E>) -> Self {
         match err {
             ExecuteError::Command(err) => {
-                Error::new(ErrorStatus::Rejected, "command_rejected").with_message(err.to_string())
+                let err: CommandError = err.into();
+                err.into()
             }
+            // Validation failures keep their status, with the failure reported as a field error
+            // when the handler did not attribute it to a field.
             ExecuteError::Validation(err) => {
-                Error::new(ErrorStatus::Rejected, "command_rejected").with_message(err.to_string())
+                let mut err: CommandError = err.into();
+                if err.fields.is_empty() {
+                    err.fields = vec![FieldError::new("", err.message.clone())];
+                }
+                err.into()
             }
             ExecuteError::DCB(err) => err.into(),
             ExecuteError::Serialization(err) => err.into(),
+            ExecuteError::StalePosition { expected } => {
+                Error::new(ErrorStatus::PreconditionFailed, "stale_position").with_message(format!(
+                    "events were appended after the expected position {expected}"
+                ))
+            }
         }
     }
 }
 
+/// Uses the reason as the error code when set, otherwise a generic code like `command_rejected`.
 impl From<CommandError> for Error {
     fn from(err: CommandError) -> Self {
         let status = match err.code {
             ErrorCode::Rejected => ErrorStatus::Rejected,
             ErrorCode::InvalidInput => ErrorStatus::InvalidInput,
+            ErrorCode::Unauthorized => ErrorStatus::Unauthorized,
+            ErrorCode::Forbidden => ErrorStatus::Forbidden,
+            ErrorCode::NotFound => ErrorStatus::NotFound,
+            ErrorCode::Conflict => ErrorStatus::Conflict,
+            ErrorCode::PreconditionFailed => ErrorStatus::PreconditionFailed,
             ErrorCode::Internal => ErrorStatus::Internal,
+            ErrorCode::Unavailable => ErrorStatus::Unavailable,
         };
-
-        Error::new(status, format!("command_{}", err.code)).with_message(err.message)
+        let code = err
+            .reason
+            .unwrap_or_else(|| format!("command_{}", err.code));
+
+        let mut error = Error::new(status, code)
+            .with_message(err.message)
+            .with_errors(err.fields);
+        error.details = err.details.map(Box::new);
+        error
     }
 }
 
@@ -0,0 +1,214 @@
+//! gRPC command API, served alongside the HTTP routes.
+//!
+//! Exposes the registered commands through a generic `esruntime.v1.Commands` service
+//! (see `proto/esruntime/v1/esruntime.proto`), with errors mapped to gRPC status codes
+//! by their [`ErrorStatus`].
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Router,
+    http::{HeaderMap, HeaderName, HeaderValue},
+};
+use futures_util::{StreamExt, stream::BoxStream};
+use serde_json::Value;
+use tonic::{Request, Response, Status, server::NamedService};
+use umadb_dcb::DCBEvent;
+
+use crate::{
+    CommandState, CommandVersions,
+    config::CommandRouterConfig,
+    context::RequestContext,
+    error::{Error, ErrorStatus},
+    stream::{self, StreamParams},
+};
+
+pub mod proto {
+    tonic::include_proto!("esruntime.v1");
+}
+
+use proto::{
+    ExecuteRequest, ExecuteResponse, SubscribeRequest,
+    commands_server::{Commands, CommandsServer},
+    execute_request,
+};
+
+/// Metadata key carrying the error code of a failed call, eg. `command_rejected`.
+pub const ERROR_CODE_METADATA: HeaderName = HeaderName::from_static("x-error-code");
+
+/// Adds the gRPC service to the router.
+pub(crate) fn route(
+    router: Router,
+    commands: HashMap<String, CommandVersions>,
+    state: CommandState,
+    config: &CommandRouterConfig,
+) -> Router {
+    let service = CommandsServer::new(GrpcCommands {
+        commands: Arc::new(commands),
+        state,
+        config: config.clone(),
+    })
+    .max_decoding_message_size(config.body_limit());
+
+    router.route_service(
+        &format!("/{}/{{*rest}}", CommandsServer::<GrpcCommands>::NAME),
+        service,
+    )
+}
+
+pub(crate) struct GrpcCommands {
+    commands: Arc<HashMap<String, CommandVersions>>,
+    state: CommandState,
+    config: CommandRouterConfig,
+}
+
+#[tonic::async_trait]
+impl Commands for GrpcCommands {
+    async fn execute(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<ExecuteResponse>, Status> {
+        let mut headers = request.metadata().clone().into_headers();
+        let request = request.into_inner();
+        headers.extend(metadata_headers(request.metadata)?);
+
+        let execute = self
+            .commands
+            .get(&request.command)
+            .ok_or_else(|| {
+                Error::new(ErrorStatus::NotFound, "command_not_found")
+                    .with_message(format!("command `{}` is not registered", request.command))
+            })?
+            .executor(&request.command, &headers)?;
+        let input = match request.input {
+            Some(execute_request::Input::Json(json)) => serde_json::from_str(&json),
+            Some(execute_request::Input::Bytes(bytes)) => serde_json::from_slice(&bytes),
+            None => Ok(Value::Null),
+        }
+        .map_err(|err| {
+            Error::new(ErrorStatus::InvalidInput, "invalid_command").with_message(err.to_string())
+        })?;
+        let RequestContext(context) = RequestContext::from_headers(&headers)?;
+
+        let timeout = self.config.command_timeout(&request.command);
+        let (context, result) = tokio::time::timeout(
+            timeout,
+            execute(self.state.clone(), context, headers, input),
+        )
+        .await
+        .map_err(|_| Status::deadline_exceeded("command timed out"))??;
+
+        Ok(Response::new(ExecuteResponse {
+            events: result
+                .events
+                .into_iter()
+                .map(|event| event_proto(event, None))
+                .collect(),
+            result: result.output.map(|output| output.to_string()),
+            position: result.position,
+            correlation_id: context.correlation_id.to_string(),
+            causation_id: context.command_id.to_string(),
+            replayed: result.replayed,
+        }))
+    }
+
+    type SubscribeStream = BoxStream<'static, Result<proto::Event, Status>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let request = request.into_inner();
+        let params = StreamParams {
+            event_types: Some(request.event_types.join(",")),
+            domain_ids: Some(request.domain_ids.join(",")),
+            after_position: request.after_position,
+        };
+
+        let events =
+            stream::subscribe(&self.state.umadb_client, &params, &HeaderMap::new()).await?;
+        let events = events.map(|result| {
+            result
+                .map(|event| event_proto(event.event, Some(event.position)))
+                .map_err(|err| Status::from(Error::from(err)))
+        });
+
+        Ok(Response::new(events.boxed()))
+    }
+}
+
+/// Parses the metadata of an execute request into headers.
+fn metadata_headers(metadata: HashMap<String, String>) -> Result<HeaderMap, Error> {
+    metadata
+        .into_iter()
+        .map(|(key, value)| {
+            let name = HeaderName::try_from(key.as_str()).map_err(|_| {
+                Error::new(ErrorStatus::InvalidInput, "invalid_metadata")
+                    .with_message(format!("`{key}` is not a valid metadata key"))
+            })?;
+            let value = HeaderValue::try_from(value).map_err(|_| {
+                Error::new(ErrorStatus::InvalidInput, "invalid_metadata")
+                    .with_message(format!("`{key}` metadata value is invalid"))
+            })?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+fn event_proto(event: DCBEvent, position: Option<u64>) -> proto::Event {
+    proto::Event {
+        id: event.uuid.map(|id| id.to_string()),
+        r#type: event.event_type,
+        data: event.data,
+        tags: event.tags,
+        position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_is_parsed_as_headers() {
+        let metadata = HashMap::from([(
+            "X-Correlation-Id".to_string(),
+            "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b".to_string(),
+        )]);
+        let headers = metadata_headers(metadata).unwrap();
+        let RequestContext(context) = RequestContext::from_headers(&headers).unwrap();
+
+        assert_eq!(
+            context.correlation_id.to_string(),
+            "0b7e6f0c-3c1f-4b8e-9d6a-8f0f4f1d2a3b"
+        );
+    }
+
+    #[test]
+    fn invalid_metadata_is_rejected() {
+        let metadata = HashMap::from([("not a header".to_string(), "value".to_string())]);
+        let err = metadata_headers(metadata).unwrap_err();
+
+        assert_eq!(err.status(), ErrorStatus::InvalidInput);
+    }
+
+    #[test]
+    fn errors_map_to_grpc_codes() {
+        let status = Status::from(
+            Error::new(ErrorStatus::Rejected, "command_rejected")
+                .with_message("insufficient funds"),
+        );
+
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(status.message(), "insufficient funds");
+        assert_eq!(
+            status
+                .metadata()
+                .get(ERROR_CODE_METADATA.as_str())
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            "command_rejected"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{Json, http::StatusCode};
+use serde::Serialize;
+use umadb_client::AsyncUmaDBClient;
+use umadb_dcb::DCBEventStoreAsync;
+
+/// A component contributing to the readiness of the server.
+///
+/// The server only reports itself as ready when every check is ready,
+/// for example once a projection has caught up with the head of the event store.
+///
+/// Implemented for closures returning a [`Readiness`].
+pub trait ReadinessCheck: Send + Sync + 'static {
+    fn readiness(&self) -> Readiness;
+}
+
+impl<F> ReadinessCheck for F
+where
+    F: Fn() -> Readiness + Send + Sync + 'static,
+{
+    fn readiness(&self) -> Readiness {
+        self()
+    }
+}
+
+/// The result of a readiness check.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// How long the check took to respond, eg. the latency of pinging a dependency.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+impl Readiness {
+    pub fn ready() -> Self {
+        Readiness {
+            ready: true,
+            message: None,
+            latency_ms: None,
+        }
+    }
+
+    pub fn not_ready(message: impl Into<String>) -> Self {
+        Readiness {
+            ready: false,
+            message: Some(message.into()),
+            latency_ms: None,
+        }
+    }
+
+    /// Sets how long the check took to respond.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = Some(latency.as_secs_f64() * 1000.0);
+        self
+    }
+}
+
+/// How long `/ready` waits for UmaDB to respond before reporting it as not ready.
+const UMADB_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+pub(crate) struct HealthState {
+    pub(crate) umadb_client: Arc<AsyncUmaDBClient>,
+    /// Names of the registered commands
+    pub(crate) commands: Arc<BTreeSet<String>>,
+    #[cfg(feature = "wasm")]
+    pub(crate) wasm_runtime: Option<crate::wasm::WasmRuntime>,
+    pub(crate) readiness_checks: Arc<Vec<(String, Box<dyn ReadinessCheck>)>>,
+}
+
+impl HealthState {
+    /// Number of registered commands and uploaded handlers, counting each name once.
+    fn handlers(&self) -> usize {
+        #[cfg(feature = "wasm")]
+        if let Some(runtime) = &self.wasm_runtime {
+            let uploaded: BTreeSet<_> = runtime
+                .handlers()
+                .into_iter()
+                .map(|handler| handler.name)
+                .filter(|name| !self.commands.contains(name))
+                .collect();
+            return self.commands.len() + uploaded.len();
+        }
+
+        self.commands.len()
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct HealthResponse {
+    status: &'static str,
+    version: &'static str,
+    handlers: usize,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ReadyResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    head: Option<u64>,
+    checks: BTreeMap<String, Readiness>,
+}
+
+/// Reports the process as alive, without checking its dependencies.
+///
+/// Suitable as a liveness probe, as an outage of UmaDB does not make the server unhealthy.
+pub(crate) async fn health(state: &HealthState) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "healthy",
+        version: env!("CARGO_PKG_VERSION"),
+        handlers: state.handlers(),
+    })
+}
+
+/// Ready when UmaDB responds in time and every registered readiness check is ready.
+pub(crate) async fn ready(state: &HealthState) -> (StatusCode, Json<ReadyResponse>) {
+    let mut checks = BTreeMap::new();
+    let start = Instant::now();
+    let (umadb, head) = match tokio::time::timeout(UMADB_TIMEOUT, state.umadb_client.head()).await {
+        Ok(Ok(head)) => (Readiness::ready().with_latency(start.elapsed()), head),
+        Ok(Err(err)) => (
+            Readiness::not_ready(err.to_string()).with_latency(start.elapsed()),
+            None,
+        ),
+        Err(_) => (
+            Readiness::not_ready(format!("no response within {UMADB_TIMEOUT:?}")),
+            None,
+        ),
+    };
+    checks.insert("umadb".to_string(), umadb);
+
+    for (name, check) in state.readiness_checks.iter() {
+        checks.insert(name.clone(), check.readiness());
+    }
+
+    let ready = checks.values().all(|readiness| readiness.ready);
+    let (status_code, status) = if ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+
+    (
+        status_code,
+        Json(ReadyResponse {
+            status,
+            head,
+            checks,
+        }),
+    )
+}
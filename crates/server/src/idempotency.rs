@@ -0,0 +1,870 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Deserializer, Serialize, de};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::duration_secs,
+    error::{Error, ErrorStatus},
+};
+
+/// Default header carrying the client-supplied idempotency key.
+pub const DEFAULT_IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("x-idempotency-key");
+/// Response header set when a response is replayed for a previously used idempotency key.
+pub const IDEMPOTENCY_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotency-replayed");
+
+/// Interval at which expired responses are dropped from a [`MemoryStore`].
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Status codes which are never cached, as retrying them may succeed.
+const UNCACHED_STATUS_CODES: [StatusCode; 9] = [
+    StatusCode::BAD_REQUEST,
+    StatusCode::UNAUTHORIZED,
+    StatusCode::FORBIDDEN,
+    StatusCode::REQUEST_TIMEOUT,
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::INTERNAL_SERVER_ERROR,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// Where idempotency keys are recorded.
+#[derive(Clone)]
+pub enum IdempotencyBackend {
+    /// Responses are cached in memory by command and idempotency key.
+    ///
+    /// Keys are lost on restart and are not shared between replicas.
+    Memory(Arc<MemoryStore>),
+    /// Responses are cached in a Postgres table by command and idempotency key.
+    ///
+    /// Keys survive restarts and are shared between replicas using the same database.
+    #[cfg(feature = "postgres")]
+    Postgres(Arc<PostgresStore>),
+    /// Keys are recorded as a tag on the emitted events.
+    ///
+    /// Duplicates are caught by the append condition itself, so keys never expire and
+    /// are shared by everything writing to the event store. Only commands which emit
+    /// events are recorded; rejected commands are executed again when retried.
+    EventStore,
+    /// Idempotency keys are ignored.
+    Disabled,
+}
+
+/// Parses `memory`, `event_store` or `disabled`.
+///
+/// The Postgres backend needs a connection pool, so must be configured with [`IdempotencyConfig::postgres`].
+impl FromStr for IdempotencyBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(IdempotencyBackend::Memory(Arc::new(MemoryStore::new()))),
+            "event_store" => Ok(IdempotencyBackend::EventStore),
+            "disabled" => Ok(IdempotencyBackend::Disabled),
+            "postgres" => Err(
+                "the postgres backend must be configured with IdempotencyConfig::postgres"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "unknown idempotency backend `{s}`, expected `memory`, `event_store` or `disabled`"
+            )),
+        }
+    }
+}
+
+/// Configuration for the idempotency of command requests.
+///
+/// Defaults to an in-memory store using the `X-Idempotency-Key` header,
+/// caching responses for 5 minutes except for conflicts.
+#[derive(Clone)]
+pub struct IdempotencyConfig {
+    backend: IdempotencyBackend,
+    header: HeaderName,
+    expire_after: Duration,
+    ignored_status_codes: Vec<StatusCode>,
+}
+
+impl IdempotencyConfig {
+    pub fn new(backend: IdempotencyBackend) -> Self {
+        IdempotencyConfig {
+            backend,
+            header: DEFAULT_IDEMPOTENCY_KEY_HEADER,
+            expire_after: Duration::from_secs(60 * 5),
+            ignored_status_codes: vec![StatusCode::CONFLICT],
+        }
+    }
+
+    /// Caches responses in memory.
+    pub fn memory() -> Self {
+        IdempotencyConfig::new(IdempotencyBackend::Memory(Arc::new(MemoryStore::new())))
+    }
+
+    /// Caches responses in Postgres, creating the `idempotency_keys` table if it does not exist.
+    #[cfg(feature = "postgres")]
+    pub async fn postgres(pool: sqlx::PgPool) -> Result<Self, sqlx::Error> {
+        let store = PostgresStore::new(pool).await?;
+        Ok(IdempotencyConfig::new(IdempotencyBackend::Postgres(
+            Arc::new(store),
+        )))
+    }
+
+    /// Records idempotency keys as tags on the emitted events.
+    pub fn event_store() -> Self {
+        IdempotencyConfig::new(IdempotencyBackend::EventStore)
+    }
+
+    /// Ignores idempotency keys.
+    pub fn disabled() -> Self {
+        IdempotencyConfig::new(IdempotencyBackend::Disabled)
+    }
+
+    /// Replaces the backend, keeping the other settings.
+    pub fn with_backend(mut self, backend: IdempotencyBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets the header carrying the idempotency key.
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets how long cached responses are kept for.
+    ///
+    /// Has no effect with [`IdempotencyBackend::EventStore`], where keys never expire.
+    pub fn with_expire_after(mut self, expire_after: Duration) -> Self {
+        self.expire_after = expire_after;
+        self
+    }
+
+    /// Sets the response status codes which are not cached.
+    ///
+    /// `400`, `401`, `403`, `408`, `429`, `500`, `502`, `503` and `504` are never cached.
+    pub fn ignored_status_codes(
+        mut self,
+        status_codes: impl IntoIterator<Item = StatusCode>,
+    ) -> Self {
+        self.ignored_status_codes = status_codes.into_iter().collect();
+        self
+    }
+
+    /// Adds a response status code which is not cached.
+    pub fn ignore_status_code(mut self, status_code: StatusCode) -> Self {
+        self.ignored_status_codes.push(status_code);
+        self
+    }
+
+    pub fn backend(&self) -> &IdempotencyBackend {
+        &self.backend
+    }
+
+    pub fn header(&self) -> &HeaderName {
+        &self.header
+    }
+
+    /// The header to read into `CommandContext::idempotency_key`, if keys are recorded in the event store.
+    pub(crate) fn event_store_header(&self) -> Option<HeaderName> {
+        match self.backend {
+            IdempotencyBackend::EventStore => Some(self.header.clone()),
+            _ => None,
+        }
+    }
+
+    /// Replays the response cached for the command and idempotency key of a request, otherwise
+    /// runs `respond` and caches its response.
+    ///
+    /// The key is claimed before running `respond`, so a concurrent request with the same key
+    /// fails with `409 Conflict` instead of executing the command again. Keys are bound to a hash
+    /// of the input, and reusing a key with a different input fails with `422 Unprocessable
+    /// Entity`. Requests without an idempotency key, and backends which do not cache responses,
+    /// always run `respond`.
+    pub(crate) async fn respond<F>(
+        &self,
+        command: &str,
+        headers: &HeaderMap,
+        input: &Value,
+        respond: F,
+    ) -> Result<Response, Error>
+    where
+        F: Future<Output = Result<Response, Error>>,
+    {
+        if matches!(
+            self.backend,
+            IdempotencyBackend::EventStore | IdempotencyBackend::Disabled
+        ) {
+            return respond.await;
+        }
+        let Some(key) = idempotency_key(Some(&self.header), headers)? else {
+            return respond.await;
+        };
+
+        let request_hash = request_hash(input);
+        let claim = self
+            .claim(command, &key, &request_hash)
+            .await
+            .map_err(|err| {
+                tracing::error!("failed to claim idempotency key: {err}");
+                Error::new(ErrorStatus::Unavailable, "idempotency_unavailable")
+                    .with_message("failed to claim the idempotency key")
+            })?;
+        match claim {
+            Claim::Claimed => {}
+            Claim::Cached(cached) => return Ok(cached.into_response()),
+            Claim::InFlight => {
+                return Err(Error::new(ErrorStatus::Conflict, "idempotency_key_in_use")
+                    .with_message("a request with this idempotency key is still in progress"));
+            }
+            Claim::Mismatch => {
+                return Err(
+                    Error::new(ErrorStatus::Rejected, "idempotency_key_mismatch").with_message(
+                        "the idempotency key was already used for a request with a different body",
+                    ),
+                );
+            }
+        }
+        // Releases the claim if the request is cancelled before completing.
+        let mut claim = ClaimGuard {
+            config: self.clone(),
+            command: command.to_string(),
+            key,
+            released: false,
+        };
+
+        let response = respond.await.unwrap_or_else(IntoResponse::into_response);
+        let status = response.status();
+        if UNCACHED_STATUS_CODES.contains(&status) || self.ignored_status_codes.contains(&status) {
+            claim.released = true;
+            if let Err(err) = self.release(command, &claim.key).await {
+                tracing::error!("failed to release idempotency key: {err}");
+            }
+            return Ok(response);
+        }
+
+        let (parts, body) = response.into_parts();
+        let body = to_bytes(body, usize::MAX).await.map_err(|err| {
+            Error::new(ErrorStatus::Internal, "internal").with_message(err.to_string())
+        })?;
+        let cached = CachedResponse {
+            status: status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            body: body.to_vec(),
+        };
+        claim.released = true;
+        if let Err(err) = self.cache(command, &claim.key, &cached).await {
+            tracing::error!("failed to cache response: {err}");
+        }
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Claims a key for a request, unless a response is cached or another request claimed it.
+    async fn claim(
+        &self,
+        command: &str,
+        key: &str,
+        request_hash: &str,
+    ) -> Result<Claim, StoreError> {
+        match &self.backend {
+            IdempotencyBackend::Memory(store) => {
+                Ok(store.claim(command, key, request_hash, self.expire_after))
+            }
+            #[cfg(feature = "postgres")]
+            IdempotencyBackend::Postgres(store) => Ok(store
+                .claim(command, key, request_hash, self.expire_after)
+                .await?),
+            IdempotencyBackend::EventStore | IdempotencyBackend::Disabled => Ok(Claim::Claimed),
+        }
+    }
+
+    /// Releases a claimed key without caching a response, so the request can be retried.
+    async fn release(&self, command: &str, key: &str) -> Result<(), StoreError> {
+        match &self.backend {
+            IdempotencyBackend::Memory(store) => {
+                store.release(command, key);
+                Ok(())
+            }
+            #[cfg(feature = "postgres")]
+            IdempotencyBackend::Postgres(store) => Ok(store.release(command, key).await?),
+            IdempotencyBackend::EventStore | IdempotencyBackend::Disabled => Ok(()),
+        }
+    }
+
+    async fn cache(
+        &self,
+        command: &str,
+        key: &str,
+        response: &CachedResponse,
+    ) -> Result<(), StoreError> {
+        match &self.backend {
+            IdempotencyBackend::Memory(store) => {
+                store.insert(command, key, response.clone(), self.expire_after);
+                Ok(())
+            }
+            #[cfg(feature = "postgres")]
+            IdempotencyBackend::Postgres(store) => Ok(store
+                .insert(command, key, response, self.expire_after)
+                .await?),
+            IdempotencyBackend::EventStore | IdempotencyBackend::Disabled => Ok(()),
+        }
+    }
+}
+
+type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Result of claiming an idempotency key.
+#[derive(Debug)]
+enum Claim {
+    /// The key was claimed, so the command should be executed.
+    Claimed,
+    /// A response is cached for the key.
+    Cached(CachedResponse),
+    /// Another request claimed the key and has not completed.
+    InFlight,
+    /// The key was claimed for a request with a different body.
+    Mismatch,
+}
+
+/// Releases a claimed key when dropped before the response is cached, eg. when the client
+/// disconnects.
+struct ClaimGuard {
+    config: IdempotencyConfig,
+    command: String,
+    key: String,
+    released: bool,
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let config = self.config.clone();
+        let command = std::mem::take(&mut self.command);
+        let key = std::mem::take(&mut self.key);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(err) = config.release(&command, &key).await {
+                    tracing::error!("failed to release idempotency key: {err}");
+                }
+            });
+        }
+    }
+}
+
+/// Hashes the input of a request, which a key is bound to.
+fn request_hash(input: &Value) -> String {
+    let input = serde_json::to_vec(input).expect("json values are serializable");
+    format!("{:x}", Sha256::digest(input))
+}
+
+/// A response cached for an idempotency key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl IntoResponse for CachedResponse {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) =
+                (HeaderName::try_from(name), HeaderValue::try_from(value))
+            {
+                response.headers_mut().append(name, value);
+            }
+        }
+        response.headers_mut().insert(
+            IDEMPOTENCY_REPLAYED_HEADER,
+            HeaderValue::from_static("true"),
+        );
+        response
+    }
+}
+
+/// Caches responses in memory by command and idempotency key.
+///
+/// Expired responses are dropped at most once a minute, when a key is claimed.
+#[derive(Debug)]
+pub struct MemoryStore {
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Debug)]
+struct MemoryEntries {
+    entries: HashMap<(String, String), MemoryEntry>,
+    next_sweep: Instant,
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    request_hash: String,
+    /// The cached response, or `None` while the request is in flight.
+    response: Option<CachedResponse>,
+    expires_at: Instant,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn claim(&self, command: &str, key: &str, request_hash: &str, expire_after: Duration) -> Claim {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("entries lock poisoned");
+        if now >= entries.next_sweep {
+            entries.entries.retain(|_, entry| entry.expires_at > now);
+            entries.next_sweep = now + SWEEP_INTERVAL;
+        }
+
+        let id = (command.to_string(), key.to_string());
+        if let Some(entry) = entries
+            .entries
+            .get(&id)
+            .filter(|entry| entry.expires_at > now)
+        {
+            return if entry.request_hash != request_hash {
+                Claim::Mismatch
+            } else {
+                match &entry.response {
+                    Some(response) => Claim::Cached(response.clone()),
+                    None => Claim::InFlight,
+                }
+            };
+        }
+        entries.entries.insert(
+            id,
+            MemoryEntry {
+                request_hash: request_hash.to_string(),
+                response: None,
+                expires_at: now + expire_after,
+            },
+        );
+
+        Claim::Claimed
+    }
+
+    fn release(&self, command: &str, key: &str) {
+        let mut entries = self.entries.lock().expect("entries lock poisoned");
+        let id = (command.to_string(), key.to_string());
+        if entries
+            .entries
+            .get(&id)
+            .is_some_and(|entry| entry.response.is_none())
+        {
+            entries.entries.remove(&id);
+        }
+    }
+
+    /// Caches the response of a claimed key.
+    fn insert(&self, command: &str, key: &str, response: CachedResponse, expire_after: Duration) {
+        let mut entries = self.entries.lock().expect("entries lock poisoned");
+        if let Some(entry) = entries
+            .entries
+            .get_mut(&(command.to_string(), key.to_string()))
+        {
+            entry.response = Some(response);
+            entry.expires_at = Instant::now() + expire_after;
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore {
+            entries: Mutex::new(MemoryEntries {
+                entries: HashMap::new(),
+                next_sweep: Instant::now() + SWEEP_INTERVAL,
+            }),
+        }
+    }
+}
+
+/// Caches responses in the `idempotency_keys` table by command and idempotency key.
+#[cfg(feature = "postgres")]
+#[derive(Clone, Debug)]
+pub struct PostgresStore {
+    pool: sqlx::PgPool,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresStore {
+    /// Creates the store, creating the `idempotency_keys` table if it does not exist.
+    pub async fn new(pool: sqlx::PgPool) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS idempotency_keys (
+                command TEXT NOT NULL,
+                key TEXT NOT NULL,
+                request_hash TEXT NOT NULL DEFAULT '',
+                response JSONB,
+                expires_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (command, key)
+            )",
+        )
+        .execute(&pool)
+        .await?;
+        // Tables created before keys were claimed have no request hash, and require a response.
+        sqlx::query(
+            "ALTER TABLE idempotency_keys
+            ADD COLUMN IF NOT EXISTS request_hash TEXT NOT NULL DEFAULT '',
+            ALTER COLUMN response DROP NOT NULL",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(PostgresStore { pool })
+    }
+
+    /// Claims a key by inserting a row without a response, replacing an expired row.
+    async fn claim(
+        &self,
+        command: &str,
+        key: &str,
+        request_hash: &str,
+        expire_after: Duration,
+    ) -> Result<Claim, sqlx::Error> {
+        let claimed = sqlx::query(
+            "INSERT INTO idempotency_keys (command, key, request_hash, response, expires_at)
+            VALUES ($1, $2, $3, NULL, now() + make_interval(secs => $4))
+            ON CONFLICT (command, key) DO UPDATE
+            SET request_hash = EXCLUDED.request_hash, response = NULL, expires_at = EXCLUDED.expires_at
+            WHERE idempotency_keys.expires_at <= now()",
+        )
+        .bind(command)
+        .bind(key)
+        .bind(request_hash)
+        .bind(expire_after.as_secs_f64())
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+        if claimed {
+            return Ok(Claim::Claimed);
+        }
+
+        let row = sqlx::query_as::<_, (String, Option<sqlx::types::Json<CachedResponse>>)>(
+            "SELECT request_hash, response FROM idempotency_keys WHERE command = $1 AND key = $2",
+        )
+        .bind(command)
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        // A row released since the insert is reported as in flight, so the request is retried.
+        Ok(match row {
+            Some((hash, _)) if hash != request_hash => Claim::Mismatch,
+            Some((_, Some(response))) => Claim::Cached(response.0),
+            Some((_, None)) | None => Claim::InFlight,
+        })
+    }
+
+    async fn release(&self, command: &str, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "DELETE FROM idempotency_keys
+            WHERE command = $1 AND key = $2 AND response IS NULL",
+        )
+        .bind(command)
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Caches the response of a claimed key.
+    async fn insert(
+        &self,
+        command: &str,
+        key: &str,
+        response: &CachedResponse,
+        expire_after: Duration,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE idempotency_keys
+            SET response = $3, expires_at = now() + make_interval(secs => $4)
+            WHERE command = $1 AND key = $2",
+        )
+        .bind(command)
+        .bind(key)
+        .bind(sqlx::types::Json(response))
+        .bind(expire_after.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Deserializes the settings, with the backend given as `memory`, `event_store` or `disabled`.
+impl<'de> Deserialize<'de> for IdempotencyConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        struct Settings {
+            backend: Option<String>,
+            header: Option<String>,
+            #[serde(with = "duration_secs")]
+            expire_after: Duration,
+            ignored_status_codes: Option<Vec<u16>>,
+        }
+
+        impl Default for Settings {
+            fn default() -> Self {
+                let defaults = IdempotencyConfig::default();
+                Settings {
+                    backend: None,
+                    header: None,
+                    expire_after: defaults.expire_after,
+                    ignored_status_codes: None,
+                }
+            }
+        }
+
+        let settings = Settings::deserialize(deserializer)?;
+        let mut config = match settings.backend {
+            Some(backend) => IdempotencyConfig::new(backend.parse().map_err(de::Error::custom)?),
+            None => IdempotencyConfig::default(),
+        };
+        config.expire_after = settings.expire_after;
+        if let Some(header) = settings.header {
+            config.header = HeaderName::try_from(header).map_err(de::Error::custom)?;
+        }
+        if let Some(status_codes) = settings.ignored_status_codes {
+            config.ignored_status_codes = status_codes
+                .into_iter()
+                .map(StatusCode::from_u16)
+                .collect::<Result<_, _>>()
+                .map_err(de::Error::custom)?;
+        }
+
+        Ok(config)
+    }
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig::memory()
+    }
+}
+
+/// Reads the idempotency key header, if present.
+pub(crate) fn idempotency_key(
+    header: Option<&HeaderName>,
+    headers: &HeaderMap,
+) -> Result<Option<String>, Error> {
+    let Some(value) = header.and_then(|header| headers.get(header)) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            Error::new(ErrorStatus::InvalidInput, "invalid_header")
+                .with_message("idempotency key must be a non-empty string")
+        })
+        .map(|key| Some(key.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{
+        Json, Router,
+        extract::{Path, State},
+        http::{Request, header::CONTENT_TYPE},
+        routing::post,
+    };
+    use serde_json::json;
+    use tokio::sync::oneshot;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router(idempotency: IdempotencyConfig, executions: Arc<AtomicUsize>) -> Router {
+        Router::new()
+            .route(
+                "/commands/{name}",
+                post(
+                    |State(idempotency): State<IdempotencyConfig>,
+                     Path(name): Path<String>,
+                     headers: HeaderMap,
+                     Json(input): Json<Value>| async move {
+                        idempotency
+                            .respond(&name, &headers, &input, async {
+                                let execution = executions.fetch_add(1, Ordering::SeqCst);
+                                Ok(execution.to_string().into_response())
+                            })
+                            .await
+                    },
+                ),
+            )
+            .with_state(idempotency)
+    }
+
+    async fn execute(router: &Router, command: &str, key: &str) -> Response {
+        execute_with(router, command, key, json!({ "account_id": "alice" })).await
+    }
+
+    async fn execute_with(router: &Router, command: &str, key: &str, input: Value) -> Response {
+        router
+            .clone()
+            .oneshot(
+                Request::post(format!("/commands/{command}"))
+                    .header(DEFAULT_IDEMPOTENCY_KEY_HEADER, key)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(input.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn body(response: Response) -> String {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_responses_by_command_and_key_without_cookies() {
+        let executions = Arc::new(AtomicUsize::new(0));
+        let router = router(IdempotencyConfig::memory(), executions.clone());
+
+        let first = execute(&router, "open_account", "abc").await;
+        assert!(!first.headers().contains_key(IDEMPOTENCY_REPLAYED_HEADER));
+        assert_eq!(body(first).await, "0");
+
+        let replayed = execute(&router, "open_account", "abc").await;
+        assert_eq!(replayed.headers()[IDEMPOTENCY_REPLAYED_HEADER], "true");
+        assert_eq!(body(replayed).await, "0");
+
+        let other_command = execute(&router, "close_account", "abc").await;
+        assert_eq!(body(other_command).await, "1");
+        assert_eq!(executions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_responses_are_not_replayed() {
+        let executions = Arc::new(AtomicUsize::new(0));
+        let router = router(
+            IdempotencyConfig::memory().with_expire_after(Duration::ZERO),
+            executions.clone(),
+        );
+
+        execute(&router, "open_account", "abc").await;
+        let response = execute(&router, "open_account", "abc").await;
+        assert!(!response.headers().contains_key(IDEMPOTENCY_REPLAYED_HEADER));
+        assert_eq!(executions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_keys_reused_with_a_different_body() {
+        let executions = Arc::new(AtomicUsize::new(0));
+        let router = router(IdempotencyConfig::memory(), executions.clone());
+
+        execute(&router, "open_account", "abc").await;
+        let response = execute_with(
+            &router,
+            "open_account",
+            "abc",
+            json!({ "account_id": "bob" }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(executions.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_with_the_same_key_execute_once() {
+        let idempotency = IdempotencyConfig::memory();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            DEFAULT_IDEMPOTENCY_KEY_HEADER,
+            HeaderValue::from_static("abc"),
+        );
+        let input = json!({ "account_id": "alice" });
+        let (done, wait) = oneshot::channel::<()>();
+
+        let first = idempotency.respond("open_account", &headers, &input, async {
+            wait.await.ok();
+            Ok("first".into_response())
+        });
+        let second = async {
+            let response = idempotency
+                .respond("open_account", &headers, &input, async {
+                    Ok("second".into_response())
+                })
+                .await;
+            done.send(()).unwrap();
+            response
+        };
+        let (first, second) = tokio::join!(first, second);
+
+        assert_eq!(body(first.unwrap()).await, "first");
+        assert_eq!(second.unwrap_err().status(), ErrorStatus::Conflict);
+
+        let replayed = idempotency
+            .respond("open_account", &headers, &input, async {
+                Ok("third".into_response())
+            })
+            .await
+            .unwrap();
+        assert_eq!(body(replayed).await, "first");
+    }
+
+    #[tokio::test]
+    async fn uncached_responses_release_the_key() {
+        let idempotency = IdempotencyConfig::memory();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            DEFAULT_IDEMPOTENCY_KEY_HEADER,
+            HeaderValue::from_static("abc"),
+        );
+        let input = json!({});
+
+        let failed = idempotency
+            .respond("open_account", &headers, &input, async {
+                Ok(StatusCode::SERVICE_UNAVAILABLE.into_response())
+            })
+            .await
+            .unwrap();
+        assert_eq!(failed.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let retried = idempotency
+            .respond("open_account", &headers, &input, async {
+                Ok("retried".into_response())
+            })
+            .await
+            .unwrap();
+        assert_eq!(body(retried).await, "retried");
+    }
+}
@@ -0,0 +1,225 @@
+use esruntime_sdk::error::FieldError;
+use jsonschema::error::ValidationErrorKind;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::{Error, ErrorStatus};
+
+/// Deserializes command input, reporting every invalid field, and every unknown field when
+/// `deny_unknown_fields` is set.
+///
+/// Invalid fields are found by validating the input against the JSON schema of `T`. When the
+/// schema accepts input which fails to deserialize, eg. with a custom `Deserialize`
+/// implementation, the error reported by serde is returned instead.
+///
+/// Paths are JSON pointers into the input, eg. `/amount`.
+pub(crate) fn deserialize_input<T: DeserializeOwned + JsonSchema>(
+    input: Value,
+    deny_unknown_fields: bool,
+) -> Result<T, Error> {
+    let mut errors = Vec::new();
+    let mut unknown_field = |path: serde_ignored::Path<'_>| {
+        if deny_unknown_fields {
+            errors.push(FieldError::new(ignored_pointer(&path), "unknown field"));
+        }
+    };
+    let result = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+        &input,
+        &mut unknown_field,
+    ));
+
+    match result {
+        Ok(input) if errors.is_empty() => return Ok(input),
+        Ok(_) => {}
+        Err(err) => {
+            let schema_errors = schema_errors::<T>(&input);
+            if schema_errors.is_empty() {
+                errors.push(serde_error(err));
+            }
+            for error in schema_errors {
+                if !errors.contains(&error) {
+                    errors.push(error);
+                }
+            }
+        }
+    }
+
+    Err(invalid_input(errors))
+}
+
+/// Validates the input against the JSON schema of `T`, returning every invalid field.
+fn schema_errors<T: JsonSchema>(input: &Value) -> Vec<FieldError> {
+    let schema = schemars::schema_for!(T);
+    let Ok(validator) = jsonschema::validator_for(schema.as_value()) else {
+        return Vec::new();
+    };
+
+    validator
+        .iter_errors(input)
+        .flat_map(|err| {
+            let path = err.instance_path.to_string();
+            match &err.kind {
+                // Missing fields are reported at their parent, so point at the field itself.
+                ValidationErrorKind::Required { property } => {
+                    let field = property.as_str().unwrap_or_default().to_string();
+                    vec![FieldError::new(
+                        format!("{path}/{}", escape_token(&field)),
+                        format!("missing field `{field}`"),
+                    )]
+                }
+                ValidationErrorKind::AdditionalProperties { unexpected } => unexpected
+                    .iter()
+                    .map(|field| {
+                        FieldError::new(format!("{path}/{}", escape_token(field)), "unknown field")
+                    })
+                    .collect(),
+                _ => vec![FieldError::new(path, err.to_string())],
+            }
+        })
+        .collect()
+}
+
+/// Converts the first error reported by serde into a field error.
+fn serde_error(err: serde_path_to_error::Error<serde_json::Error>) -> FieldError {
+    let mut path = err
+        .path()
+        .iter()
+        .map(|segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => index.to_string(),
+            serde_path_to_error::Segment::Map { key } => key.clone(),
+            serde_path_to_error::Segment::Enum { variant } => variant.clone(),
+            serde_path_to_error::Segment::Unknown => "?".to_string(),
+        })
+        .fold(String::new(), |pointer, token| {
+            pointer + "/" + &escape_token(&token)
+        });
+    let message = err.into_inner().to_string();
+
+    // Missing fields are reported at their parent, so point at the field itself.
+    if let Some(field) = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.strip_suffix('`'))
+    {
+        path = path + "/" + &escape_token(field);
+    }
+
+    FieldError::new(path, message)
+}
+
+/// Error returned for invalid command input.
+fn invalid_input(errors: Vec<FieldError>) -> Error {
+    Error::new(ErrorStatus::InvalidInput, "invalid_command")
+        .with_message("invalid command input")
+        .with_errors(errors)
+}
+
+fn ignored_pointer(path: &serde_ignored::Path<'_>) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => {
+            format!("{}/{index}", ignored_pointer(parent))
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            format!("{}/{}", ignored_pointer(parent), escape_token(key))
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => ignored_pointer(parent),
+    }
+}
+
+/// Escapes a JSON pointer reference token (RFC 6901).
+fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct TransferFundsInput {
+        source_account: String,
+        amount: f64,
+        items: Vec<Item>,
+    }
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct Item {
+        name: String,
+    }
+
+    fn errors(input: Value) -> Vec<FieldError> {
+        deserialize_input::<TransferFundsInput>(input, true)
+            .unwrap_err()
+            .errors()
+            .to_vec()
+    }
+
+    #[test]
+    fn valid_input() {
+        let input = json!({ "source_account": "alice", "amount": 50.0, "items": [] });
+        assert!(deserialize_input::<TransferFundsInput>(input, true).is_ok());
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored_by_default() {
+        let input = json!({ "source_account": "alice", "amount": 50.0, "items": [], "note": "" });
+        assert!(deserialize_input::<TransferFundsInput>(input, false).is_ok());
+    }
+
+    #[test]
+    fn invalid_field_is_reported_with_path() {
+        let errors = errors(json!({
+            "source_account": "alice",
+            "amount": 50.0,
+            "items": [{ "name": 1 }],
+        }));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/items/0/name");
+        assert!(errors[0].message.contains("\"string\""));
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let errors = errors(json!({
+            "source_account": 1,
+            "items": [{ "name": "a" }, { "name": false }],
+        }));
+
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().any(|err| err.path == "/source_account"));
+        assert!(errors.iter().any(|err| err.path == "/items/1/name"));
+        assert!(errors.contains(&FieldError::new("/amount", "missing field `amount`")));
+    }
+
+    #[test]
+    fn missing_field_points_at_field() {
+        let errors = errors(json!({ "source_account": "alice", "items": [] }));
+
+        assert_eq!(errors[0].path, "/amount");
+        assert_eq!(errors[0].message, "missing field `amount`");
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let errors = errors(json!({
+            "source_account": "alice",
+            "amount": 50.0,
+            "items": [{ "name": "a", "colour": "red" }],
+            "a/b": true,
+        }));
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors.contains(&FieldError::new("/items/0/colour", "unknown field")));
+        assert!(errors.contains(&FieldError::new("/a~1b", "unknown field")));
+    }
+}
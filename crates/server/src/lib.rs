@@ -1,126 +1,663 @@
+pub mod config;
+pub mod consistency;
+pub mod context;
 pub mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod idempotency;
+mod input;
+pub mod metrics;
+mod openapi;
+pub mod schema;
+pub mod stream;
+pub mod submission;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use axum::{
     Json, Router,
-    extract::{DefaultBodyLimit, State},
-    http::StatusCode,
-    routing::post,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
 };
-use axum_idempotent::{IdempotentLayer, IdempotentOptions};
 use base64::{Engine, prelude::BASE64_STANDARD};
 use esruntime_sdk::prelude::*;
-use ruts::{
-    CookieOptions, SessionLayer, store::memory::MemoryStore, tower_cookies::CookieManagerLayer,
-};
-use serde::de::DeserializeOwned;
+use futures_util::{FutureExt, future::BoxFuture};
+use metrics_exporter_prometheus::PrometheusHandle;
+use schemars::JsonSchema;
+use serde::{Serialize, de::DeserializeOwned};
 use serde_json::{Value, json};
 use tokio::{io, net::ToSocketAddrs};
-use tower_http::timeout::TimeoutLayer;
+use tower_http::{compression::CompressionLayer, timeout::TimeoutLayer};
 use umadb_client::AsyncUmaDBClient;
+use umadb_dcb::DCBEvent;
+use uuid::Uuid;
 
-use crate::error::{Error, ErrorStatus};
+use crate::{
+    config::CommandRouterConfig,
+    context::{RequestContext, context_headers, handler_version, handler_version_not_found},
+    error::Error,
+    health::{HealthState, ReadinessCheck},
+    idempotency::{IDEMPOTENCY_REPLAYED_HEADER, IdempotencyConfig, idempotency_key},
+    openapi::OpenApi,
+    schema::SchemaRegistry,
+    stream::StreamParams,
+    submission::{ExecuteParams, Submissions},
+};
 
 pub struct CommandRouter {
-    router: Router<CommandState>,
+    commands: HashMap<String, CommandVersions>,
     umadb_client: Arc<AsyncUmaDBClient>,
+    config: CommandRouterConfig,
+    metrics: Option<PrometheusHandle>,
+    readiness_checks: Vec<(String, Box<dyn ReadinessCheck>)>,
+    openapi: OpenApi,
+    schema_registry: Option<SchemaRegistry>,
+    #[cfg(feature = "wasm")]
+    wasm_runtime: Option<wasm::WasmRuntime>,
 }
 
 impl CommandRouter {
     pub fn new(umadb_client: Arc<AsyncUmaDBClient>) -> Self {
-        let router = Router::new();
-
         CommandRouter {
-            router,
+            commands: HashMap::new(),
             umadb_client,
+            config: CommandRouterConfig::default(),
+            metrics: None,
+            readiness_checks: Vec::new(),
+            openapi: OpenApi::new(),
+            schema_registry: None,
+            #[cfg(feature = "wasm")]
+            wasm_runtime: None,
         }
     }
 
-    pub fn build(self) -> Router {
-        let store = Arc::new(MemoryStore::new());
-        let idempotent_options = IdempotentOptions::default()
-            .use_idempotency_key_header(Some("X-Idempotency-Key"))
-            .ignore_response_status_code(StatusCode::CONFLICT)
-            .expire_after(60 * 5);
+    /// Configures limits, middleware and shutdown behaviour.
+    pub fn config(mut self, config: CommandRouterConfig) -> Self {
+        self.config = config;
+        self
+    }
 
-        let router = self
-            .router
-            .layer(DefaultBodyLimit::max(256 * 1024))
-            .layer(TimeoutLayer::with_status_code(
-                StatusCode::REQUEST_TIMEOUT,
-                Duration::from_secs(30),
-            ))
-            .layer(IdempotentLayer::<MemoryStore>::new(idempotent_options))
-            .layer(
-                SessionLayer::new(store)
-                    .with_cookie_options(CookieOptions::build().name("session")),
-            )
-            .layer(CookieManagerLayer::new());
-
-        router.with_state(CommandState {
-            umadb_client: self.umadb_client,
+    /// Configures how idempotency keys are recorded.
+    pub fn idempotency(mut self, idempotency: IdempotencyConfig) -> Self {
+        self.config = self.config.with_idempotency(idempotency);
+        self
+    }
+
+    /// Serves the metrics recorded by `handle` at `/metrics` in the Prometheus text format.
+    ///
+    /// See [`metrics::install_recorder`].
+    pub fn metrics(mut self, handle: PrometheusHandle) -> Self {
+        self.metrics = Some(handle);
+        self
+    }
+
+    /// Serves the ESDL schema at `GET /schema`, replaced with `PUT /schema` and checked with
+    /// `POST /schema/validate`.
+    ///
+    /// Handlers uploaded to the WASM runtime are validated against the current schema.
+    pub fn schema_registry(mut self, registry: SchemaRegistry) -> Self {
+        self.schema_registry = Some(registry);
+        self
+    }
+
+    /// Executes handlers uploaded as WASM modules with `PUT /handlers/{name}`.
+    ///
+    /// Commands registered with [`CommandRouter::register_command`] take precedence over
+    /// uploaded handlers with the same name.
+    #[cfg(feature = "wasm")]
+    pub fn wasm_runtime(mut self, runtime: wasm::WasmRuntime) -> Self {
+        self.wasm_runtime = Some(runtime);
+        self.openapi.add_wasm_handlers();
+        self
+    }
+
+    /// Registers a check which must be ready for `/ready` to report the server as ready.
+    pub fn readiness_check(mut self, name: impl Into<String>, check: impl ReadinessCheck) -> Self {
+        self.readiness_checks.push((name.into(), Box::new(check)));
+        self
+    }
+
+    /// Reports the server as not ready until the projection is within `max_lag` events of the head.
+    #[cfg(feature = "postgres")]
+    pub fn projection_readiness(
+        self,
+        status: esruntime_postgres::ProjectionStatus,
+        max_lag: u64,
+    ) -> Self {
+        let name = format!("projection:{}", status.name());
+        self.readiness_check(name, move || {
+            let lag = status.lag();
+            if lag <= max_lag {
+                health::Readiness::ready()
+            } else {
+                health::Readiness::not_ready(format!("{lag} events behind head"))
+            }
         })
     }
 
+    pub fn build(self) -> Router {
+        let config = self.config;
+        let openapi = Json(self.openapi.document(config.idempotency().header()));
+        let router = self
+            .commands
+            .iter()
+            .fold(Router::new(), |router, (name, versions)| {
+                let timeout = config.command_timeout(name);
+                let command: Arc<str> = name.as_str().into();
+                let versions = versions.clone();
+                let route = move |State(state): State<CommandState>,
+                                  RequestContext(context): RequestContext,
+                                  Query(params): Query<ExecuteParams>,
+                                  headers: HeaderMap,
+                                  Json(input): Json<Value>| {
+                    let command = command.clone();
+                    let execute = versions.executor(&command, &headers);
+                    async move {
+                        command_route(
+                            command, execute?, state, context, params, headers, input, timeout,
+                        )
+                        .await
+                    }
+                };
+                let route = post(route).layer(TimeoutLayer::with_status_code(
+                    StatusCode::REQUEST_TIMEOUT,
+                    timeout,
+                ));
+                router.route(&format!("/commands/{name}"), route)
+            });
+        #[cfg(feature = "wasm")]
+        let router = match &self.wasm_runtime {
+            Some(runtime) => router.route("/commands/{name}", runtime.command_route(&config)),
+            None => router,
+        };
+        let idempotency = config.idempotency();
+
+        let health_state = HealthState {
+            umadb_client: self.umadb_client.clone(),
+            commands: Arc::new(self.commands.keys().cloned().collect()),
+            #[cfg(feature = "wasm")]
+            wasm_runtime: self.wasm_runtime.clone(),
+            readiness_checks: Arc::new(self.readiness_checks),
+        };
+        let state = CommandState {
+            umadb_client: self.umadb_client.clone(),
+            idempotency_key_header: idempotency.event_store_header(),
+            idempotency: idempotency.clone(),
+            deny_unknown_fields: config.deny_unknown_fields(),
+            submissions: Submissions::new(config.submission().clone()),
+        };
+        let submissions = state.submissions.clone();
+        let stream_client = self.umadb_client.clone();
+        let mut router =
+            router
+                .with_state(state.clone())
+                .route(
+                    "/events/stream",
+                    get(
+                        move |headers: HeaderMap, Query(params): Query<StreamParams>| async move {
+                            stream::sse(stream_client, params, headers).await
+                        },
+                    ),
+                )
+                .route(
+                    "/commands/status/{command_id}",
+                    get(move |Path(command_id): Path<Uuid>| async move {
+                        submissions.status(command_id)
+                    }),
+                )
+                .route("/openapi.json", get(|| async move { openapi }))
+                .route(
+                    "/health",
+                    get({
+                        let state = health_state.clone();
+                        || async move { health::health(&state).await }
+                    }),
+                )
+                .route(
+                    "/ready",
+                    get(|| async move { health::ready(&health_state).await }),
+                );
+
+        #[cfg(feature = "wasm")]
+        if let Some(runtime) = &self.wasm_runtime {
+            router = router.merge(runtime.routes());
+        }
+        if let Some(registry) = &self.schema_registry {
+            #[cfg(feature = "wasm")]
+            if let Some(runtime) = self.wasm_runtime.clone() {
+                registry.subscribe(move |schema| runtime.set_schema(schema.into()));
+            }
+            router = router.merge(registry.routes());
+        }
+        #[cfg(feature = "grpc")]
+        {
+            router = grpc::route(router, self.commands, state, &config);
+        }
+        #[cfg(feature = "ws")]
+        {
+            let umadb_client = self.umadb_client;
+            router = router.route(
+                "/events/ws",
+                get(
+                    move |headers: HeaderMap,
+                          Query(params): Query<StreamParams>,
+                          upgrade: axum::extract::ws::WebSocketUpgrade| async move {
+                        stream::websocket(umadb_client, params, headers, upgrade).await
+                    },
+                ),
+            );
+        }
+        if let Some(handle) = self.metrics {
+            router = router.route("/metrics", get(|| async move { metrics::render(&handle) }));
+        }
+        router = router.layer(DefaultBodyLimit::max(config.body_limit()));
+        if config.compression() {
+            router = router.layer(CompressionLayer::new());
+        }
+        if let Some(cors) = config.cors() {
+            router = router.layer(cors.layer(idempotency.header()));
+        }
+
+        router
+    }
+
+    /// Serves the router until Ctrl+C or `SIGTERM` is received.
+    ///
+    /// See [`CommandRouter::serve_with_shutdown`].
     pub async fn serve<A: ToSocketAddrs>(self, addr: A) -> io::Result<()> {
+        self.serve_with_shutdown(addr, shutdown_signal()).await
+    }
+
+    /// Serves the router until `signal` completes.
+    ///
+    /// New connections are refused once the signal completes, and in-flight requests
+    /// are given until the configured shutdown timeout to complete.
+    pub async fn serve_with_shutdown<A, F>(self, addr: A, signal: F) -> io::Result<()>
+    where
+        A: ToSocketAddrs,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown_timeout = self.config.shutdown_timeout();
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        axum::serve(listener, self.build()).await
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let server = axum::serve(listener, self.build()).with_graceful_shutdown(async move {
+            signal.await;
+            let _ = shutdown_tx.send(true);
+        });
+
+        let drain_deadline = async move {
+            let mut shutdown_rx = shutdown_rx;
+            if shutdown_rx.wait_for(|shutdown| *shutdown).await.is_err() {
+                // The server stopped without a shutdown signal.
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(shutdown_timeout).await;
+        };
+
+        tokio::select! {
+            res = server.into_future() => res,
+            _ = drain_deadline => {
+                tracing::warn!(
+                    timeout = ?shutdown_timeout,
+                    "shutdown timeout elapsed with requests still in flight"
+                );
+                Ok(())
+            }
+        }
     }
 
-    pub fn register_command<C>(mut self, name: &str) -> Self
+    /// Registers a command, executed with `POST /commands/{name}`.
+    ///
+    /// The output of a `Command<O>` is returned as the `result` of the response. `O` is inferred,
+    /// eg. `register_command::<CreateTask, _>("create_task")`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a single path segment, or is reserved for a built-in route under
+    /// `/commands`.
+    pub fn register_command<C, O>(self, name: &str) -> Self
     where
-        C: Command + Send,
-        C::Input: DeserializeOwned + Send + 'static,
-        C::Error: std::error::Error,
+        C: Command<O> + Send + 'static,
+        O: Serialize + Send + 'static,
+        C::Input: DeserializeOwned + JsonSchema + Send + 'static,
+        C::Error: Into<CommandError>,
     {
-        let route = |State(state): State<CommandState>, Json(input): Json<Value>| async move {
-            let input: C::Input = serde_json::from_value(input).map_err(|err| {
-                Error::new(ErrorStatus::InvalidInput, "invalid_command")
-                    .with_message(err.to_string())
-            })?;
-
-            let result = C::execute(state.umadb_client.as_ref(), input).await?;
-
-            if result.events.is_empty() {
-                return Ok(Json(json!({
-                    "status": "ok",
-                    "events": [],
-                    "position": result.position,
-                })));
+        self.register::<C, O>(name, None)
+    }
+
+    /// Registers a version of a command, alongside the other versions registered under `name`.
+    ///
+    /// Requests select a version with the `X-Handler-Version` header, defaulting to the most
+    /// recently registered. The version is recorded in the metadata of the emitted events.
+    pub fn register_command_version<C, O>(self, name: &str, version: &str) -> Self
+    where
+        C: Command<O> + Send + 'static,
+        O: Serialize + Send + 'static,
+        C::Input: DeserializeOwned + JsonSchema + Send + 'static,
+        C::Error: Into<CommandError>,
+    {
+        self.register::<C, O>(name, Some(version))
+    }
+
+    fn register<C, O>(mut self, name: &str, version: Option<&str>) -> Self
+    where
+        C: Command<O> + Send + 'static,
+        O: Serialize + Send + 'static,
+        C::Input: DeserializeOwned + JsonSchema + Send + 'static,
+        C::Error: Into<CommandError>,
+    {
+        check_command_name(name);
+        let execute = executor::<C, O>(name, version);
+        self.openapi.add_command::<C::Input>(name);
+        self.commands
+            .entry(name.to_string())
+            .or_default()
+            .insert(version, execute);
+        self
+    }
+}
+
+/// Command names reserved for built-in routes under `/commands`.
+const RESERVED_COMMAND_NAMES: &[&str] = &["status"];
+
+fn check_command_name(name: &str) {
+    assert!(
+        !name.is_empty() && !name.contains(['/', '{', '}']),
+        "invalid command name `{name}`: names must be a single path segment"
+    );
+    assert!(
+        !RESERVED_COMMAND_NAMES.contains(&name),
+        "invalid command name `{name}`: `/commands/{name}` is reserved"
+    );
+}
+
+/// Executes a command for a request, or submits it when executed asynchronously, limiting each
+/// attempt of a submitted command to `timeout`.
+///
+/// Responses are cached by command and idempotency key, unless keys are recorded in the event
+/// store.
+#[allow(clippy::too_many_arguments)]
+async fn command_route(
+    command: Arc<str>,
+    execute: Executor,
+    state: CommandState,
+    context: CommandContext,
+    params: ExecuteParams,
+    headers: HeaderMap,
+    input: Value,
+    timeout: Duration,
+) -> Result<Response, Error> {
+    let idempotency = state.idempotency.clone();
+    let name = command.clone();
+    let request_headers = headers.clone();
+    let request_input = input.clone();
+    idempotency
+        .respond(&name, &request_headers, &request_input, async move {
+            if params.run_async {
+                let submissions = state.submissions.clone();
+                return submissions
+                    .submit(command, execute, state, context, headers, input, timeout);
             }
 
-            let resp_events: Vec<_> = result
-                .events
-                .into_iter()
-                .map(|event| {
-                    let data = match serde_json::from_slice(&event.data) {
-                        Ok(data) => data,
-                        Err(_) => Value::String(BASE64_STANDARD.encode(&event.data)),
-                    };
-
-                    json!({
-                        "id": event.uuid,
-                        "type": event.event_type,
-                        "data": data,
-                        "tags": event.tags,
-                    })
-                })
-                .collect();
-
-            Ok::<_, Error>(Json(json!({
-                "status": "ok",
-                "events": resp_events,
-                "position": result.position,
-            })))
+            execute(state, context, headers, input)
+                .await
+                .map(|(context, result)| command_response(&context, &result).into_response())
+        })
+        .await
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for ctrl+c: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
+
+/// Type erased command execution, registered per command name.
+pub(crate) type Executor = Arc<
+    dyn Fn(
+            CommandState,
+            CommandContext,
+            HeaderMap,
+            Value,
+        ) -> BoxFuture<'static, Result<(CommandContext, ExecuteResult<Value>), Error>>
+        + Send
+        + Sync,
+>;
+
+/// Versions of a command registered under the same name, in order of registration.
+#[derive(Clone, Default)]
+pub(crate) struct CommandVersions(Vec<(Option<String>, Executor)>);
+
+impl CommandVersions {
+    /// Adds a version, replacing any executor registered with the same version.
+    fn insert(&mut self, version: Option<&str>, execute: Executor) {
+        self.0
+            .retain(|(registered, _)| registered.as_deref() != version);
+        self.0.push((version.map(str::to_string), execute));
+    }
+
+    /// Returns the version requested with `X-Handler-Version`, or the latest if none is requested.
+    pub(crate) fn executor(&self, command: &str, headers: &HeaderMap) -> Result<Executor, Error> {
+        let Some(version) = handler_version(headers)? else {
+            let (_, execute) = self.0.last().expect("commands have a registered version");
+            return Ok(execute.clone());
         };
 
-        self.router = self.router.route(&format!("/{name}"), post(route));
-        self
+        self.0
+            .iter()
+            .find(|(registered, _)| registered.as_deref() == Some(version))
+            .map(|(_, execute)| execute.clone())
+            .ok_or_else(|| handler_version_not_found(command, version))
     }
 }
 
+fn executor<C, O>(name: &str, version: Option<&str>) -> Executor
+where
+    C: Command<O> + Send + 'static,
+    O: Serialize + Send + 'static,
+    C::Input: DeserializeOwned + JsonSchema + Send + 'static,
+    C::Error: Into<CommandError>,
+{
+    let command: Arc<str> = name.into();
+    let version = version.map(str::to_string);
+    Arc::new(move |state, mut context, headers, input| {
+        let command = command.clone();
+        context.handler_version = version.clone();
+        async move { execute_command::<C, O>(&command, &state, context, &headers, input).await }
+            .boxed()
+    })
+}
+
+/// Executes a command, recording its metrics.
+async fn execute_command<C, O>(
+    command: &str,
+    state: &CommandState,
+    context: CommandContext,
+    headers: &HeaderMap,
+    input: Value,
+) -> Result<(CommandContext, ExecuteResult<Value>), Error>
+where
+    C: Command<O> + Send,
+    O: Serialize + Send,
+    C::Input: DeserializeOwned + JsonSchema + Send + 'static,
+    C::Error: Into<CommandError>,
+{
+    let umadb_client = state.umadb_client.clone();
+    let deny_unknown_fields = state.deny_unknown_fields;
+    execute_recorded(command, state, context, headers, |context| async move {
+        try_execute_command::<C, O>(umadb_client.as_ref(), context, input, deny_unknown_fields)
+            .await
+    })
+    .await
+}
+
+/// Runs an execution with the idempotency key from the request headers, recording its metrics.
+pub(crate) async fn execute_recorded<F, Fut>(
+    command: &str,
+    state: &CommandState,
+    mut context: CommandContext,
+    headers: &HeaderMap,
+    execute: F,
+) -> Result<(CommandContext, ExecuteResult<Value>), Error>
+where
+    F: FnOnce(CommandContext) -> Fut,
+    Fut: Future<Output = Result<ExecuteResult<Value>, Error>>,
+{
+    let start = Instant::now();
+    let result: Result<_, Error> = async {
+        context.idempotency_key = idempotency_key(state.idempotency_key_header.as_ref(), headers)
+            .map_err(|err| err.with_context(&context))?;
+        context.command = Some(command.to_string());
+        let result = execute(context.clone())
+            .await
+            .map_err(|err| err.with_context(&context))?;
+        Ok((context, result))
+    }
+    .await;
+    metrics::record_command(
+        command,
+        start.elapsed(),
+        result
+            .as_ref()
+            .map(|(_, result)| emitted_events(result))
+            .map_err(Error::status),
+    );
+
+    result
+}
+
+async fn try_execute_command<C, O>(
+    umadb_client: &AsyncUmaDBClient,
+    context: CommandContext,
+    input: Value,
+    deny_unknown_fields: bool,
+) -> Result<ExecuteResult<Value>, Error>
+where
+    C: Command<O> + Send,
+    O: Serialize + Send,
+    C::Input: DeserializeOwned + JsonSchema + Send + 'static,
+    C::Error: Into<CommandError>,
+{
+    let input: C::Input = input::deserialize_input(input, deny_unknown_fields)?;
+    let result = C::execute_with(umadb_client, input, context).await?;
+
+    Ok(result.serialize_output()?)
+}
+
+/// Number of events emitted by an execution, which is zero when replayed.
+fn emitted_events(result: &ExecuteResult<Value>) -> usize {
+    if result.replayed {
+        0
+    } else {
+        result.events.len()
+    }
+}
+
+fn command_response(
+    context: &CommandContext,
+    result: &ExecuteResult<Value>,
+) -> (HeaderMap, Json<Value>) {
+    let resp_events: Vec<_> = result.events.iter().map(event_json).collect();
+
+    let mut resp_headers = context_headers(context);
+    if result.replayed {
+        resp_headers.insert(
+            IDEMPOTENCY_REPLAYED_HEADER,
+            HeaderValue::from_static("true"),
+        );
+    }
+
+    (
+        resp_headers,
+        Json(json!({
+            "status": "ok",
+            "events": resp_events,
+            "result": result.output,
+            "position": result.position,
+            "correlation_id": context.correlation_id,
+            "causation_id": context.command_id,
+        })),
+    )
+}
+
+/// Serializes an event, decoding JSON data and falling back to base64 for other encodings.
+pub(crate) fn event_json(event: &DCBEvent) -> Value {
+    let data = match serde_json::from_slice(&event.data) {
+        Ok(data) => data,
+        Err(_) => Value::String(BASE64_STANDARD.encode(&event.data)),
+    };
+
+    json!({
+        "id": event.uuid,
+        "type": event.event_type,
+        "data": data,
+        "tags": event.tags,
+    })
+}
+
 #[derive(Clone)]
 struct CommandState {
     umadb_client: Arc<AsyncUmaDBClient>,
+    idempotency_key_header: Option<HeaderName>,
+    idempotency: IdempotencyConfig,
+    deny_unknown_fields: bool,
+    submissions: Submissions,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_command_names() {
+        check_command_name("open_account");
+        check_command_name("transfer-funds");
+    }
+
+    #[test]
+    #[should_panic(expected = "is reserved")]
+    fn rejects_reserved_command_names() {
+        check_command_name("status");
+    }
+
+    #[test]
+    #[should_panic(expected = "single path segment")]
+    fn rejects_nested_command_names() {
+        check_command_name("accounts/open");
+    }
 }
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use axum::{http::header, response::IntoResponse};
+use metrics::{Unit, counter, describe_counter, describe_histogram, histogram};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::error::ErrorStatus;
+
+pub const COMMANDS_EXECUTED: &str = "esruntime_commands_executed_total";
+pub const COMMANDS_REJECTED: &str = "esruntime_commands_rejected_total";
+pub const COMMANDS_CONFLICTED: &str = "esruntime_commands_conflicted_total";
+pub const COMMANDS_INPUT_ERRORS: &str = "esruntime_commands_input_errors_total";
+pub const COMMAND_DURATION: &str = "esruntime_command_duration_seconds";
+pub const EVENTS_EMITTED: &str = "esruntime_events_emitted_total";
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Installs a global Prometheus recorder, returning a handle to render the metrics with.
+///
+/// Metrics recorded by projections in `esruntime-postgres` are exported by the same recorder.
+pub fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("_duration_seconds".to_string()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    describe_counter!(COMMANDS_EXECUTED, "Number of command executions");
+    describe_counter!(
+        COMMANDS_REJECTED,
+        "Number of commands rejected by business rules"
+    );
+    describe_counter!(
+        COMMANDS_CONFLICTED,
+        "Number of commands which failed due to a concurrent modification"
+    );
+    describe_counter!(
+        COMMANDS_INPUT_ERRORS,
+        "Number of commands with invalid input"
+    );
+    describe_counter!(EVENTS_EMITTED, "Number of events emitted by commands");
+    describe_histogram!(
+        COMMAND_DURATION,
+        Unit::Seconds,
+        "Time taken to execute a command"
+    );
+
+    Ok(handle)
+}
+
+/// Records the outcome of a command execution.
+///
+/// On success, the outcome is the number of events emitted.
+pub(crate) fn record_command(
+    command: &str,
+    duration: Duration,
+    outcome: Result<usize, ErrorStatus>,
+) {
+    let command = command.to_string();
+    counter!(COMMANDS_EXECUTED, "command" => command.clone()).increment(1);
+    histogram!(COMMAND_DURATION, "command" => command.clone()).record(duration);
+
+    match outcome {
+        Ok(events) => {
+            counter!(EVENTS_EMITTED, "command" => command).increment(events as u64);
+        }
+        Err(ErrorStatus::Rejected) => {
+            counter!(COMMANDS_REJECTED, "command" => command).increment(1);
+        }
+        Err(ErrorStatus::Conflict) => {
+            counter!(COMMANDS_CONFLICTED, "command" => command).increment(1);
+        }
+        Err(ErrorStatus::InvalidInput) => {
+            counter!(COMMANDS_INPUT_ERRORS, "command" => command).increment(1);
+        }
+        Err(_) => {}
+    }
+}
+
+pub(crate) fn render(handle: &PrometheusHandle) -> impl IntoResponse + use<> {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    )
+}
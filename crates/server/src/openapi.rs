@@ -0,0 +1,506 @@
+use axum::http::{HeaderName, header::IF_MATCH};
+use schemars::{JsonSchema, SchemaGenerator, generate::SchemaSettings};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    context::{CAUSATION_ID_HEADER, CORRELATION_ID_HEADER, HANDLER_VERSION_HEADER},
+    error::ErrorStatus,
+    idempotency::IDEMPOTENCY_REPLAYED_HEADER,
+};
+
+const OPENAPI_VERSION: &str = "3.1.0";
+
+/// Collects the paths and schemas of registered commands into an OpenAPI 3.1 document.
+#[derive(Clone)]
+pub(crate) struct OpenApi {
+    generator: SchemaGenerator,
+    commands: Vec<(String, Value)>,
+    wasm_handlers: bool,
+}
+
+impl OpenApi {
+    pub(crate) fn new() -> Self {
+        let generator = SchemaSettings::draft2020_12()
+            .with(|settings| {
+                settings.definitions_path = "/components/schemas".into();
+                settings.meta_schema = None;
+            })
+            .into_generator();
+
+        OpenApi {
+            generator,
+            commands: Vec::new(),
+            wasm_handlers: false,
+        }
+    }
+
+    /// Adds a command, referencing its input schema from `components/schemas`.
+    ///
+    /// Replaces a command with the same name, so the latest registered version is documented.
+    pub(crate) fn add_command<I: JsonSchema>(&mut self, name: &str) {
+        let input_schema = self.generator.subschema_for::<I>();
+        self.commands.retain(|(command, _)| command != name);
+        self.commands
+            .push((name.to_string(), input_schema.to_value()));
+    }
+
+    /// Documents the uploaded WASM handlers executed at `/commands/{command_name}`.
+    #[cfg(feature = "wasm")]
+    pub(crate) fn add_wasm_handlers(&mut self) {
+        self.wasm_handlers = true;
+    }
+
+    /// Generates the document, using `idempotency_header` for the idempotency key parameter.
+    pub(crate) fn document(&self, idempotency_header: &HeaderName) -> Value {
+        let mut generator = self.generator.clone();
+        let mut schemas = generator.take_definitions(true);
+        schemas.insert("CommandResponse".to_string(), command_response_schema());
+        schemas.insert("Event".to_string(), event_schema());
+        schemas.insert("ErrorResponse".to_string(), error_response_schema());
+        schemas.insert("SubmissionStatus".to_string(), submission_status_schema());
+
+        let mut paths: Map<String, Value> = self
+            .commands
+            .iter()
+            .map(|(name, input_schema)| {
+                let operation = command_operation(name, input_schema, idempotency_header);
+                (format!("/commands/{name}"), json!({ "post": operation }))
+            })
+            .collect();
+        // Registered commands are matched before the templated path of uploaded handlers.
+        if self.wasm_handlers {
+            paths.insert(
+                "/commands/{command_name}".to_string(),
+                json!({ "post": wasm_handler_operation(idempotency_header) }),
+            );
+        }
+        paths.insert(
+            "/commands/status/{command_id}".to_string(),
+            json!({ "get": submission_status_operation() }),
+        );
+
+        let responses: Map<String, Value> = ErrorStatus::ALL
+            .iter()
+            .map(|status| (response_name(*status), error_response(*status)))
+            .collect();
+
+        json!({
+            "openapi": OPENAPI_VERSION,
+            "info": {
+                "title": "esruntime",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": paths,
+            "components": {
+                "schemas": schemas,
+                "responses": responses,
+            },
+        })
+    }
+}
+
+fn command_operation(name: &str, input_schema: &Value, idempotency_header: &HeaderName) -> Value {
+    let mut responses = Map::new();
+    responses.insert(
+        "200".to_string(),
+        json!({
+            "description": "The command was executed",
+            "headers": {
+                CORRELATION_ID_HEADER.as_str(): uuid_header("Correlation ID of the command"),
+                CAUSATION_ID_HEADER.as_str(): uuid_header("ID of the command, the causation ID of the emitted events"),
+                HANDLER_VERSION_HEADER.as_str(): {
+                    "description": "Version of the handler which executed the command",
+                    "schema": { "type": "string" },
+                },
+                IDEMPOTENCY_REPLAYED_HEADER.as_str(): {
+                    "description": "Set when the response was replayed for a previously used idempotency key",
+                    "schema": { "type": "string", "const": "true" },
+                },
+            },
+            "content": {
+                "application/json": {
+                    "schema": { "$ref": "#/components/schemas/CommandResponse" },
+                },
+            },
+        }),
+    );
+    responses.insert(
+        "202".to_string(),
+        json!({
+            "description": "The command was submitted with `async=true`, and will be executed in the background",
+            "headers": {
+                "location": {
+                    "description": "Where to poll the status of the command",
+                    "schema": { "type": "string" },
+                },
+            },
+            "content": {
+                "application/json": {
+                    "schema": {
+                        "type": "object",
+                        "required": ["status", "command_id", "correlation_id", "status_url"],
+                        "properties": {
+                            "status": { "type": "string", "const": "pending" },
+                            "command_id": { "type": "string", "format": "uuid" },
+                            "correlation_id": { "type": "string", "format": "uuid" },
+                            "status_url": { "type": "string" },
+                        },
+                    },
+                },
+            },
+        }),
+    );
+    // Only uploaded handlers exhaust their resources, which share the 422 status with rejections.
+    for status in ErrorStatus::ALL
+        .into_iter()
+        .filter(|status| *status != ErrorStatus::ResourceExhausted)
+    {
+        responses.insert(
+            status.status_code().as_u16().to_string(),
+            json!({ "$ref": format!("#/components/responses/{}", response_name(status)) }),
+        );
+    }
+    responses.insert(
+        ErrorStatus::PreconditionFailed
+            .status_code()
+            .as_u16()
+            .to_string(),
+        json!({
+            "description": "Events matching the command's query were appended after the position in `If-Match`",
+            "content": error_response(ErrorStatus::PreconditionFailed)["content"].clone(),
+        }),
+    );
+
+    json!({
+        "operationId": name,
+        "tags": ["commands"],
+        "parameters": [
+            {
+                "name": idempotency_header.as_str(),
+                "in": "header",
+                "description": "Ensures the command is executed at most once",
+                "schema": { "type": "string" },
+            },
+            {
+                "name": CORRELATION_ID_HEADER.as_str(),
+                "in": "header",
+                "description": "Continues an existing correlation",
+                "schema": { "type": "string", "format": "uuid" },
+            },
+            {
+                "name": CAUSATION_ID_HEADER.as_str(),
+                "in": "header",
+                "description": "ID of the event or command which triggered this command",
+                "schema": { "type": "string", "format": "uuid" },
+            },
+            {
+                "name": HANDLER_VERSION_HEADER.as_str(),
+                "in": "header",
+                "description": "Version of the handler to execute, defaulting to the most recently registered",
+                "schema": { "type": "string" },
+            },
+            {
+                "name": IF_MATCH.as_str(),
+                "in": "header",
+                "description": "Position the client last observed, eg. `\"12\"`. The command fails with `412 Precondition Failed` if events matching its query were appended after it",
+                "schema": { "type": "string" },
+            },
+            {
+                "name": "async",
+                "in": "query",
+                "description": "Executes the command in the background, responding with `202 Accepted`",
+                "schema": { "type": "boolean" },
+            },
+        ],
+        "requestBody": {
+            "required": true,
+            "content": {
+                "application/json": { "schema": input_schema },
+            },
+        },
+        "responses": responses,
+    })
+}
+
+/// Operation executing an uploaded WASM handler, whose input schema is not known.
+fn wasm_handler_operation(idempotency_header: &HeaderName) -> Value {
+    let mut operation = command_operation("execute_handler", &json!({}), idempotency_header);
+    operation["description"] =
+        "Executes a WASM handler uploaded with `PUT /handlers/{command_name}`. Fails with \
+         `404 Not Found` and the `handler_not_found` code if no handler was uploaded with the name"
+            .into();
+    if let Some(parameters) = operation["parameters"].as_array_mut() {
+        parameters.insert(
+            0,
+            json!({
+                "name": "command_name",
+                "in": "path",
+                "required": true,
+                "description": "Name the handler was uploaded with",
+                "schema": { "type": "string" },
+            }),
+        );
+    }
+    operation
+}
+
+fn command_response_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["status", "events", "result", "position", "correlation_id", "causation_id"],
+        "properties": {
+            "status": { "type": "string", "const": "ok" },
+            "events": {
+                "type": "array",
+                "items": { "$ref": "#/components/schemas/Event" },
+            },
+            "result": {
+                "description": "The output returned by the command with `Emit::output`, or `null`",
+            },
+            "position": {
+                "type": ["integer", "null"],
+                "format": "uint64",
+                "minimum": 0,
+                "description": "Position of the last event, usable with the `X-Min-Position` header",
+            },
+            "correlation_id": { "type": "string", "format": "uuid" },
+            "causation_id": { "type": "string", "format": "uuid" },
+        },
+    })
+}
+
+fn submission_status_operation() -> Value {
+    json!({
+        "operationId": "command_status",
+        "tags": ["commands"],
+        "parameters": [
+            {
+                "name": "command_id",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string", "format": "uuid" },
+            },
+        ],
+        "responses": {
+            "200": {
+                "description": "The status of a command submitted with `async=true`",
+                "content": {
+                    "application/json": {
+                        "schema": { "$ref": "#/components/schemas/SubmissionStatus" },
+                    },
+                },
+            },
+            "404": { "$ref": "#/components/responses/NotFound" },
+        },
+    })
+}
+
+fn submission_status_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["status", "command_id", "command"],
+        "properties": {
+            "status": {
+                "type": "string",
+                "enum": ["pending", "succeeded", "rejected", "failed"],
+            },
+            "command_id": { "type": "string", "format": "uuid" },
+            "command": { "type": "string" },
+            "events": {
+                "type": "array",
+                "items": { "$ref": "#/components/schemas/Event" },
+                "description": "The emitted events, once succeeded",
+            },
+            "result": { "description": "The output of the command, once succeeded" },
+            "position": { "type": ["integer", "null"], "format": "uint64", "minimum": 0 },
+            "error": {
+                "$ref": "#/components/schemas/ErrorResponse",
+                "description": "Why the command was rejected or failed",
+            },
+        },
+    })
+}
+
+fn event_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["id", "type", "data", "tags"],
+        "properties": {
+            "id": { "type": ["string", "null"], "format": "uuid" },
+            "type": { "type": "string" },
+            "data": {
+                "description": "The event data, or a base64 string if it is not JSON",
+            },
+            "tags": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Domain IDs formatted as `category:value`",
+            },
+        },
+    })
+}
+
+fn error_response_schema() -> Value {
+    let statuses: Vec<_> = ErrorStatus::ALL.iter().map(ErrorStatus::as_str).collect();
+
+    json!({
+        "type": "object",
+        "required": ["status", "code"],
+        "properties": {
+            "status": { "type": "string", "enum": statuses },
+            "code": { "type": "string" },
+            "message": { "type": "string" },
+            "errors": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["path", "message"],
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "JSON pointer to the input field, eg. `/amount`",
+                        },
+                        "message": { "type": "string" },
+                    },
+                },
+                "description": "Errors of individual input fields",
+            },
+            "details": {
+                "description": "Structured details of the error, specific to its code",
+            },
+            "request_id": { "type": "string", "format": "uuid" },
+        },
+    })
+}
+
+fn error_response(status: ErrorStatus) -> Value {
+    let status_code = status.status_code();
+
+    json!({
+        "description": status_code.canonical_reason().unwrap_or(status.as_str()),
+        "content": {
+            "application/json": {
+                "schema": {
+                    "allOf": [
+                        { "$ref": "#/components/schemas/ErrorResponse" },
+                        { "properties": { "status": { "const": status.as_str() } } },
+                    ],
+                },
+            },
+        },
+    })
+}
+
+fn uuid_header(description: &str) -> Value {
+    json!({
+        "description": description,
+        "schema": { "type": "string", "format": "uuid" },
+    })
+}
+
+/// Converts a status like `invalid_input` to a component name like `InvalidInput`.
+fn response_name(status: ErrorStatus) -> String {
+    status
+        .as_str()
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::idempotency::DEFAULT_IDEMPOTENCY_KEY_HEADER;
+
+    use super::*;
+
+    #[derive(Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct TransferFundsInput {
+        from_account_id: String,
+        to_account_id: String,
+        amount: f64,
+    }
+
+    #[test]
+    fn documents_registered_commands() {
+        let mut openapi = OpenApi::new();
+        openapi.add_command::<TransferFundsInput>("transfer_funds");
+        let document = openapi.document(&DEFAULT_IDEMPOTENCY_KEY_HEADER);
+
+        assert_eq!(document["openapi"], "3.1.0");
+
+        let operation = &document["paths"]["/commands/transfer_funds"]["post"];
+        assert_eq!(operation["operationId"], "transfer_funds");
+        assert_eq!(
+            operation["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/TransferFundsInput"
+        );
+        assert_eq!(
+            operation["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CommandResponse"
+        );
+        assert_eq!(
+            operation["responses"]["422"]["$ref"],
+            "#/components/responses/Rejected"
+        );
+        let parameters = operation["parameters"].as_array().unwrap();
+        for header in [
+            "if-match",
+            "x-handler-version",
+            DEFAULT_IDEMPOTENCY_KEY_HEADER.as_str(),
+        ] {
+            assert!(
+                parameters
+                    .iter()
+                    .any(|parameter| parameter["name"] == header),
+                "{header}"
+            );
+        }
+        assert_eq!(
+            operation["responses"]["412"]["content"]["application/json"]["schema"]["allOf"][1]["properties"]
+                ["status"]["const"],
+            "precondition_failed"
+        );
+
+        let input = &document["components"]["schemas"]["TransferFundsInput"];
+        assert_eq!(input["type"], "object");
+        assert_eq!(input["properties"]["amount"]["type"], "number");
+        assert!(document["components"]["responses"]["InvalidInput"].is_object());
+        assert!(document["paths"]["/commands/status/{command_id}"]["get"].is_object());
+        assert!(document["paths"]["/commands/{command_name}"].is_null());
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn documents_wasm_handlers() {
+        let mut openapi = OpenApi::new();
+        openapi.add_wasm_handlers();
+        let document = openapi.document(&DEFAULT_IDEMPOTENCY_KEY_HEADER);
+
+        let operation = &document["paths"]["/commands/{command_name}"]["post"];
+        assert_eq!(operation["operationId"], "execute_handler");
+        assert_eq!(operation["parameters"][0]["in"], "path");
+        assert_eq!(operation["parameters"][0]["name"], "command_name");
+        assert!(
+            operation["parameters"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|parameter| parameter["name"] == "x-handler-version")
+        );
+    }
+
+    #[test]
+    fn response_names_are_pascal_case() {
+        assert_eq!(response_name(ErrorStatus::InvalidInput), "InvalidInput");
+        assert_eq!(response_name(ErrorStatus::Rejected), "Rejected");
+    }
+}
@@ -0,0 +1,354 @@
+//! The event types known to the server, and the ESDL schema describing them.
+//!
+//! A [`SchemaRegistry`] serves the schema at `GET /schema`, replaces it with `PUT /schema`, and
+//! reports what a new schema would change with `POST /schema/validate`. Breaking changes are
+//! rejected unless `?force=true` is set, in which case the major version is bumped.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    Json, Router,
+    extract::Query,
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use esruntime_schema::{Diagnostics, Schema, SchemaChanges};
+use esruntime_sdk::event::Event;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::error::{Error, ErrorStatus};
+
+/// Version of the first schema registered.
+const INITIAL_VERSION: &str = "0.1.0";
+
+/// Event types and their domain ID fields, used to validate uploaded handlers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventSchema {
+    events: BTreeMap<String, Vec<String>>,
+}
+
+impl EventSchema {
+    pub fn new() -> Self {
+        EventSchema::default()
+    }
+
+    /// Adds an event type with its domain ID fields.
+    pub fn event_type<I>(mut self, event_type: impl Into<String>, domain_ids: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.events.insert(
+            event_type.into(),
+            domain_ids.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    /// Adds an event defined in Rust.
+    pub fn event<E: Event>(self) -> Self {
+        self.event_type(E::EVENT_TYPE, E::DOMAIN_ID_FIELDS.iter().copied())
+    }
+
+    /// Returns whether the schema contains an event type.
+    pub fn contains(&self, event_type: &str) -> bool {
+        self.events.contains_key(event_type)
+    }
+
+    /// Returns the domain ID fields of an event type.
+    pub fn domain_ids(&self, event_type: &str) -> Option<&[String]> {
+        self.events.get(event_type).map(Vec::as_slice)
+    }
+
+    /// Returns the event types, sorted by name.
+    pub fn event_types(&self) -> impl Iterator<Item = &str> {
+        self.events.keys().map(String::as_str)
+    }
+}
+
+impl From<&Schema> for EventSchema {
+    fn from(schema: &Schema) -> Self {
+        schema
+            .events
+            .iter()
+            .fold(EventSchema::new(), |event_schema, event| {
+                event_schema.event_type(&event.name, event.domain_ids())
+            })
+    }
+}
+
+/// A version of the ESDL schema.
+#[derive(Clone, Debug)]
+pub struct SchemaVersion {
+    pub version: String,
+    /// The ESDL source, as it was registered.
+    pub source: String,
+    pub schema: Schema,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SchemaVersion {
+    /// Returns the body of `GET /schema`.
+    fn to_json(&self) -> Value {
+        let events: Vec<_> = self
+            .schema
+            .events
+            .iter()
+            .map(|event| {
+                let fields: Vec<_> = event
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        json!({
+                            "name": field.name,
+                            "type": field.ty,
+                            "domain_id": field.domain_id,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "name": event.name,
+                    "domain_ids": event.domain_ids().collect::<Vec<_>>(),
+                    "fields": fields,
+                })
+            })
+            .collect();
+
+        json!({
+            "version": self.version,
+            "schema": self.source,
+            "events": events,
+            "updated_at": self.updated_at,
+        })
+    }
+}
+
+/// The version a schema was, or would be, registered as, and what it changed.
+#[derive(Clone, Debug, Serialize)]
+pub struct SchemaUpdate {
+    pub version: String,
+    pub changes: SchemaChanges,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SchemaParams {
+    /// Applies breaking changes as a new major version.
+    #[serde(default)]
+    force: bool,
+}
+
+type Subscriber = Box<dyn Fn(&Schema) + Send + Sync>;
+
+/// The current ESDL schema, replaced at runtime with `PUT /schema`.
+#[derive(Clone, Default)]
+pub struct SchemaRegistry {
+    current: Arc<RwLock<Option<Arc<SchemaVersion>>>>,
+    subscribers: Arc<RwLock<Vec<Subscriber>>>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        SchemaRegistry::default()
+    }
+
+    /// Creates a registry with an initial schema, eg. one loaded from a file at startup.
+    pub fn with_schema(source: &str) -> Result<Self, Diagnostics> {
+        let registry = SchemaRegistry::new();
+        *registry.current.write().expect("schema lock poisoned") = Some(Arc::new(SchemaVersion {
+            version: INITIAL_VERSION.to_string(),
+            source: source.to_string(),
+            schema: esruntime_schema::parse(source)?,
+            updated_at: Utc::now(),
+        }));
+        Ok(registry)
+    }
+
+    /// Returns the current schema, if one is registered.
+    pub fn current(&self) -> Option<Arc<SchemaVersion>> {
+        self.current.read().expect("schema lock poisoned").clone()
+    }
+
+    /// Calls `f` with the current schema, and with each schema registered after it.
+    pub fn subscribe(&self, f: impl Fn(&Schema) + Send + Sync + 'static) {
+        let current = self.current.read().expect("schema lock poisoned");
+        if let Some(current) = current.as_ref() {
+            f(&current.schema);
+        }
+        self.subscribers
+            .write()
+            .expect("subscribers lock poisoned")
+            .push(Box::new(f));
+    }
+
+    /// Returns what registering a schema would change, without registering it.
+    pub fn validate(&self, source: &str, force: bool) -> Result<SchemaUpdate, Error> {
+        let current = self.current();
+        plan(current.as_deref(), source, force).map(|(_, update)| update)
+    }
+
+    /// Registers a schema, failing if it is invalid or has breaking changes and `force` is not set.
+    pub fn update(&self, source: &str, force: bool) -> Result<SchemaUpdate, Error> {
+        let mut current = self.current.write().expect("schema lock poisoned");
+        let (schema, update) = plan(current.as_deref(), source, force)?;
+
+        for subscriber in self
+            .subscribers
+            .read()
+            .expect("subscribers lock poisoned")
+            .iter()
+        {
+            subscriber(&schema);
+        }
+        *current = Some(Arc::new(SchemaVersion {
+            version: update.version.clone(),
+            source: source.to_string(),
+            schema,
+            updated_at: Utc::now(),
+        }));
+
+        Ok(update)
+    }
+
+    /// Routes for reading, updating and validating the schema.
+    pub(crate) fn routes<S>(&self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let current = self.clone();
+        let update = self.clone();
+        let validate = self.clone();
+        Router::new()
+            .route(
+                "/schema",
+                get(|| async move {
+                    current
+                        .current()
+                        .map(|version| Json(version.to_json()))
+                        .ok_or_else(|| {
+                            Error::new(ErrorStatus::NotFound, "schema_not_found")
+                                .with_message("no schema is registered")
+                        })
+                })
+                .put(
+                    |Query(params): Query<SchemaParams>, source: String| async move {
+                        update.update(&source, params.force).map(Json)
+                    },
+                ),
+            )
+            .route(
+                "/schema/validate",
+                post(
+                    |Query(params): Query<SchemaParams>, source: String| async move {
+                        validate.validate(&source, params.force).map(Json)
+                    },
+                ),
+            )
+    }
+}
+
+/// Parses a schema and computes its changes from the current version.
+fn plan(
+    current: Option<&SchemaVersion>,
+    source: &str,
+    force: bool,
+) -> Result<(Schema, SchemaUpdate), Error> {
+    let schema = esruntime_schema::parse(source).map_err(|diagnostics| {
+        Error::new(ErrorStatus::InvalidInput, "invalid_schema")
+            .with_message(format!("schema has {} errors", diagnostics.len()))
+            .with_details(json!({ "diagnostics": diagnostics }))
+    })?;
+    let Some(current) = current else {
+        let changes = SchemaChanges {
+            added_events: schema
+                .events
+                .iter()
+                .map(|event| event.name.clone())
+                .collect(),
+            ..SchemaChanges::default()
+        };
+        let version = INITIAL_VERSION.to_string();
+        return Ok((schema, SchemaUpdate { version, changes }));
+    };
+
+    let changes = esruntime_schema::diff(&current.schema, &schema);
+    if changes.is_breaking() && !force {
+        return Err(Error::new(ErrorStatus::Rejected, "breaking_changes")
+            .with_message("schema contains changes which are incompatible with stored events")
+            .with_details(json!({
+                "breaking_changes": changes.breaking_changes,
+                "hint": "Set `force=true` to apply them as a new major version",
+            })));
+    }
+
+    let version = next_version(&current.version, &changes);
+    Ok((schema, SchemaUpdate { version, changes }))
+}
+
+/// Bumps the major version for breaking changes, and the minor version for other changes.
+fn next_version(version: &str, changes: &SchemaChanges) -> String {
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    let (major, minor, patch) = (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    );
+    if changes.is_breaking() {
+        format!("{}.0.0", major + 1)
+    } else if !changes.is_empty() {
+        format!("{major}.{}.0", minor + 1)
+    } else {
+        format!("{major}.{minor}.{patch}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn versions_schema_updates() {
+        let registry = SchemaRegistry::new();
+        let event_schemas = Arc::new(Mutex::new(Vec::new()));
+        registry.subscribe({
+            let event_schemas = event_schemas.clone();
+            move |schema| {
+                event_schemas
+                    .lock()
+                    .unwrap()
+                    .push(EventSchema::from(schema))
+            }
+        });
+
+        let update = registry.update("event A { @id: String }", false).unwrap();
+        assert_eq!(update.version, "0.1.0");
+        assert_eq!(update.changes.added_events, ["A"]);
+
+        let update = registry
+            .validate("event A { @id: String }\nevent B { x: Int }", false)
+            .unwrap();
+        assert_eq!(update.version, "0.2.0");
+        assert_eq!(registry.current().unwrap().version, "0.1.0");
+
+        let err = registry.update("event B { x: Int }", false).unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::Rejected);
+        let update = registry.update("event B { x: Int }", true).unwrap();
+        assert_eq!(update.version, "1.0.0");
+        assert_eq!(update.changes.removed_events, ["A"]);
+
+        let err = registry.update("event {", false).unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::InvalidInput);
+
+        let event_schemas = event_schemas.lock().unwrap();
+        assert_eq!(event_schemas.len(), 2);
+        assert_eq!(event_schemas[0].domain_ids("A").unwrap(), ["id"]);
+        assert!(event_schemas[1].contains("B"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    http::{HeaderMap, HeaderName},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use umadb_client::AsyncUmaDBClient;
+use umadb_dcb::{DCBError, DCBEventStoreAsync, DCBQuery, DCBQueryItem, DCBSequencedEvent};
+
+use crate::{
+    error::{Error, ErrorStatus},
+    event_json,
+};
+
+/// Header sent by `EventSource` clients when reconnecting, carrying the position of the last event received.
+pub const LAST_EVENT_ID_HEADER: HeaderName = HeaderName::from_static("last-event-id");
+
+/// Query parameters of an event subscription.
+///
+/// Without a start position, only events appended after subscribing are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamParams {
+    /// Comma separated event types, eg. `SentFunds,ReceivedFunds`.
+    pub event_types: Option<String>,
+    /// Comma separated domain IDs, eg. `account_id:alice,account_id:bob`.
+    ///
+    /// Events with any of the domain IDs are sent.
+    pub domain_ids: Option<String>,
+    /// Only send events after this position.
+    ///
+    /// Overridden by the `Last-Event-ID` header.
+    pub after_position: Option<u64>,
+}
+
+impl StreamParams {
+    fn query(&self) -> Result<Option<DCBQuery>, Error> {
+        let types = split_list(self.event_types.as_deref());
+        let domain_ids = split_list(self.domain_ids.as_deref());
+
+        if let Some(domain_id) = domain_ids.iter().find(|domain_id| !domain_id.contains(':')) {
+            return Err(
+                Error::new(ErrorStatus::InvalidInput, "invalid_domain_id").with_message(format!(
+                    "domain id `{domain_id}` must be formatted as `category:value`"
+                )),
+            );
+        }
+
+        let items = if domain_ids.is_empty() {
+            if types.is_empty() {
+                return Ok(None);
+            }
+            vec![DCBQueryItem {
+                types,
+                tags: Vec::new(),
+            }]
+        } else {
+            domain_ids
+                .into_iter()
+                .map(|domain_id| DCBQueryItem {
+                    types: types.clone(),
+                    tags: vec![domain_id],
+                })
+                .collect()
+        };
+
+        Ok(Some(DCBQuery { items }))
+    }
+
+    fn after_position(&self, headers: &HeaderMap) -> Result<Option<u64>, Error> {
+        let Some(value) = headers.get(&LAST_EVENT_ID_HEADER) else {
+            return Ok(self.after_position);
+        };
+
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                Error::new(ErrorStatus::InvalidInput, "invalid_header")
+                    .with_message(format!("{LAST_EVENT_ID_HEADER} header must be a position"))
+            })
+    }
+}
+
+/// Subscribes to the events matching `params`, continuing once caught up with the head.
+pub(crate) async fn subscribe(
+    umadb_client: &AsyncUmaDBClient,
+    params: &StreamParams,
+    headers: &HeaderMap,
+) -> Result<impl Stream<Item = Result<DCBSequencedEvent, DCBError>> + Send + use<>, Error> {
+    let query = params.query()?;
+    let after = match params.after_position(headers)? {
+        Some(after) => after,
+        None => umadb_client.head().await?.unwrap_or(0),
+    };
+
+    let stream = umadb_client
+        .read(query, Some(after.saturating_add(1)), false, None, true)
+        .await?;
+
+    Ok(stream)
+}
+
+/// Serializes an event like the events of a command response, including its position.
+pub(crate) fn sequenced_event_json(event: &DCBSequencedEvent) -> Value {
+    let mut value = event_json(&event.event);
+    value["position"] = json!(event.position);
+    value
+}
+
+/// Streams events as Server-Sent Events, with the position as the event ID.
+///
+/// Subscription errors are sent as an `error` event, closing the stream.
+pub(crate) async fn sse(
+    umadb_client: Arc<AsyncUmaDBClient>,
+    params: StreamParams,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>> + Send + use<>>, Error> {
+    let events = subscribe(&umadb_client, &params, &headers).await?;
+
+    let events = events.scan(false, |failed, result| {
+        if *failed {
+            return std::future::ready(None);
+        }
+
+        let event = match result {
+            Ok(event) => Event::default()
+                .id(event.position.to_string())
+                .json_data(sequenced_event_json(&event))
+                .unwrap_or_else(|err| Event::default().event("error").data(err.to_string())),
+            Err(err) => {
+                *failed = true;
+                Event::default().event("error").data(err.to_string())
+            }
+        };
+        std::future::ready(Some(Ok(event)))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Streams events over a WebSocket as JSON text messages.
+///
+/// Subscription errors are sent as an `{"error": ...}` message before closing the socket.
+#[cfg(feature = "ws")]
+pub(crate) async fn websocket(
+    umadb_client: Arc<AsyncUmaDBClient>,
+    params: StreamParams,
+    headers: HeaderMap,
+    upgrade: axum::extract::ws::WebSocketUpgrade,
+) -> Result<axum::response::Response, Error> {
+    use axum::extract::ws::{Message, WebSocket};
+
+    let mut events = Box::pin(subscribe(&umadb_client, &params, &headers).await?);
+
+    Ok(upgrade.on_upgrade(move |mut socket: WebSocket| async move {
+        loop {
+            tokio::select! {
+                result = events.next() => {
+                    let msg = match result {
+                        Some(Ok(event)) => sequenced_event_json(&event),
+                        Some(Err(err)) => json!({ "error": err.to_string() }),
+                        None => break,
+                    };
+                    let failed = msg.get("error").is_some();
+                    if socket.send(Message::Text(msg.to_string().into())).await.is_err() || failed {
+                        break;
+                    }
+                }
+                msg = socket.recv() => {
+                    match msg {
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {}
+                    }
+                }
+            }
+        }
+        let _ = socket.send(Message::Close(None)).await;
+    }))
+}
+
+fn split_list(list: Option<&str>) -> Vec<String> {
+    list.into_iter()
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn params(event_types: Option<&str>, domain_ids: Option<&str>) -> StreamParams {
+        StreamParams {
+            event_types: event_types.map(str::to_string),
+            domain_ids: domain_ids.map(str::to_string),
+            after_position: None,
+        }
+    }
+
+    #[test]
+    fn no_filters_reads_everything() {
+        assert!(params(None, None).query().unwrap().is_none());
+        assert!(params(Some(""), Some(" ")).query().unwrap().is_none());
+    }
+
+    #[test]
+    fn event_types_filter() {
+        let query = params(Some("SentFunds, ReceivedFunds"), None)
+            .query()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(query.items.len(), 1);
+        assert_eq!(query.items[0].types, ["SentFunds", "ReceivedFunds"]);
+        assert!(query.items[0].tags.is_empty());
+    }
+
+    #[test]
+    fn domain_ids_match_any() {
+        let query = params(Some("SentFunds"), Some("account_id:alice,account_id:bob"))
+            .query()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(query.items.len(), 2);
+        assert_eq!(query.items[0].types, ["SentFunds"]);
+        assert_eq!(query.items[0].tags, ["account_id:alice"]);
+        assert_eq!(query.items[1].tags, ["account_id:bob"]);
+    }
+
+    #[test]
+    fn invalid_domain_id_is_rejected() {
+        assert!(params(None, Some("alice")).query().is_err());
+    }
+
+    #[test]
+    fn last_event_id_overrides_after_position() {
+        let params = StreamParams {
+            after_position: Some(10),
+            ..StreamParams::default()
+        };
+        assert_eq!(params.after_position(&HeaderMap::new()).unwrap(), Some(10));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(LAST_EVENT_ID_HEADER, HeaderValue::from_static("42"));
+        assert_eq!(params.after_position(&headers).unwrap(), Some(42));
+
+        headers.insert(LAST_EVENT_ID_HEADER, HeaderValue::from_static("abc"));
+        assert!(params.after_position(&headers).is_err());
+    }
+}
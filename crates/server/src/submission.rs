@@ -0,0 +1,431 @@
+//! Asynchronous command submission.
+//!
+//! Commands posted with `?async=true` are accepted with `202 Accepted` and executed by a
+//! pool of background workers, retrying conflicts. Their status is polled with
+//! `GET /commands/status/{command_id}`.
+
+use std::{
+    collections::HashMap,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{LOCATION, RETRY_AFTER},
+    },
+    response::{IntoResponse, Response},
+};
+use esruntime_sdk::command::CommandContext;
+use futures_util::FutureExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    CommandState, Executor, command_response,
+    config::duration_secs,
+    context::context_headers,
+    error::{Error, ErrorStatus},
+};
+
+/// Configuration for asynchronously submitted commands.
+///
+/// Defaults to 16 workers with up to 1,024 commands queued or running, retrying conflicts up
+/// to 3 times starting with a 100ms backoff of up to 5s, and keeping finished statuses for an
+/// hour.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubmissionConfig {
+    workers: usize,
+    max_queued: usize,
+    max_retries: u32,
+    #[serde(with = "duration_secs")]
+    retry_backoff: Duration,
+    #[serde(with = "duration_secs")]
+    max_retry_backoff: Duration,
+    #[serde(with = "duration_secs")]
+    expire_after: Duration,
+}
+
+impl SubmissionConfig {
+    pub fn new() -> Self {
+        SubmissionConfig::default()
+    }
+
+    /// Sets how many submitted commands are executed concurrently.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets how many submitted commands can be queued or running at once.
+    ///
+    /// Further submissions fail with `503 Service Unavailable` until a command finishes.
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    /// Sets how many times a command is retried when it conflicts or the event store is unavailable.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, doubling with each subsequent retry.
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Sets the maximum delay between retries.
+    pub fn with_max_retry_backoff(mut self, max_retry_backoff: Duration) -> Self {
+        self.max_retry_backoff = max_retry_backoff;
+        self
+    }
+
+    /// Sets how long the status of a finished command is kept for.
+    pub fn with_expire_after(mut self, expire_after: Duration) -> Self {
+        self.expire_after = expire_after;
+        self
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    pub fn retry_backoff(&self) -> Duration {
+        self.retry_backoff
+    }
+
+    pub fn max_retry_backoff(&self) -> Duration {
+        self.max_retry_backoff
+    }
+
+    pub fn expire_after(&self) -> Duration {
+        self.expire_after
+    }
+}
+
+impl Default for SubmissionConfig {
+    fn default() -> Self {
+        SubmissionConfig {
+            workers: 16,
+            max_queued: 1024,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(100),
+            max_retry_backoff: Duration::from_secs(5),
+            expire_after: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Query parameters of a command request.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ExecuteParams {
+    /// Executes the command in the background, responding with `202 Accepted`.
+    #[serde(rename = "async")]
+    pub(crate) run_async: bool,
+}
+
+/// Statuses of submitted commands, and the workers executing them.
+///
+/// Statuses are kept in memory, so are lost on restart along with any pending commands.
+#[derive(Clone)]
+pub(crate) struct Submissions {
+    statuses: Arc<Mutex<HashMap<Uuid, Submission>>>,
+    workers: Arc<Semaphore>,
+    /// Permits for commands which are queued or running.
+    queue: Arc<Semaphore>,
+    config: SubmissionConfig,
+}
+
+struct Submission {
+    command: Arc<str>,
+    status: SubmissionStatus,
+    updated_at: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum SubmissionStatus {
+    Pending,
+    /// The command response body.
+    Succeeded(Value),
+    /// The command was rejected, with the error response body.
+    Rejected(Value),
+    /// The command could not be executed, with the error response body.
+    Failed(Value),
+}
+
+impl Submissions {
+    pub(crate) fn new(config: SubmissionConfig) -> Self {
+        Submissions {
+            statuses: Arc::default(),
+            workers: Arc::new(Semaphore::new(config.workers.max(1))),
+            queue: Arc::new(Semaphore::new(config.max_queued.max(1))),
+            config,
+        }
+    }
+
+    /// Queues a command for execution, responding with `202 Accepted` and where to poll its status.
+    ///
+    /// Each attempt is limited to `timeout`. Fails with `503 Service Unavailable` when the queue
+    /// is full.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn submit(
+        &self,
+        command: Arc<str>,
+        execute: Executor,
+        state: CommandState,
+        context: CommandContext,
+        headers: HeaderMap,
+        input: Value,
+        timeout: Duration,
+    ) -> Result<Response, Error> {
+        let queued = self.reserve()?;
+        let command_id = context.command_id;
+        self.set_status(command_id, &command, SubmissionStatus::Pending);
+
+        let location = format!("/commands/status/{command_id}");
+        let mut resp_headers = context_headers(&context);
+        if let Ok(location) = HeaderValue::try_from(&location) {
+            resp_headers.insert(LOCATION, location);
+        }
+        let body = json!({
+            "status": "pending",
+            "command_id": command_id,
+            "correlation_id": context.correlation_id,
+            "status_url": location,
+        });
+
+        let submissions = self.clone();
+        tokio::spawn(async move {
+            let _queued = queued;
+            let _permit = submissions
+                .workers
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("worker semaphore is never closed");
+            let run = submissions.run(&command, execute, state, context, headers, input, timeout);
+            // A panicking command would otherwise be left pending forever.
+            let status = AssertUnwindSafe(run)
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|_| {
+                    let err = Error::new(ErrorStatus::Internal, "internal")
+                        .with_message("the command panicked");
+                    SubmissionStatus::Failed(serde_json::to_value(err.body()).unwrap_or_default())
+                });
+            submissions.set_status(command_id, &command, status);
+        });
+
+        Ok((StatusCode::ACCEPTED, resp_headers, Json(body)).into_response())
+    }
+
+    /// Reserves a place in the queue, held until the command finishes.
+    fn reserve(&self) -> Result<OwnedSemaphorePermit, Error> {
+        self.queue.clone().try_acquire_owned().map_err(|_| {
+            Error::new(ErrorStatus::Unavailable, "submission_queue_full")
+                .with_message("too many commands are queued, try again later")
+                .with_header(RETRY_AFTER, self.config.retry_backoff.as_secs().max(1))
+        })
+    }
+
+    /// Returns the status of a submitted command.
+    pub(crate) fn status(&self, command_id: Uuid) -> Result<Json<Value>, Error> {
+        let statuses = self.statuses.lock().expect("statuses lock poisoned");
+        let submission = statuses.get(&command_id).ok_or_else(|| {
+            Error::new(ErrorStatus::NotFound, "submission_not_found")
+                .with_message(format!("no command was submitted with ID `{command_id}`"))
+        })?;
+
+        Ok(Json(status_json(
+            command_id,
+            &submission.command,
+            &submission.status,
+        )))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run(
+        &self,
+        command: &str,
+        execute: Executor,
+        state: CommandState,
+        context: CommandContext,
+        headers: HeaderMap,
+        input: Value,
+        timeout: Duration,
+    ) -> SubmissionStatus {
+        let mut backoff = self.config.retry_backoff;
+        let mut retries = 0;
+        loop {
+            let attempt = execute(
+                state.clone(),
+                context.clone(),
+                headers.clone(),
+                input.clone(),
+            );
+            let result = tokio::time::timeout(timeout, attempt)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(Error::new(ErrorStatus::Unavailable, "timeout")
+                        .with_message("the command did not complete in time"))
+                });
+
+            match result {
+                Ok((context, result)) => {
+                    let (_, Json(body)) = command_response(&context, &result);
+                    return SubmissionStatus::Succeeded(body);
+                }
+                Err(err) if is_retryable(&err) && retries < self.config.max_retries => {
+                    retries += 1;
+                    warn!(%command, command_id = %context.command_id, retries, "submitted command failed, retrying in {backoff:?}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2).min(self.config.max_retry_backoff);
+                }
+                Err(err) => {
+                    let body = serde_json::to_value(err.body()).unwrap_or_default();
+                    return if is_retryable(&err)
+                        || matches!(
+                            err.status(),
+                            ErrorStatus::Internal | ErrorStatus::ResourceExhausted
+                        ) {
+                        SubmissionStatus::Failed(body)
+                    } else {
+                        SubmissionStatus::Rejected(body)
+                    };
+                }
+            }
+        }
+    }
+
+    /// Sets the status of a command, removing expired statuses of finished commands.
+    fn set_status(&self, command_id: Uuid, command: &Arc<str>, status: SubmissionStatus) {
+        let now = Instant::now();
+        let mut statuses = self.statuses.lock().expect("statuses lock poisoned");
+        statuses.retain(|_, submission| {
+            submission.status == SubmissionStatus::Pending
+                || now.duration_since(submission.updated_at) < self.config.expire_after
+        });
+        statuses.insert(
+            command_id,
+            Submission {
+                command: command.clone(),
+                status,
+                updated_at: now,
+            },
+        );
+    }
+}
+
+/// Conflicts and an unavailable event store are worth retrying.
+fn is_retryable(err: &Error) -> bool {
+    matches!(
+        err.status(),
+        ErrorStatus::Conflict | ErrorStatus::Unavailable
+    )
+}
+
+fn status_json(command_id: Uuid, command: &str, status: &SubmissionStatus) -> Value {
+    let (status, mut body) = match status {
+        SubmissionStatus::Pending => ("pending", json!({})),
+        SubmissionStatus::Succeeded(body) => ("succeeded", body.clone()),
+        SubmissionStatus::Rejected(error) => ("rejected", json!({ "error": error })),
+        SubmissionStatus::Failed(error) => ("failed", json!({ "error": error })),
+    };
+    body["status"] = status.into();
+    body["command_id"] = json!(command_id);
+    body["command"] = command.into();
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn succeeded_status_includes_response() {
+        let command_id = Uuid::new_v4();
+        let status = status_json(
+            command_id,
+            "transfer_funds",
+            &SubmissionStatus::Succeeded(json!({
+                "status": "ok",
+                "events": [],
+                "result": null,
+                "position": 12,
+            })),
+        );
+
+        assert_eq!(status["status"], "succeeded");
+        assert_eq!(status["command_id"], json!(command_id));
+        assert_eq!(status["command"], "transfer_funds");
+        assert_eq!(status["position"], 12);
+    }
+
+    #[test]
+    fn rejected_status_includes_error() {
+        let err = Error::new(ErrorStatus::Rejected, "insufficient_funds");
+        let status = status_json(
+            Uuid::new_v4(),
+            "transfer_funds",
+            &SubmissionStatus::Rejected(serde_json::to_value(err.body()).unwrap()),
+        );
+
+        assert_eq!(status["status"], "rejected");
+        assert_eq!(status["error"]["code"], "insufficient_funds");
+    }
+
+    #[test]
+    fn unknown_submissions_are_not_found() {
+        let submissions = Submissions::new(SubmissionConfig::default());
+        let err = submissions.status(Uuid::new_v4()).unwrap_err();
+
+        assert_eq!(err.status(), ErrorStatus::NotFound);
+    }
+
+    #[test]
+    fn full_queues_are_unavailable() {
+        let submissions = Submissions::new(SubmissionConfig::default().with_max_queued(1));
+        let queued = submissions.reserve().unwrap();
+
+        let err = submissions.reserve().unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::Unavailable);
+        assert_eq!(err.into_response().headers()[RETRY_AFTER], "1");
+
+        drop(queued);
+        assert!(submissions.reserve().is_ok());
+    }
+
+    #[test]
+    fn finished_statuses_expire() {
+        let submissions =
+            Submissions::new(SubmissionConfig::default().with_expire_after(Duration::ZERO));
+        let command: Arc<str> = "transfer_funds".into();
+        let pending = Uuid::new_v4();
+        let finished = Uuid::new_v4();
+        submissions.set_status(pending, &command, SubmissionStatus::Pending);
+        submissions.set_status(finished, &command, SubmissionStatus::Succeeded(json!({})));
+        submissions.set_status(Uuid::new_v4(), &command, SubmissionStatus::Pending);
+
+        assert!(submissions.status(pending).is_ok());
+        assert!(submissions.status(finished).is_err());
+    }
+}
@@ -0,0 +1,1491 @@
+//! Command handlers uploaded as WASM modules.
+//!
+//! Modules implement the ABI described in [`esruntime_sdk::wasm`], and are uploaded with
+//! `PUT /handlers/{name}` to be executed at `POST /commands/{name}`. Each execution runs in a
+//! fresh instance, driven by the same read, apply, handle and append loop as
+//! [`Command::execute_with`](esruntime_sdk::command::Command::execute_with).
+//!
+//! Several versions of a handler can be uploaded under the same name. Requests select one with
+//! the `X-Handler-Version` header, defaulting to the most recently uploaded, and executions
+//! which already started keep running the version they selected while a new one is uploaded.
+//!
+//! Anyone able to upload a handler can append arbitrary events, so the handler routes should
+//! only be reachable by trusted callers.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, StatusCode, header::CONTENT_TYPE},
+    routing::{MethodRouter, get, post},
+};
+use chrono::{DateTime, Utc};
+use esruntime_sdk::{
+    command::{CommandContext, ExecuteResult, check_expected_position, replay_idempotent},
+    emit::encode_with_envelope,
+    error::{CommandError, ExecuteError, SerializationError},
+    event::StoredEventData,
+    wasm::{
+        ABI_VERSION, EXPORT_ABI_VERSION, EXPORT_ALLOC, EXPORT_APPLY, EXPORT_DEALLOC,
+        EXPORT_DESCRIBE, EXPORT_HANDLE, EXPORT_MEMORY, EXPORT_QUERY, WasmDescription, WasmEmit,
+        WasmEmittedEvent, WasmEvent, WasmQuery, WasmResult, unpack,
+    },
+};
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use umadb_dcb::{DCBAppendCondition, DCBEvent, DCBEventStoreAsync, DCBSequencedEvent};
+use uuid::Uuid;
+use wasmtime::{
+    Config, Engine, ExternType, Instance, Memory, Module, ResourceLimiter, Store, Trap, TypedFunc,
+};
+
+use crate::{
+    CommandState, Executor,
+    config::{CommandRouterConfig, duration_secs},
+    context::{HANDLER_VERSION_HEADER, RequestContext, handler_version, handler_version_not_found},
+    error::{Error, ErrorStatus},
+    schema::EventSchema,
+    submission::ExecuteParams,
+};
+
+/// Configuration for uploaded WASM handlers.
+///
+/// Defaults to accepting modules up to 16 MiB, executed with the default [`WasmLimits`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasmConfig {
+    max_module_size: usize,
+    limits: WasmLimits,
+    handler_limits: HashMap<String, WasmLimits>,
+}
+
+impl WasmConfig {
+    pub fn new() -> Self {
+        WasmConfig::default()
+    }
+
+    /// Sets the maximum size of an uploaded module in bytes.
+    pub fn with_max_module_size(mut self, max_module_size: usize) -> Self {
+        self.max_module_size = max_module_size;
+        self
+    }
+
+    /// Sets the default limits of every handler.
+    pub fn with_limits(mut self, limits: WasmLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Overrides the limits of a single handler.
+    pub fn with_handler_limits(mut self, handler: impl Into<String>, limits: WasmLimits) -> Self {
+        self.handler_limits.insert(handler.into(), limits);
+        self
+    }
+
+    pub fn max_module_size(&self) -> usize {
+        self.max_module_size
+    }
+
+    /// Returns the limits of a handler.
+    pub fn handler_limits(&self, handler: &str) -> &WasmLimits {
+        self.handler_limits.get(handler).unwrap_or(&self.limits)
+    }
+}
+
+impl Default for WasmConfig {
+    fn default() -> Self {
+        WasmConfig {
+            max_module_size: 16 * 1024 * 1024,
+            limits: WasmLimits::default(),
+            handler_limits: HashMap::new(),
+        }
+    }
+}
+
+/// Resources available to a single execution of a handler.
+///
+/// Exceeding a limit fails the command with the `handler_resource_exhausted` code, naming the
+/// exhausted resource in the error details.
+///
+/// Defaults to 1 billion units of fuel, 64 MiB of memory, a 5 second deadline, reading up to
+/// 10,000 events, emitting up to 1,000 events and returning results of up to 16 MiB.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasmLimits {
+    fuel: u64,
+    max_memory: usize,
+    #[serde(with = "duration_secs")]
+    deadline: Duration,
+    max_events_read: usize,
+    max_events_emitted: usize,
+    max_result_size: usize,
+}
+
+impl WasmLimits {
+    pub fn new() -> Self {
+        WasmLimits::default()
+    }
+
+    /// Sets the fuel available to the handler, roughly the number of instructions it can execute.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Sets the maximum size of the handler's linear memory in bytes.
+    pub fn with_max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    /// Sets how long an execution can take, including reading events from the event store.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Sets the maximum number of events read by the handler's query.
+    pub fn with_max_events_read(mut self, max_events_read: usize) -> Self {
+        self.max_events_read = max_events_read;
+        self
+    }
+
+    /// Sets the maximum number of events emitted by a single execution.
+    pub fn with_max_events_emitted(mut self, max_events_emitted: usize) -> Self {
+        self.max_events_emitted = max_events_emitted;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a result returned by one of the handler's exports.
+    pub fn with_max_result_size(mut self, max_result_size: usize) -> Self {
+        self.max_result_size = max_result_size;
+        self
+    }
+
+    pub fn fuel(&self) -> u64 {
+        self.fuel
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.max_memory
+    }
+
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    pub fn max_events_read(&self) -> usize {
+        self.max_events_read
+    }
+
+    pub fn max_events_emitted(&self) -> usize {
+        self.max_events_emitted
+    }
+
+    pub fn max_result_size(&self) -> usize {
+        self.max_result_size
+    }
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        WasmLimits {
+            fuel: 1_000_000_000,
+            max_memory: 64 * 1024 * 1024,
+            deadline: Duration::from_secs(5),
+            max_events_read: 10_000,
+            max_events_emitted: 1_000,
+            max_result_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Interval at which the engine's epoch is incremented, the granularity of handler deadlines.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Function exports required by the ABI.
+const FUNCTION_EXPORTS: [&str; 7] = [
+    EXPORT_ABI_VERSION,
+    EXPORT_ALLOC,
+    EXPORT_DEALLOC,
+    EXPORT_DESCRIBE,
+    EXPORT_QUERY,
+    EXPORT_APPLY,
+    EXPORT_HANDLE,
+];
+
+/// Compiles and executes uploaded handler modules.
+///
+/// Handlers are kept in memory, so must be uploaded again after a restart.
+#[derive(Clone)]
+pub struct WasmRuntime {
+    engine: Engine,
+    /// Versions of each handler, in order of upload.
+    handlers: Arc<RwLock<HashMap<String, Vec<Arc<WasmHandler>>>>>,
+    schema: Arc<RwLock<Option<Arc<EventSchema>>>>,
+    config: Arc<WasmConfig>,
+    /// Keeps the epoch ticker running while the runtime or one of its guests is in use.
+    ticker: Arc<()>,
+}
+
+struct WasmHandler {
+    info: HandlerInfo,
+    module: Module,
+}
+
+/// Details of an uploaded handler.
+#[derive(Clone, Debug, Serialize)]
+pub struct HandlerInfo {
+    pub name: String,
+    pub version: String,
+    /// Event types read by the handler
+    pub event_types: Vec<String>,
+    /// Event types emitted by the handler, empty when undeclared
+    pub emitted_event_types: Vec<String>,
+    /// Domain IDs the handler's reads are scoped by
+    pub domain_id_fields: Vec<String>,
+    pub uploaded_at: DateTime<Utc>,
+    pub wasm_size_bytes: usize,
+    /// Hex encoded SHA-256 digest of the module
+    pub sha256: String,
+    pub validation: HandlerValidation,
+}
+
+/// Result of validating a handler against the ABI and event schema when uploaded.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HandlerValidation {
+    pub status: ValidationStatus,
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ValidationError>,
+}
+
+impl HandlerValidation {
+    fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+
+    fn error(&mut self, code: &str, message: impl Into<String>) {
+        self.status = ValidationStatus::Rejected;
+        self.errors.push(ValidationError {
+            code: code.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationStatus {
+    #[default]
+    Ok,
+    Rejected,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidationError {
+    pub code: String,
+    pub message: String,
+}
+
+impl WasmRuntime {
+    pub fn new(config: WasmConfig) -> Self {
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true).epoch_interruption(true);
+        let engine = Engine::new(&engine_config).expect("fuel and epochs are supported");
+
+        let ticker = Arc::new(());
+        let running = Arc::downgrade(&ticker);
+        let ticker_engine = engine.clone();
+        thread::spawn(move || {
+            while running.strong_count() > 0 {
+                thread::sleep(EPOCH_TICK);
+                ticker_engine.increment_epoch();
+            }
+        });
+
+        WasmRuntime {
+            engine,
+            handlers: Arc::default(),
+            schema: Arc::default(),
+            config: Arc::new(config),
+            ticker,
+        }
+    }
+
+    /// Sets the event schema handlers are validated against, replacing any previous schema.
+    ///
+    /// Handlers which were already uploaded are not validated again.
+    pub fn set_schema(&self, schema: EventSchema) {
+        *self.schema.write().expect("schema lock poisoned") = Some(Arc::new(schema));
+    }
+
+    fn schema(&self) -> Option<Arc<EventSchema>> {
+        self.schema.read().expect("schema lock poisoned").clone()
+    }
+
+    /// Compiles a module and registers it as the latest version of the handler for `name`.
+    ///
+    /// Replaces a previously uploaded module with the same version, while other versions remain
+    /// available.
+    /// The module's exports and ABI version are checked, then the events described by
+    /// `esruntime_describe` are validated against the event schema. Without a schema, only the
+    /// exports are checked.
+    pub fn load(&self, name: &str, version: &str, wasm: &[u8]) -> Result<HandlerInfo, Error> {
+        let module = Module::new(&self.engine, wasm).map_err(invalid_module)?;
+        let mut validation = HandlerValidation::default();
+        let description = self.describe(name, &module, &mut validation)?;
+        if let Some(description) = &description {
+            validate_description(description, self.schema().as_deref(), &mut validation);
+        }
+        if validation.status == ValidationStatus::Rejected {
+            return Err(validation_failed(validation));
+        }
+
+        let description = description.unwrap_or_default();
+        let domain_id_fields: BTreeSet<_> = description
+            .event_domain_ids
+            .into_values()
+            .flatten()
+            .collect();
+        let info = HandlerInfo {
+            name: name.to_string(),
+            version: version.to_string(),
+            event_types: description.event_types,
+            emitted_event_types: description.emitted_event_types,
+            domain_id_fields: domain_id_fields.into_iter().collect(),
+            uploaded_at: Utc::now(),
+            wasm_size_bytes: wasm.len(),
+            sha256: format!("{:x}", Sha256::digest(wasm)),
+            validation,
+        };
+        let handler = Arc::new(WasmHandler {
+            info: info.clone(),
+            module,
+        });
+        let mut handlers = self.handlers.write().expect("handlers lock poisoned");
+        let versions = handlers.entry(name.to_string()).or_default();
+        versions.retain(|handler| handler.info.version != version);
+        versions.push(handler);
+
+        Ok(info)
+    }
+
+    /// Checks the exports and ABI version of a module, returning its description if they match.
+    fn describe(
+        &self,
+        name: &str,
+        module: &Module,
+        validation: &mut HandlerValidation,
+    ) -> Result<Option<WasmDescription>, Error> {
+        validate_exports(module, validation);
+        if validation.status == ValidationStatus::Rejected {
+            return Ok(None);
+        }
+
+        let limits = self.config.handler_limits(name);
+        let mut instance =
+            HandlerInstance::instantiate(&self.engine, module, limits).map_err(invalid_module)?;
+        let abi_version = instance.abi_version().map_err(invalid_module)?;
+        if abi_version != ABI_VERSION {
+            validation.error(
+                "unsupported_abi_version",
+                format!("Handler implements ABI version {abi_version}, expected {ABI_VERSION}"),
+            );
+            return Ok(None);
+        }
+
+        instance.describe().map(Some).map_err(invalid_module)
+    }
+
+    /// Removes a version of a handler, or every version when `version` is `None`, returning
+    /// whether any were registered.
+    pub fn remove(&self, name: &str, version: Option<&str>) -> bool {
+        let mut handlers = self.handlers.write().expect("handlers lock poisoned");
+        let Some(version) = version else {
+            return handlers.remove(name).is_some();
+        };
+        let Some(versions) = handlers.get_mut(name) else {
+            return false;
+        };
+
+        let len = versions.len();
+        versions.retain(|handler| handler.info.version != version);
+        let removed = versions.len() < len;
+        if versions.is_empty() {
+            handlers.remove(name);
+        }
+        removed
+    }
+
+    /// Returns the details of a version of a handler, or the latest when `version` is `None`.
+    pub fn handler(&self, name: &str, version: Option<&str>) -> Option<HandlerInfo> {
+        self.get(name, version)
+            .ok()
+            .map(|handler| handler.info.clone())
+    }
+
+    /// Returns the details of every version of every handler, sorted by name then upload.
+    pub fn handlers(&self) -> Vec<HandlerInfo> {
+        let mut handlers: Vec<_> = self
+            .handlers
+            .read()
+            .expect("handlers lock poisoned")
+            .values()
+            .flatten()
+            .map(|handler| handler.info.clone())
+            .collect();
+        handlers.sort_by(|a, b| a.name.cmp(&b.name));
+        handlers
+    }
+
+    /// Executes a version of a handler, or the latest when `version` is `None`, persisting the
+    /// emitted events.
+    ///
+    /// Calls `esruntime_query` with the input, applies each event read by the query with
+    /// `esruntime_apply`, then appends the events returned by `esruntime_handle`. The handler's
+    /// version is recorded in the metadata of the emitted events.
+    pub async fn execute(
+        &self,
+        store: &impl DCBEventStoreAsync,
+        name: &str,
+        version: Option<&str>,
+        input: Value,
+        mut context: CommandContext,
+    ) -> Result<ExecuteResult<Value>, Error> {
+        let handler = self.get(name, version)?;
+        context.handler_version = Some(handler.info.version.clone());
+        self.execute_handler(store, &handler, input, context).await
+    }
+
+    async fn execute_handler(
+        &self,
+        store: &impl DCBEventStoreAsync,
+        handler: &WasmHandler,
+        input: Value,
+        mut context: CommandContext,
+    ) -> Result<ExecuteResult<Value>, Error> {
+        context
+            .command
+            .get_or_insert_with(|| handler.info.name.clone());
+        let limits = self.config.handler_limits(&handler.info.name).clone();
+        // The epoch deadline of the instance starts when it is created, so the same deadline
+        // covers reading events from the event store.
+        let deadline = tokio::time::Instant::now() + limits.deadline;
+        let (engine, module, guest_limits) =
+            (self.engine.clone(), handler.module.clone(), limits.clone());
+        let (mut instance, query, input) = self
+            .spawn_guest(move || {
+                let mut instance = HandlerInstance::new(&engine, &module, &guest_limits)?;
+                let query = instance.query(&input)?;
+                Ok((instance, query, input))
+            })
+            .await?;
+
+        let query = context.idempotent_query(query.into());
+        // Reads one event past the limit, so exceeding it is detected without reading every event.
+        let read_limit = u32::try_from(limits.max_events_read)
+            .unwrap_or(u32::MAX)
+            .saturating_add(1);
+        let read = async {
+            store
+                .read(Some(query.clone()), Some(0), false, Some(read_limit), false)
+                .await?
+                .collect_with_head()
+                .await
+        };
+        let (events, head) = tokio::time::timeout_at(deadline, read)
+            .await
+            .map_err(|_| resource_exhausted("deadline", limits.deadline.as_secs_f64()))??;
+
+        if let Some(result) = replay_idempotent(&context, &events) {
+            return Ok(result);
+        }
+        check_expected_position::<CommandError>(&context, &events)?;
+        if events.len() > limits.max_events_read {
+            return Err(resource_exhausted("events_read", limits.max_events_read));
+        }
+
+        let WasmEmit { events, output } = self
+            .spawn_guest(move || {
+                for DCBSequencedEvent { position: _, event } in events {
+                    let StoredEventData {
+                        data, timestamp, ..
+                    } = serde_json::from_slice(&event.data)
+                        .map_err(|err| Error::from(SerializationError::from(err)))?;
+                    instance.apply(&WasmEvent {
+                        event_type: event.event_type,
+                        data,
+                        timestamp,
+                    })?;
+                }
+                instance.handle(&input)
+            })
+            .await?;
+        let timestamp = Utc::now();
+        if events.len() > limits.max_events_emitted {
+            return Err(resource_exhausted(
+                "events_emitted",
+                limits.max_events_emitted,
+            ));
+        }
+        validate_emitted(
+            &events,
+            &handler.info.emitted_event_types,
+            self.schema().as_deref(),
+        )?;
+        let envelope = context.event_envelope(timestamp);
+        let idempotency_tag = context.idempotency_tag();
+        let append_events: Vec<_> = events
+            .into_iter()
+            .map(|event| {
+                let mut tags = event.tags;
+                tags.extend(idempotency_tag.clone());
+                DCBEvent {
+                    event_type: event.event_type,
+                    tags,
+                    data: encode_with_envelope(&envelope, event.data),
+                    uuid: Some(Uuid::new_v4()),
+                }
+            })
+            .collect();
+
+        if append_events.is_empty() {
+            return Ok(ExecuteResult::new(head, Vec::new(), output));
+        }
+
+        let new_position = store
+            .append(
+                append_events.clone(),
+                Some(DCBAppendCondition {
+                    fail_if_events_match: query,
+                    after: context.append_after(head),
+                }),
+            )
+            .await
+            .map_err(|err| context.stale_position_error::<CommandError>(err))?;
+
+        Ok(ExecuteResult::new(
+            Some(new_position),
+            append_events,
+            output,
+        ))
+    }
+
+    /// Runs guest code on the blocking thread pool.
+    ///
+    /// A running guest never yields, so it would otherwise hold up the async worker it runs on
+    /// until its fuel or epoch deadline runs out, and timeouts of the request couldn't fire. The
+    /// epoch ticker is kept running until the guest returns, even if the runtime is dropped.
+    async fn spawn_guest<T>(
+        &self,
+        f: impl FnOnce() -> Result<T, Error> + Send + 'static,
+    ) -> Result<T, Error>
+    where
+        T: Send + 'static,
+    {
+        let ticker = self.ticker.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ticker = ticker;
+            f()
+        })
+        .await
+        .map_err(handler_failed)?
+    }
+
+    /// Returns an executor for the handler version requested with `X-Handler-Version`, failing
+    /// if it has not been uploaded.
+    ///
+    /// The version is selected once, so the executor keeps running it if a new one is uploaded.
+    pub(crate) fn executor(&self, name: &str, headers: &HeaderMap) -> Result<Executor, Error> {
+        let handler = self.get(name, handler_version(headers)?)?;
+
+        let runtime = self.clone();
+        Ok(Arc::new(
+            move |state: CommandState, mut context: CommandContext, headers, input| {
+                let runtime = runtime.clone();
+                let handler = handler.clone();
+                context.handler_version = Some(handler.info.version.clone());
+                async move {
+                    let umadb_client = state.umadb_client.clone();
+                    let name = handler.info.name.clone();
+                    crate::execute_recorded(
+                        &name,
+                        &state,
+                        context,
+                        &headers,
+                        |context| async move {
+                            runtime
+                                .execute_handler(umadb_client.as_ref(), &handler, input, context)
+                                .await
+                        },
+                    )
+                    .await
+                }
+                .boxed()
+            },
+        ))
+    }
+
+    /// Returns a version of a handler, or the latest when `version` is `None`.
+    fn get(&self, name: &str, version: Option<&str>) -> Result<Arc<WasmHandler>, Error> {
+        let handlers = self.handlers.read().expect("handlers lock poisoned");
+        let versions = handlers.get(name).ok_or_else(|| handler_not_found(name))?;
+        let handler = match version {
+            Some(version) => versions
+                .iter()
+                .find(|handler| handler.info.version == version)
+                .ok_or_else(|| handler_version_not_found(name, version))?,
+            None => versions.last().expect("handlers have an uploaded version"),
+        };
+        Ok(handler.clone())
+    }
+
+    /// Route executing uploaded handlers at `POST /commands/{name}`.
+    pub(crate) fn command_route(&self, config: &CommandRouterConfig) -> MethodRouter<CommandState> {
+        let runtime = self.clone();
+        let config = config.clone();
+        post(
+            |Path(name): Path<String>,
+             State(state): State<CommandState>,
+             RequestContext(context): RequestContext,
+             Query(params): Query<ExecuteParams>,
+             headers: HeaderMap,
+             Json(input): Json<Value>| async move {
+                let execute = runtime.executor(&name, &headers)?;
+                let timeout = config.command_timeout(&name);
+                let route = crate::command_route(
+                    name.into(),
+                    execute,
+                    state,
+                    context,
+                    params,
+                    headers,
+                    input,
+                    timeout,
+                );
+                tokio::time::timeout(timeout, route)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(Error::new(ErrorStatus::Unavailable, "timeout")
+                            .with_status_code(StatusCode::REQUEST_TIMEOUT)
+                            .with_message("the handler did not complete in time"))
+                    })
+            },
+        )
+    }
+
+    /// Routes for uploading, inspecting and removing handlers.
+    pub(crate) fn routes<S>(&self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let list = self.clone();
+        let details = self.clone();
+        let upload = self.clone();
+        let delete = self.clone();
+        Router::new()
+            .route(
+                "/handlers",
+                get(|| async move { Json(json!({ "handlers": list.handlers() })) }),
+            )
+            .route(
+                "/handlers/{name}",
+                get(|Path(name): Path<String>, headers: HeaderMap| async move {
+                    let version = handler_version(&headers)?;
+                    details
+                        .get(&name, version)
+                        .map(|handler| Json(handler.info.clone()))
+                })
+                .put(
+                    |Path(name): Path<String>, headers: HeaderMap, wasm: Bytes| async move {
+                        upload_handler(&upload, &name, &headers, &wasm)
+                    },
+                )
+                .delete(
+                    |Path(name): Path<String>, headers: HeaderMap| async move {
+                        let version = handler_version(&headers)?;
+                        if delete.remove(&name, version) {
+                            Ok(StatusCode::NO_CONTENT)
+                        } else {
+                            Err(match version {
+                                Some(version) => handler_version_not_found(&name, version),
+                                None => handler_not_found(&name),
+                            })
+                        }
+                    },
+                ),
+            )
+            .layer(DefaultBodyLimit::max(self.config.max_module_size))
+    }
+}
+
+fn upload_handler(
+    runtime: &WasmRuntime,
+    name: &str,
+    headers: &HeaderMap,
+    wasm: &[u8],
+) -> Result<Json<HandlerInfo>, Error> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if content_type != Some("application/wasm") {
+        return Err(
+            Error::new(ErrorStatus::InvalidInput, "unsupported_content_type")
+                .with_status_code(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                .with_message("handlers must be uploaded as application/wasm"),
+        );
+    }
+    let version = handler_version(headers)?.ok_or_else(|| {
+        Error::new(ErrorStatus::InvalidInput, "invalid_header")
+            .with_message(format!("{HANDLER_VERSION_HEADER} header is required"))
+    })?;
+
+    runtime.load(name, version, wasm).map(Json)
+}
+
+/// Checks a module has no imports, and exports the memory and functions of the ABI.
+fn validate_exports(module: &Module, validation: &mut HandlerValidation) {
+    for import in module.imports() {
+        validation.error(
+            "unexpected_import",
+            format!(
+                "Handler imports '{}::{}', but handlers cannot import",
+                import.module(),
+                import.name()
+            ),
+        );
+    }
+    if !matches!(
+        module.get_export(EXPORT_MEMORY),
+        Some(ExternType::Memory(_))
+    ) {
+        validation.error(
+            "missing_export",
+            format!("Handler does not export its '{EXPORT_MEMORY}'"),
+        );
+    }
+    for export in FUNCTION_EXPORTS {
+        if !matches!(module.get_export(export), Some(ExternType::Func(_))) {
+            validation.error(
+                "missing_export",
+                format!("Handler does not export the '{export}' function"),
+            );
+        }
+    }
+}
+
+/// Checks the events read by a handler exist in the schema, and are scoped by their domain IDs.
+///
+/// Mirrors the checks of `#[scope]` on a derived `EventSet`, which cannot run for modules
+/// built against a different set of events.
+fn validate_description(
+    description: &WasmDescription,
+    schema: Option<&EventSchema>,
+    validation: &mut HandlerValidation,
+) {
+    match schema {
+        Some(schema) => {
+            for event_type in &description.event_types {
+                let Some(domain_ids) = schema.domain_ids(event_type) else {
+                    validation.error(
+                        "unknown_event_type",
+                        format!("Handler reads '{event_type}' which is not in the schema"),
+                    );
+                    continue;
+                };
+                let scope = description.event_domain_ids.get(event_type);
+                for domain_id in scope.into_iter().flatten() {
+                    if !domain_ids.contains(domain_id) {
+                        validation.error(
+                            "unknown_domain_id",
+                            format!(
+                                "Handler scopes '{event_type}' by '{domain_id}' which is not one of its domain IDs"
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        None => validation.warn("No event schema is registered, so event types were not checked"),
+    }
+
+    if description.emitted_event_types.is_empty() {
+        validation.warn("Handler does not declare the event types it emits");
+    }
+    for event_type in &description.emitted_event_types {
+        if schema.is_some_and(|schema| !schema.contains(event_type)) {
+            validation.error(
+                "unknown_event_type",
+                format!("Handler emits '{event_type}' which is not in the schema"),
+            );
+        }
+    }
+
+    for (field, domain_id) in &description.input_domain_ids {
+        let scoped = description
+            .event_domain_ids
+            .values()
+            .flatten()
+            .any(|id| id == domain_id);
+        if !scoped {
+            validation.warn(format!(
+                "Input field '{field}' is bound to '{domain_id}' but no event read is scoped by it"
+            ));
+        }
+    }
+}
+
+/// Checks the events emitted by a handler against the event types it declared and the schema.
+///
+/// Every event type must be declared, when any are, and in the schema, and events may only be
+/// tagged with the domain IDs of their event type.
+fn validate_emitted(
+    events: &[WasmEmittedEvent],
+    emitted_event_types: &[String],
+    schema: Option<&EventSchema>,
+) -> Result<(), Error> {
+    for event in events {
+        if !emitted_event_types.is_empty() && !emitted_event_types.contains(&event.event_type) {
+            return Err(
+                Error::new(ErrorStatus::Internal, "undeclared_event_type").with_message(format!(
+                    "handler emitted `{}`, which it does not declare",
+                    event.event_type
+                )),
+            );
+        }
+        let Some(schema) = schema else {
+            continue;
+        };
+        let Some(domain_ids) = schema.domain_ids(&event.event_type) else {
+            return Err(
+                Error::new(ErrorStatus::Internal, "unknown_event_type").with_message(format!(
+                    "handler emitted `{}`, which is not in the schema",
+                    event.event_type
+                )),
+            );
+        };
+        let unknown_tag = event.tags.iter().find(|tag| {
+            let category = tag
+                .split_once(':')
+                .map_or(tag.as_str(), |(category, _)| category);
+            !domain_ids.iter().any(|domain_id| domain_id == category)
+        });
+        if let Some(tag) = unknown_tag {
+            return Err(
+                Error::new(ErrorStatus::Internal, "unknown_domain_id").with_message(format!(
+                    "handler tagged `{}` with `{tag}`, which is not one of its domain IDs",
+                    event.event_type
+                )),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// A single instance of a handler module.
+struct HandlerInstance {
+    store: Store<HandlerLimiter>,
+    limits: WasmLimits,
+    memory: Memory,
+    abi_version: TypedFunc<(), i32>,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: TypedFunc<(i32, i32), ()>,
+    describe: TypedFunc<(), i64>,
+    query: TypedFunc<(i32, i32), i64>,
+    apply: TypedFunc<(i32, i32), i64>,
+    handle: TypedFunc<(i32, i32), i64>,
+}
+
+impl HandlerInstance {
+    fn new(engine: &Engine, module: &Module, limits: &WasmLimits) -> Result<Self, Error> {
+        Self::instantiate(engine, module, limits).map_err(|err| execution_error(limits, err))
+    }
+
+    fn instantiate(
+        engine: &Engine,
+        module: &Module,
+        limits: &WasmLimits,
+    ) -> wasmtime::Result<Self> {
+        let mut store = Store::new(
+            engine,
+            HandlerLimiter {
+                max_memory: limits.max_memory,
+            },
+        );
+        store.limiter(|limiter| limiter);
+        store.set_fuel(limits.fuel)?;
+        store.set_epoch_deadline(
+            (limits.deadline.as_millis() / EPOCH_TICK.as_millis()).max(1) as u64,
+        );
+        let instance = Instance::new(&mut store, module, &[])?;
+
+        let memory = instance
+            .get_memory(&mut store, EXPORT_MEMORY)
+            .ok_or_else(|| wasmtime::Error::msg(format!("missing `{EXPORT_MEMORY}` export")))?;
+        let abi_version = instance.get_typed_func(&mut store, EXPORT_ABI_VERSION)?;
+        let alloc = instance.get_typed_func(&mut store, EXPORT_ALLOC)?;
+        let dealloc = instance.get_typed_func(&mut store, EXPORT_DEALLOC)?;
+        let describe = instance.get_typed_func(&mut store, EXPORT_DESCRIBE)?;
+        let query = instance.get_typed_func(&mut store, EXPORT_QUERY)?;
+        let apply = instance.get_typed_func(&mut store, EXPORT_APPLY)?;
+        let handle = instance.get_typed_func(&mut store, EXPORT_HANDLE)?;
+
+        Ok(HandlerInstance {
+            store,
+            limits: limits.clone(),
+            memory,
+            abi_version,
+            alloc,
+            dealloc,
+            describe,
+            query,
+            apply,
+            handle,
+        })
+    }
+
+    fn abi_version(&mut self) -> wasmtime::Result<i32> {
+        self.abi_version.call(&mut self.store, ())
+    }
+
+    fn describe(&mut self) -> wasmtime::Result<WasmDescription> {
+        let result = self.describe.call(&mut self.store, ())?;
+        let description = self.read_result(result)?;
+        Ok(serde_json::from_slice(&description)?)
+    }
+
+    fn query(&mut self, input: &Value) -> Result<WasmQuery, Error> {
+        let func = self.query.clone();
+        self.call(func, input)
+    }
+
+    fn apply(&mut self, event: &WasmEvent) -> Result<(), Error> {
+        let func = self.apply.clone();
+        self.call(func, event)
+    }
+
+    fn handle(&mut self, input: &Value) -> Result<WasmEmit, Error> {
+        let func = self.handle.clone();
+        self.call(func, input)
+    }
+
+    /// Calls an export with a JSON encoded argument, decoding its result.
+    fn call<A, T>(&mut self, func: TypedFunc<(i32, i32), i64>, arg: &A) -> Result<T, Error>
+    where
+        A: Serialize,
+        T: DeserializeOwned,
+    {
+        let arg =
+            serde_json::to_vec(arg).map_err(|err| Error::from(SerializationError::from(err)))?;
+        let result = self
+            .call_raw(func, &arg)
+            .map_err(|err| execution_error(&self.limits, err))?;
+        let result: WasmResult<T> = serde_json::from_slice(&result)
+            .map_err(|err| handler_failed(format!("invalid result: {err}")))?;
+
+        match result {
+            WasmResult::Ok(value) => Ok(value),
+            WasmResult::Rejected(err) => Err(ExecuteError::Command(err).into()),
+            WasmResult::Invalid(err) => Err(ExecuteError::Validation(err).into()),
+        }
+    }
+
+    fn call_raw(
+        &mut self,
+        func: TypedFunc<(i32, i32), i64>,
+        arg: &[u8],
+    ) -> wasmtime::Result<Vec<u8>> {
+        let arg_len = i32::try_from(arg.len())?;
+        let arg_ptr = self.alloc.call(&mut self.store, arg_len)?;
+        if arg_ptr == 0 {
+            return Err(wasmtime::Error::msg(format!(
+                "handler failed to allocate {arg_len} bytes"
+            )));
+        }
+        self.memory
+            .write(&mut self.store, arg_ptr as u32 as usize, arg)?;
+
+        let result = func.call(&mut self.store, (arg_ptr, arg_len))?;
+        self.dealloc.call(&mut self.store, (arg_ptr, arg_len))?;
+        self.read_result(result)
+    }
+
+    /// Copies a result out of the instance's memory, freeing its buffer.
+    ///
+    /// The length is checked against the memory and the result size limit before allocating.
+    fn read_result(&mut self, result: i64) -> wasmtime::Result<Vec<u8>> {
+        let (ptr, len) = unpack(result);
+        let end = u64::from(ptr) + u64::from(len);
+        if end > self.memory.data_size(&self.store) as u64 {
+            return Err(wasmtime::Error::msg(format!(
+                "result of {len} bytes at {ptr} is out of bounds of memory"
+            )));
+        }
+        if len as usize > self.limits.max_result_size {
+            return Err(wasmtime::Error::new(ResultTooLarge));
+        }
+        let mut result = vec![0; len as usize];
+        self.memory.read(&self.store, ptr as usize, &mut result)?;
+        self.dealloc
+            .call(&mut self.store, (ptr as i32, len as i32))?;
+
+        Ok(result)
+    }
+}
+
+/// Fails memory growth beyond the handler's limit.
+struct HandlerLimiter {
+    max_memory: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("memory limit exceeded")]
+struct MemoryExhausted;
+
+#[derive(Debug, thiserror::Error)]
+#[error("result size limit exceeded")]
+struct ResultTooLarge;
+
+impl ResourceLimiter for HandlerLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if desired > self.max_memory {
+            return Err(wasmtime::Error::new(MemoryExhausted));
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Reports exceeded limits as exhausted resources, and other traps as failures.
+fn execution_error(limits: &WasmLimits, err: wasmtime::Error) -> Error {
+    if err.downcast_ref::<MemoryExhausted>().is_some() {
+        return resource_exhausted("memory", limits.max_memory);
+    }
+    if err.downcast_ref::<ResultTooLarge>().is_some() {
+        return resource_exhausted("result_size", limits.max_result_size);
+    }
+    match err.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => resource_exhausted("fuel", limits.fuel),
+        Some(Trap::Interrupt) => resource_exhausted("deadline", limits.deadline.as_secs_f64()),
+        _ => handler_failed(err),
+    }
+}
+
+fn resource_exhausted(resource: &str, limit: impl Serialize) -> Error {
+    Error::new(ErrorStatus::ResourceExhausted, "handler_resource_exhausted")
+        .with_message(format!("handler exceeded its {resource} limit"))
+        .with_details(json!({ "resource": resource, "limit": limit }))
+}
+
+fn handler_not_found(name: &str) -> Error {
+    Error::new(ErrorStatus::NotFound, "handler_not_found")
+        .with_message(format!("no handler registered with name `{name}`"))
+}
+
+fn validation_failed(validation: HandlerValidation) -> Error {
+    Error::new(ErrorStatus::Rejected, "handler_validation_failed")
+        .with_message("handler failed validation against the ABI and event schema")
+        .with_details(json!(validation))
+}
+
+fn invalid_module(err: impl ToString) -> Error {
+    Error::new(ErrorStatus::InvalidInput, "invalid_module").with_message(err.to_string())
+}
+
+/// Error returned when a handler traps or breaks the ABI.
+fn handler_failed(err: impl ToString) -> Error {
+    Error::new(ErrorStatus::Internal, "handler_failed").with_message(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use esruntime_sdk::{memory::MemoryEventStore, wasm::pack};
+
+    use super::*;
+
+    const QUERY: &str = r#"{"ok":{"items":[{"types":["TaskCreated"],"tags":["task_id:a"]}]}}"#;
+    const APPLY: &str = r#"{"ok":null}"#;
+    const HANDLE: &str = r#"{"rejected":{"code":"rejected","message":"task already created"}}"#;
+    const DESCRIBE: &str = r#"{"event_types":["TaskCreated"],"emitted_event_types":["TaskCreated"],"event_domain_ids":{"TaskCreated":["task_id"]},"input_domain_ids":{"task_id":"task_id"}}"#;
+
+    /// A handler returning fixed results, with a bump allocator which never frees.
+    fn handler_wat(abi_version: i32) -> String {
+        handler_wat_with(
+            abi_version,
+            &format!("i64.const {}", pack(2048, HANDLE.len() as u32)),
+        )
+    }
+
+    /// A handler running `handle` as the body of `esruntime_handle`.
+    fn handler_wat_with(abi_version: i32, handle: &str) -> String {
+        let data = |offset: usize, json: &str| {
+            format!(
+                r#"(data (i32.const {offset}) "{}")"#,
+                json.replace('"', r#"\""#)
+            )
+        };
+        format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const 4096))
+                {}
+                {}
+                {}
+                {}
+                (func (export "esruntime_abi_version") (result i32) i32.const {abi_version})
+                (func (export "esruntime_alloc") (param $len i32) (result i32) (local $ptr i32)
+                    global.get $next
+                    local.set $ptr
+                    global.get $next
+                    local.get $len
+                    i32.add
+                    global.set $next
+                    local.get $ptr)
+                (func (export "esruntime_dealloc") (param i32 i32))
+                (func (export "esruntime_describe") (result i64) i64.const {})
+                (func (export "esruntime_query") (param i32 i32) (result i64) i64.const {})
+                (func (export "esruntime_apply") (param i32 i32) (result i64) i64.const {})
+                (func (export "esruntime_handle") (param i32 i32) (result i64) {handle})
+            )"#,
+            data(0, QUERY),
+            data(1024, APPLY),
+            data(2048, HANDLE),
+            data(3072, DESCRIBE),
+            pack(3072, DESCRIBE.len() as u32),
+            pack(0, QUERY.len() as u32),
+            pack(1024, APPLY.len() as u32),
+        )
+    }
+
+    #[test]
+    fn loads_handlers() {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+        let info = runtime
+            .load("create_task", "1.0.0", handler_wat(ABI_VERSION).as_bytes())
+            .unwrap();
+
+        assert_eq!(info.version, "1.0.0");
+        assert_eq!(info.event_types, ["TaskCreated"]);
+        assert_eq!(info.domain_id_fields, ["task_id"]);
+        assert_eq!(info.sha256.len(), 64);
+        assert_eq!(info.validation.warnings.len(), 1);
+        assert_eq!(runtime.handlers().len(), 1);
+        assert!(runtime.remove("create_task", None));
+        assert!(runtime.handler("create_task", None).is_none());
+    }
+
+    #[test]
+    fn routes_versions() {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+        let wasm = handler_wat(ABI_VERSION);
+        for version in ["1.0.0", "1.1.0", "1.0.0"] {
+            runtime
+                .load("create_task", version, wasm.as_bytes())
+                .unwrap();
+        }
+
+        let versions: Vec<_> = runtime
+            .handlers()
+            .into_iter()
+            .map(|info| info.version)
+            .collect();
+        assert_eq!(versions, ["1.1.0", "1.0.0"]);
+        assert_eq!(
+            runtime.handler("create_task", None).unwrap().version,
+            "1.0.0"
+        );
+
+        let err = runtime
+            .get("create_task", Some("2.0.0"))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::NotFound);
+
+        assert!(runtime.remove("create_task", Some("1.0.0")));
+        assert_eq!(
+            runtime.handler("create_task", None).unwrap().version,
+            "1.1.0"
+        );
+        assert!(runtime.remove("create_task", Some("1.1.0")));
+        assert!(runtime.handlers().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_modules() {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+
+        let err = runtime
+            .load("create_task", "1.0.0", b"not wasm")
+            .unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::InvalidInput);
+
+        for (wasm, code) in [
+            (
+                handler_wat(ABI_VERSION + 1).into_bytes(),
+                "unsupported_abi_version",
+            ),
+            (
+                br#"(module (memory (export "memory") 1))"#.to_vec(),
+                "missing_export",
+            ),
+            (
+                br#"(module (import "env" "now" (func)))"#.to_vec(),
+                "unexpected_import",
+            ),
+        ] {
+            assert_eq!(validation_errors(&runtime, &wasm)[0], code);
+        }
+        assert!(runtime.handlers().is_empty());
+    }
+
+    fn validation_errors(runtime: &WasmRuntime, wasm: &[u8]) -> Vec<Value> {
+        let err = runtime.load("create_task", "1.0.0", wasm).unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::Rejected);
+        let body = serde_json::to_value(err.body()).unwrap();
+        body["details"]["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["code"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn validates_against_schema() {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+        let wasm = handler_wat(ABI_VERSION);
+
+        runtime.set_schema(EventSchema::new().event_type("TaskCreated", ["task_id"]));
+        let info = runtime
+            .load("create_task", "1.0.0", wasm.as_bytes())
+            .unwrap();
+        assert_eq!(info.validation.status, ValidationStatus::Ok);
+        assert!(info.validation.warnings.is_empty());
+
+        runtime.set_schema(EventSchema::new().event_type("TaskCreated", ["board_id"]));
+        assert_eq!(
+            validation_errors(&runtime, wasm.as_bytes()),
+            ["unknown_domain_id"]
+        );
+
+        runtime.set_schema(EventSchema::new().event_type("TaskMoved", ["task_id"]));
+        assert_eq!(
+            validation_errors(&runtime, wasm.as_bytes()),
+            ["unknown_event_type", "unknown_event_type"]
+        );
+    }
+
+    #[test]
+    fn validates_emitted_events() {
+        let event = |event_type: &str, tags: &[&str]| WasmEmittedEvent {
+            event_type: event_type.to_string(),
+            data: json!({}),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        };
+        let code = |result: Result<(), Error>| {
+            serde_json::to_value(result.unwrap_err().body()).unwrap()["code"].clone()
+        };
+        let declared = ["TaskCreated".to_string()];
+        let schema = EventSchema::new().event_type("TaskCreated", ["task_id"]);
+
+        let created = event("TaskCreated", &["task_id:a"]);
+        assert!(validate_emitted(std::slice::from_ref(&created), &declared, Some(&schema)).is_ok());
+        assert!(validate_emitted(&[created], &[], None).is_ok());
+
+        let moved = event("TaskMoved", &["task_id:a"]);
+        assert_eq!(
+            code(validate_emitted(
+                std::slice::from_ref(&moved),
+                &declared,
+                None
+            )),
+            "undeclared_event_type"
+        );
+        assert_eq!(
+            code(validate_emitted(&[moved], &[], Some(&schema))),
+            "unknown_event_type"
+        );
+
+        let tagged = event("TaskCreated", &["task_id:a", "board_id:b"]);
+        assert_eq!(
+            code(validate_emitted(&[tagged], &declared, Some(&schema))),
+            "unknown_domain_id"
+        );
+    }
+
+    #[test]
+    fn calls_exports() {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+        let module = Module::new(&runtime.engine, handler_wat(ABI_VERSION)).unwrap();
+        let mut instance =
+            HandlerInstance::new(&runtime.engine, &module, &WasmLimits::default()).unwrap();
+
+        let query = instance.query(&json!({ "task_id": "a" })).unwrap();
+        assert_eq!(query.items[0].types, ["TaskCreated"]);
+
+        instance
+            .apply(&WasmEvent {
+                event_type: "TaskCreated".to_string(),
+                data: json!({}),
+                timestamp: Utc::now(),
+            })
+            .unwrap();
+
+        let err = instance.handle(&json!({ "task_id": "a" })).unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::Rejected);
+    }
+
+    fn exhausted_resource(handle: &str, limits: WasmLimits) -> Value {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+        let module = Module::new(&runtime.engine, handler_wat_with(ABI_VERSION, handle)).unwrap();
+        let mut instance = HandlerInstance::new(&runtime.engine, &module, &limits).unwrap();
+
+        let err = instance.handle(&json!({})).unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::ResourceExhausted);
+        assert_eq!(err.status().status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        serde_json::to_value(err.body()).unwrap()["details"]["resource"].clone()
+    }
+
+    #[test]
+    fn fuel_is_limited() {
+        let resource = exhausted_resource(
+            "(loop br 0) i64.const 0",
+            WasmLimits::default().with_fuel(100_000),
+        );
+        assert_eq!(resource, "fuel");
+    }
+
+    #[test]
+    fn memory_is_limited() {
+        let resource = exhausted_resource(
+            "i32.const 16 memory.grow drop i64.const 0",
+            WasmLimits::default().with_max_memory(128 * 1024),
+        );
+        assert_eq!(resource, "memory");
+    }
+
+    #[tokio::test]
+    async fn guests_do_not_block_the_async_runtime() {
+        let limits = WasmLimits::default()
+            .with_fuel(u64::MAX)
+            .with_deadline(Duration::from_millis(500));
+        let runtime = WasmRuntime::new(WasmConfig::default().with_limits(limits));
+        runtime
+            .load(
+                "create_task",
+                "1.0.0",
+                handler_wat_with(ABI_VERSION, "(loop br 0) i64.const 0").as_bytes(),
+            )
+            .unwrap();
+
+        let store = MemoryEventStore::new();
+        let execute = runtime.execute(
+            &store,
+            "create_task",
+            None,
+            json!({ "task_id": "a" }),
+            CommandContext::new(),
+        );
+        let result = tokio::time::timeout(Duration::from_millis(50), execute).await;
+        assert!(result.is_err(), "timeout should fire while the guest runs");
+    }
+
+    #[tokio::test]
+    async fn events_read_are_limited() {
+        let store = MemoryEventStore::new();
+        let events = (0..3)
+            .map(|_| DCBEvent {
+                event_type: "TaskCreated".to_string(),
+                tags: vec!["task_id:a".to_string()],
+                data: encode_with_envelope(
+                    &CommandContext::new().event_envelope(Utc::now()),
+                    json!({}),
+                ),
+                uuid: None,
+            })
+            .collect();
+        store.append(events, None).await.unwrap();
+
+        let runtime = WasmRuntime::new(
+            WasmConfig::default().with_limits(WasmLimits::default().with_max_events_read(2)),
+        );
+        runtime
+            .load("create_task", "1.0.0", handler_wat(ABI_VERSION).as_bytes())
+            .unwrap();
+        let err = runtime
+            .execute(
+                &store,
+                "create_task",
+                None,
+                json!({ "task_id": "a" }),
+                CommandContext::new(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::ResourceExhausted);
+        assert_eq!(
+            serde_json::to_value(err.body()).unwrap()["details"]["resource"],
+            "events_read"
+        );
+    }
+
+    #[test]
+    fn result_size_is_limited() {
+        let resource = exhausted_resource(
+            &format!("i64.const {}", pack(0, 4096)),
+            WasmLimits::default().with_max_result_size(1024),
+        );
+        assert_eq!(resource, "result_size");
+    }
+
+    #[test]
+    fn rejects_results_out_of_bounds() {
+        let runtime = WasmRuntime::new(WasmConfig::default());
+        let handle = format!("i64.const {}", pack(0, u32::MAX));
+        let module = Module::new(&runtime.engine, handler_wat_with(ABI_VERSION, &handle)).unwrap();
+        let mut instance =
+            HandlerInstance::new(&runtime.engine, &module, &WasmLimits::default()).unwrap();
+
+        let err = instance.handle(&json!({})).unwrap_err();
+        assert_eq!(err.status(), ErrorStatus::Internal);
+        assert_eq!(
+            serde_json::to_value(err.body()).unwrap()["code"],
+            "handler_failed"
+        );
+    }
+
+    #[test]
+    fn deadline_is_limited() {
+        let resource = exhausted_resource(
+            "(loop br 0) i64.const 0",
+            WasmLimits::default()
+                .with_fuel(u64::MAX)
+                .with_deadline(Duration::from_millis(50)),
+        );
+        assert_eq!(resource, "deadline");
+    }
+}
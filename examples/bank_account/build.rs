@@ -0,0 +1,5 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    esruntime_schema::build::generate("schema.esdl")?;
+
+    Ok(())
+}
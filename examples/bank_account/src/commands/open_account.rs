@@ -1,4 +1,5 @@
 use esruntime_sdk::prelude::*;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
 use crate::events::OpenedAccount;
@@ -10,7 +11,7 @@ pub enum Query {
 }
 
 /// Command payload with domain ID bindings
-#[derive(CommandInput, Deserialize)]
+#[derive(CommandInput, Deserialize, JsonSchema)]
 pub struct OpenAccountInput {
     #[domain_id]
     pub account_id: String,
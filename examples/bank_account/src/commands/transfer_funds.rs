@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use esruntime_sdk::prelude::*;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
 use crate::events::{OpenedAccount, ReceivedFunds, SentFunds};
@@ -14,7 +15,7 @@ pub enum Query {
 }
 
 /// Command payload with domain ID bindings
-#[derive(CommandInput, Deserialize)]
+#[derive(CommandInput, Deserialize, JsonSchema)]
 pub struct TransferFundsInput {
     #[domain_id("account_id")]
     pub source_account: String,
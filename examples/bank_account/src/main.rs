@@ -1,8 +1,9 @@
 use std::sync::Arc;
 
-use axum::{Router, routing::get};
 use esruntime_sdk::prelude::Command;
-use esruntime_server::CommandRouter;
+use esruntime_server::{
+    CommandRouter, config::CommandRouterConfig, metrics, schema::SchemaRegistry,
+};
 use umadb_client::UmaDBClient;
 
 use crate::commands::{
@@ -32,28 +33,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await?;
     }
 
-    let command_router = CommandRouter::new(client)
-        .register_command::<OpenAccount>("open_account")
-        .register_command::<TransferFunds>("transfer_funds")
-        .build();
-
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    let server = axum::serve(
-        listener,
-        Router::new()
-            .route(
-                "/health",
-                get(|| async { r#"{"status":"healthy","version":"0.1.0"}"# }),
-            )
-            .nest("/commands", command_router),
-    );
-
-    tokio::select! {
-        res = server => {
-           res?;
-        }
-        _ = tokio::signal::ctrl_c() => {}
-    }
+    // Serves `POST /commands/open_account` and `POST /commands/transfer_funds`.
+    CommandRouter::new(client)
+        .config(CommandRouterConfig::from_env()?)
+        .metrics(metrics::install_recorder()?)
+        .schema_registry(SchemaRegistry::with_schema(include_str!("../schema.esdl"))?)
+        .register_command::<OpenAccount, _>("open_account")
+        .register_command::<TransferFunds, _>("transfer_funds")
+        .serve("0.0.0.0:3000")
+        .await?;
 
     Ok(())
 }